@@ -0,0 +1,275 @@
+use std::fmt::Write;
+
+/// Columns per block in `Alignment::pretty`.
+const PRETTY_WIDTH: usize = 60;
+
+/// One column of an alignment, with `fst` as the reference: an insertion
+/// is a residue present only in `snd`, a deletion one present only in
+/// `fst`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Match,
+    Mismatch,
+    Insertion,
+    Deletion,
+}
+
+impl Operation {
+    /// Operation for a column pairing `a` from `fst` with `b` from `snd`.
+    /// Residues are compared ignoring case, like the substitution matrices.
+    pub fn pair(a: u8, b: u8) -> Self {
+        if a.eq_ignore_ascii_case(&b) {
+            Operation::Match
+        } else {
+            Operation::Mismatch
+        }
+    }
+
+    pub fn consumes_fst(self) -> bool {
+        self != Operation::Insertion
+    }
+
+    pub fn consumes_snd(self) -> bool {
+        self != Operation::Deletion
+    }
+
+    pub fn is_gap(self) -> bool {
+        matches!(self, Operation::Insertion | Operation::Deletion)
+    }
+
+    fn cigar_char(self) -> char {
+        match self {
+            Operation::Match => '=',
+            Operation::Mismatch => 'X',
+            Operation::Insertion => 'I',
+            Operation::Deletion => 'D',
+        }
+    }
+}
+
+/**
+ * Alignment of `fst[fst_start..fst_end]` with `snd[snd_start..snd_end]`,
+ * returned by every aligner in the crate. Coordinates are 0-based and
+ * half-open. The sequences themselves are not stored, so methods that
+ * print residues take them as arguments.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Alignment {
+    pub score: isize,
+    pub fst_start: usize,
+    pub fst_end: usize,
+    pub snd_start: usize,
+    pub snd_end: usize,
+    pub fst_len: usize,
+    pub snd_len: usize,
+    pub ops: Vec<Operation>,
+}
+
+impl Alignment {
+    /// Alignment starting at `start` in the two sequences, whose ends are
+    /// worked out from `ops`. `lens` are the full sequence lengths.
+    pub fn new(
+        score: isize,
+        start: (usize, usize),
+        ops: Vec<Operation>,
+        lens: (usize, usize),
+    ) -> Self {
+        let fst_end = start.0 + ops.iter().filter(|op| op.consumes_fst()).count();
+        let snd_end = start.1 + ops.iter().filter(|op| op.consumes_snd()).count();
+        Alignment {
+            score,
+            fst_start: start.0,
+            fst_end,
+            snd_start: start.1,
+            snd_end,
+            fst_len: lens.0,
+            snd_len: lens.1,
+            ops,
+        }
+    }
+
+    /// The aligned parts of `fst` and `snd`, with `-` for gaps.
+    pub fn aligned(&self, fst: &[u8], snd: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut fst_iter = fst[self.fst_start..self.fst_end].iter();
+        let mut snd_iter = snd[self.snd_start..self.snd_end].iter();
+        let mut fst_aligned = Vec::with_capacity(self.ops.len());
+        let mut snd_aligned = Vec::with_capacity(self.ops.len());
+        for &op in &self.ops {
+            fst_aligned.push(match op.consumes_fst() {
+                true => *fst_iter.next().unwrap(),
+                false => b'-',
+            });
+            snd_aligned.push(match op.consumes_snd() {
+                true => *snd_iter.next().unwrap(),
+                false => b'-',
+            });
+        }
+        (fst_aligned, snd_aligned)
+    }
+
+    /**
+     * Extended CIGAR string with `fst` as the reference, e.g. `3=1X2I4=`.
+     * Parts of `snd` left out of the alignment are soft clipped (`S`).
+     */
+    pub fn cigar(&self) -> String {
+        let mut cigar = String::new();
+        if self.snd_start > 0 {
+            write!(cigar, "{}S", self.snd_start).unwrap();
+        }
+        let mut ops = self.ops.iter().peekable();
+        while let Some(&op) = ops.next() {
+            let mut run = 1;
+            while ops.next_if_eq(&&op).is_some() {
+                run += 1;
+            }
+            write!(cigar, "{}{}", run, op.cigar_char()).unwrap();
+        }
+        if self.snd_end < self.snd_len {
+            write!(cigar, "{}S", self.snd_len - self.snd_end).unwrap();
+        }
+        cigar
+    }
+
+    fn count(&self, op: Operation) -> usize {
+        self.ops.iter().filter(|&&other| other == op).count()
+    }
+
+    pub fn matches(&self) -> usize {
+        self.count(Operation::Match)
+    }
+
+    pub fn mismatches(&self) -> usize {
+        self.count(Operation::Mismatch)
+    }
+
+    pub fn insertions(&self) -> usize {
+        self.count(Operation::Insertion)
+    }
+
+    pub fn deletions(&self) -> usize {
+        self.count(Operation::Deletion)
+    }
+
+    /// Number of gaps, counting each run of insertions or deletions once.
+    pub fn gap_opens(&self) -> usize {
+        let mut prev = None;
+        let mut opens = 0;
+        for &op in &self.ops {
+            if op.is_gap() && prev != Some(op) {
+                opens += 1;
+            }
+            prev = Some(op);
+        }
+        opens
+    }
+
+    /// Fraction of alignment columns that are matches, 0 for an empty
+    /// alignment.
+    pub fn identity(&self) -> f64 {
+        if self.ops.is_empty() {
+            0.0
+        } else {
+            self.matches() as f64 / self.ops.len() as f64
+        }
+    }
+
+    /**
+     * Three-line view of the alignment, `fst` on top, `snd` below and a
+     * `|` between matching residues, wrapped into blocks of 60 columns
+     * separated by a blank line.
+     */
+    pub fn pretty(&self, fst: &[u8], snd: &[u8]) -> String {
+        let (fst_aligned, snd_aligned) = self.aligned(fst, snd);
+        let markers: Vec<u8> = self
+            .ops
+            .iter()
+            .map(|&op| match op {
+                Operation::Match => b'|',
+                Operation::Mismatch => b'.',
+                _ => b' ',
+            })
+            .collect();
+        let blocks = fst_aligned
+            .chunks(PRETTY_WIDTH)
+            .zip(markers.chunks(PRETTY_WIDTH))
+            .zip(snd_aligned.chunks(PRETTY_WIDTH))
+            .map(|((top, middle), bottom)| {
+                [top, middle, bottom]
+                    .map(|line| String::from_utf8_lossy(line).into_owned())
+                    .join("\n")
+            })
+            .collect::<Vec<String>>();
+        let mut pretty = blocks.join("\n\n");
+        pretty.push('\n');
+        pretty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Operation::*;
+
+    fn example() -> Alignment {
+        // ACGTTGA against TTACCTTG, starting at the third residue of snd.
+        let ops = vec![Match, Match, Mismatch, Deletion, Match, Insertion, Match];
+        Alignment::new(7, (0, 2), ops, (7, 8))
+    }
+
+    #[test]
+    fn test_coordinates() {
+        let alignment = example();
+        assert_eq!((0, 6), (alignment.fst_start, alignment.fst_end));
+        assert_eq!((2, 8), (alignment.snd_start, alignment.snd_end));
+    }
+
+    #[test]
+    fn test_aligned() {
+        let (fst_aligned, snd_aligned) = example().aligned(b"ACGTTGA", b"TTACCTTG");
+        assert_eq!(b"ACGTT-G".to_vec(), fst_aligned);
+        assert_eq!(b"ACC-TTG".to_vec(), snd_aligned);
+    }
+
+    #[test]
+    fn test_cigar() {
+        assert_eq!("2S2=1X1D1=1I1=", example().cigar());
+        let empty = Alignment::new(0, (0, 0), Vec::new(), (0, 0));
+        assert_eq!("", empty.cigar());
+        let clipped = Alignment::new(0, (1, 1), vec![Match, Match], (4, 4));
+        assert_eq!("1S2=1S", clipped.cigar());
+    }
+
+    #[test]
+    fn test_stats() {
+        let alignment = example();
+        assert_eq!(4, alignment.matches());
+        assert_eq!(1, alignment.mismatches());
+        assert_eq!(1, alignment.insertions());
+        assert_eq!(1, alignment.deletions());
+        assert_eq!(2, alignment.gap_opens());
+        assert!((alignment.identity() - 4.0 / 7.0).abs() < 1e-12);
+
+        let runs = Alignment::new(
+            0,
+            (0, 0),
+            vec![Deletion, Deletion, Insertion, Match],
+            (3, 2),
+        );
+        assert_eq!(2, runs.gap_opens());
+    }
+
+    #[test]
+    fn test_pretty() {
+        let pretty = example().pretty(b"ACGTTGA", b"TTACCTTG");
+        assert_eq!("ACGTT-G\n||. | |\nACC-TTG\n", pretty);
+
+        let long = Alignment::new(0, (0, 0), vec![Match; 70], (70, 70));
+        let seq = vec![b'A'; 70];
+        let pretty = long.pretty(&seq, &seq);
+        let lines: Vec<&str> = pretty.lines().collect();
+        assert_eq!(7, lines.len());
+        assert_eq!(60, lines[0].len());
+        assert_eq!("", lines[3]);
+        assert_eq!(10, lines[4].len());
+    }
+}
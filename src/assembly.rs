@@ -0,0 +1,350 @@
+use std::fmt::Write;
+
+use petgraph::prelude::*;
+
+use crate::utils;
+
+/**
+ * Length of the longest proper suffix of `fst` that is also a prefix of
+ * `snd`, found with the KMP failure function of `snd` run over `fst`.
+ * Proper means shorter than both, so a read never overlaps itself fully.
+ */
+pub fn overlap(fst: &[u8], snd: &[u8]) -> usize {
+    if fst.is_empty() || snd.is_empty() {
+        return 0;
+    }
+    let mut failure = vec![0; snd.len()];
+    for idx in 1..snd.len() {
+        let mut len = failure[idx - 1];
+        while len > 0 && snd[idx] != snd[len] {
+            len = failure[len - 1];
+        }
+        failure[idx] = len + (snd[idx] == snd[len]) as usize;
+    }
+    let mut len = 0;
+    for (idx, &ch) in fst.iter().enumerate() {
+        // Skip the first residue so the suffix stays proper.
+        if idx == 0 && fst.len() <= snd.len() {
+            continue;
+        }
+        while len > 0 && (len == snd.len() || ch != snd[len]) {
+            len = failure[len - 1];
+        }
+        len += (ch == snd[len]) as usize;
+    }
+    while len >= fst.len().min(snd.len()) && len > 0 {
+        len = failure[len - 1];
+    }
+    len
+}
+
+/// Reads that are not a substring of another one, keeping the first of
+/// identical reads.
+fn uncontained(reads: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let contains = |long: &[u8], short: &[u8]| {
+        short.is_empty() || long.windows(short.len()).any(|window| window == short)
+    };
+    reads
+        .iter()
+        .enumerate()
+        .filter(|&(idx, read)| {
+            reads.iter().enumerate().all(|(other_idx, other)| {
+                other_idx == idx
+                    || !contains(other, read)
+                    || (other.len() == read.len() && other_idx > idx)
+            })
+        })
+        .map(|(_, read)| read.clone())
+        .collect()
+}
+
+/**
+ * Greedy shortest common superstring: after dropping reads contained in
+ * others, the two reads overlapping the most are merged until one is
+ * left, the first pair in read order winning ties. Not always the
+ * shortest, but at most about twice as long.
+ */
+pub fn greedy_superstring(reads: &[Vec<u8>]) -> Vec<u8> {
+    let mut reads = uncontained(reads);
+    while reads.len() > 1 {
+        let mut best = (0, 0, 1);
+        for (i, fst) in reads.iter().enumerate() {
+            for (j, snd) in reads.iter().enumerate() {
+                let len = overlap(fst, snd);
+                if i != j && len > best.0 {
+                    best = (len, i, j);
+                }
+            }
+        }
+        let (len, i, j) = best;
+        let snd = reads[j].clone();
+        reads[i].extend_from_slice(&snd[len..]);
+        reads.remove(j);
+    }
+    reads.pop().unwrap_or_default()
+}
+
+/**
+ * Overlap graph assembler for error-free reads from the forward strand.
+ * Reads contained in others are dropped, the rest become nodes with an
+ * edge weighted by the overlap wherever a suffix of one read is a prefix
+ * of another for at least `min_overlap` residues.
+ */
+pub struct Builder<'a> {
+    reads: &'a [Vec<u8>],
+    min_overlap: usize,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(reads: &'a [Vec<u8>]) -> Self {
+        Builder {
+            reads,
+            min_overlap: 1,
+        }
+    }
+
+    pub fn min_overlap(mut self, min_overlap: usize) -> Self {
+        self.min_overlap = min_overlap.max(1);
+        self
+    }
+
+    pub fn graph(&self) -> OverlapGraph {
+        let reads = uncontained(self.reads);
+        let mut graph = Graph::new();
+        let nodes: Vec<NodeIndex> = (0..reads.len()).map(|idx| graph.add_node(idx)).collect();
+        for (i, fst) in reads.iter().enumerate() {
+            for (j, snd) in reads.iter().enumerate() {
+                let len = overlap(fst, snd);
+                if i != j && len >= self.min_overlap {
+                    graph.add_edge(nodes[i], nodes[j], len);
+                }
+            }
+        }
+        OverlapGraph { reads, graph }
+    }
+
+    /// Contigs of the transitively reduced overlap graph.
+    pub fn assemble(&self) -> Vec<Vec<u8>> {
+        let mut graph = self.graph();
+        graph.reduce();
+        graph.contigs()
+    }
+}
+
+/// Reads as nodes, overlaps as edges weighted by their length.
+pub struct OverlapGraph {
+    reads: Vec<Vec<u8>>,
+    graph: Graph<usize, usize>,
+}
+
+impl OverlapGraph {
+    /// Reads kept as nodes, indexed by the node weights.
+    pub fn reads(&self) -> &[Vec<u8>] {
+        &self.reads
+    }
+
+    pub fn graph(&self) -> &Graph<usize, usize> {
+        &self.graph
+    }
+
+    /**
+     * Transitive reduction into a string graph (Myers 2005): an edge
+     * `a -> c` is dropped when some `a -> b -> c` spells the same string,
+     * i.e. when `b` starts between the starts of `a` and `c`. What is left
+     * is a chain of reads wherever the genome is not repeated.
+     */
+    pub fn reduce(&mut self) {
+        let offset = |edge: EdgeIndex| {
+            let (from, _) = self.graph.edge_endpoints(edge).unwrap();
+            self.reads[self.graph[from]].len() - self.graph[edge]
+        };
+        let mut transitive = Vec::new();
+        for edge in self.graph.edge_indices() {
+            let (from, to) = self.graph.edge_endpoints(edge).unwrap();
+            let through = self.graph.edges(from).any(|first| {
+                first.target() != to
+                    && self
+                        .graph
+                        .edges_connecting(first.target(), to)
+                        .any(|second| offset(first.id()) + offset(second.id()) == offset(edge))
+            });
+            if through {
+                transitive.push(edge);
+            }
+        }
+        // Removing an edge moves the last edge into its index.
+        transitive.sort_unstable_by(|fst, snd| snd.cmp(fst));
+        for edge in transitive {
+            self.graph.remove_edge(edge);
+        }
+    }
+
+    /**
+     * Spells the maximal non-branching paths of the graph: each node
+     * starts a contig unless it is the only successor of a node with a
+     * single successor, and the contig grows for as long as that holds.
+     * Cycles of such nodes become one contig each, starting at their
+     * first read.
+     */
+    pub fn contigs(&self) -> Vec<Vec<u8>> {
+        let single_out = |node: NodeIndex| {
+            let mut edges = self.graph.edges(node);
+            match (edges.next(), edges.next()) {
+                (Some(edge), None) => Some(edge),
+                _ => None,
+            }
+        };
+        let extends = |node: NodeIndex| {
+            let mut incoming = self.graph.edges_directed(node, Incoming);
+            match (incoming.next(), incoming.next()) {
+                (Some(edge), None) => single_out(edge.source()).is_some(),
+                _ => false,
+            }
+        };
+
+        let mut visited = vec![false; self.graph.node_count()];
+        let mut contigs = Vec::new();
+        let starts = self.graph.node_indices().filter(|&node| !extends(node));
+        let cycles = self.graph.node_indices();
+        for start in starts.chain(cycles) {
+            if visited[start.index()] {
+                continue;
+            }
+            visited[start.index()] = true;
+            let mut contig = self.reads[self.graph[start]].clone();
+            let mut node = start;
+            while let Some(edge) = single_out(node) {
+                let next = edge.target();
+                if visited[next.index()] || !extends(next) {
+                    break;
+                }
+                visited[next.index()] = true;
+                contig.extend_from_slice(&self.reads[self.graph[next]][*edge.weight()..]);
+                node = next;
+            }
+            contigs.push(contig);
+        }
+        contigs
+    }
+
+    /// The graph in GFA 1 format, a segment for each read named by its
+    /// index and a link for each overlap, sorted by their ends.
+    pub fn gfa(&self) -> String {
+        let mut gfa = String::from("H\tVN:Z:1.0\n");
+        for node in self.graph.node_indices() {
+            let read = &self.reads[self.graph[node]];
+            writeln!(
+                gfa,
+                "S\t{}\t{}",
+                self.graph[node],
+                String::from_utf8_lossy(read)
+            )
+            .unwrap();
+        }
+        let mut links: Vec<(usize, usize, usize)> = self
+            .graph
+            .edge_references()
+            .map(|edge| {
+                let (from, to) = (self.graph[edge.source()], self.graph[edge.target()]);
+                (from, to, *edge.weight())
+            })
+            .collect();
+        links.sort_unstable();
+        for (from, to, len) in links {
+            writeln!(gfa, "L\t{}\t+\t{}\t+\t{}M", from, to, len).unwrap();
+        }
+        gfa
+    }
+}
+
+/// Contigs as FASTA, named `contig_1`, `contig_2` and so on.
+pub fn contigs_fasta(contigs: &[Vec<u8>]) -> String {
+    let names: Vec<String> = (1..=contigs.len())
+        .map(|idx| format!("contig_{}", idx))
+        .collect();
+    utils::fasta(&names, contigs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, random_dna};
+    use rand::Rng;
+
+    fn reads(reads: &[&str]) -> Vec<Vec<u8>> {
+        reads.iter().map(|read| read.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_overlap() {
+        assert_eq!(4, overlap(b"ATTAGACCTG", b"CCTGCCGGAA"));
+        assert_eq!(1, overlap(b"CCTGCCGGAA", b"ATTAGACCTG"));
+        assert_eq!(2, overlap(b"AAA", b"AAA"));
+        assert_eq!(2, overlap(b"AAA", b"AAAAAA"));
+        assert_eq!(0, overlap(b"ACGACG", b"ACG"));
+        assert_eq!(0, overlap(b"", b"ACG"));
+        let mut rng = testing::rng(2);
+        for _ in 0..200 {
+            let len = rng.gen_range(1..9);
+            let fst = random_dna(&mut rng, len);
+            let len = rng.gen_range(1..9);
+            let snd = random_dna(&mut rng, len);
+            let naive = (1..fst.len().min(snd.len()))
+                .rev()
+                .find(|&len| fst.ends_with(&snd[..len]))
+                .unwrap_or(0);
+            assert_eq!(naive, overlap(&fst, &snd));
+        }
+    }
+
+    #[test]
+    fn test_greedy_superstring() {
+        // The sample of Rosalind's LONG problem.
+        let sample = reads(&["ATTAGACCTG", "CCTGCCGGAA", "AGACCTGCCG", "GCCGGAATAC"]);
+        assert_eq!(b"ATTAGACCTGCCGGAATAC".to_vec(), greedy_superstring(&sample));
+        let contained = reads(&["ACGTTG", "CGT", "TTGCA", "ACGTTG"]);
+        assert_eq!(b"ACGTTGCA".to_vec(), greedy_superstring(&contained));
+        assert_eq!(Vec::<u8>::new(), greedy_superstring(&[]));
+    }
+
+    #[test]
+    fn test_overlap_graph() {
+        let sample = reads(&["ATTAGACCTG", "CCTGCCGGAA", "AGACCTGCCG", "GCCGGAATAC"]);
+        let mut graph = Builder::new(&sample).min_overlap(3).graph();
+        assert_eq!(4, graph.graph().node_count());
+        assert_eq!(5, graph.graph().edge_count());
+        graph.reduce();
+        assert_eq!(3, graph.graph().edge_count());
+        assert_eq!(vec![b"ATTAGACCTGCCGGAATAC".to_vec()], graph.contigs());
+        assert_eq!(
+            "H\tVN:Z:1.0\n\
+             S\t0\tATTAGACCTG\n\
+             S\t1\tCCTGCCGGAA\n\
+             S\t2\tAGACCTGCCG\n\
+             S\t3\tGCCGGAATAC\n\
+             L\t0\t+\t2\t+\t7M\n\
+             L\t1\t+\t3\t+\t7M\n\
+             L\t2\t+\t1\t+\t7M\n",
+            graph.gfa()
+        );
+
+        let genome = random_dna(&mut testing::rng(4), 300);
+        let tiled: Vec<Vec<u8>> = (0..=280)
+            .step_by(7)
+            .map(|start| genome[start..start + 20].to_vec())
+            .collect();
+        assert_eq!(
+            vec![genome[..300].to_vec()],
+            Builder::new(&tiled).min_overlap(10).assemble()
+        );
+
+        // Too little overlap breaks the genome into two contigs.
+        let split = reads(&["ACGTACG", "TACGGAT", "CCCTTTG", "TTTGAAA"]);
+        let contigs = Builder::new(&split).min_overlap(3).assemble();
+        assert_eq!(reads(&["ACGTACGGAT", "CCCTTTGAAA"]), contigs);
+        assert_eq!(
+            ">contig_1\nACGTACGGAT\n>contig_2\nCCCTTTGAAA\n",
+            contigs_fasta(&contigs)
+        );
+    }
+}
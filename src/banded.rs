@@ -0,0 +1,427 @@
+use std::ops::Range;
+
+use crate::alignment::{Alignment, Operation};
+use crate::dp::{BandedMatrix, Direction, Directions, ScoreTable};
+use crate::pairwise::{self, NEG_INF};
+use crate::substitution::SubstitutionMatrix;
+
+/// Band width tried first when none is given, doubled until the result is
+/// provably optimal.
+const INITIAL_BAND: usize = 8;
+
+/**
+ * Global alignment restricted to a band of diagonals, for sequences that
+ * are expected to be nearly identical. The band always contains the main
+ * diagonal and the one through the bottom right corner, plus `band` more
+ * diagonals on either side.
+ *
+ * Without an explicit band, the band starts narrow and is doubled until
+ * the best score found beats anything a path leaving the band could
+ * score, so the result always matches `pairwise::Builder` in
+ * `Mode::Global`.
+ */
+pub struct Builder<'a> {
+    fst: &'a [u8],
+    snd: &'a [u8],
+    linear_gap_penalty: isize,
+    gap_open: isize,
+    matrix: SubstitutionMatrix,
+    band: Option<usize>,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(fst: &'a [u8], snd: &'a [u8], linear_gap_penalty: isize) -> Self {
+        Builder {
+            fst,
+            snd,
+            linear_gap_penalty,
+            gap_open: 0,
+            matrix: SubstitutionMatrix::blosum62(),
+            band: None,
+        }
+    }
+
+    /// Extra penalty charged once for every gap, on top of the per-residue
+    /// `linear_gap_penalty`.
+    pub fn gap_open(mut self, penalty: isize) -> Self {
+        self.gap_open = penalty;
+        self
+    }
+
+    pub fn matrix(mut self, matrix: SubstitutionMatrix) -> Self {
+        self.matrix = matrix;
+        self
+    }
+
+    /// Fixed band width. The result is only guaranteed optimal if an
+    /// optimal alignment stays inside the band.
+    pub fn band(mut self, band: usize) -> Self {
+        self.band = Some(band);
+        self
+    }
+
+    fn core(&self) -> pairwise::Builder<'a> {
+        pairwise::Builder::new(self.fst, self.snd, self.linear_gap_penalty)
+            .matrix(self.matrix.clone())
+            .gap_open(self.gap_open)
+    }
+
+    pub fn align(&self) -> Alignment {
+        let core = self.core();
+        let mut band = self.band.unwrap_or(INITIAL_BAND);
+        loop {
+            let mat = self.fill(&core, band);
+            let end = (self.fst.len(), self.snd.len());
+            let score = mat.score(end.0, end.1);
+            if self.band.is_some() || score >= self.outside_bound(band) {
+                return core.traceback(&mat, end).0;
+            }
+            band *= 2;
+        }
+    }
+
+    /// Lowest and highest diagonal (`snd_idx - fst_idx`) inside the band.
+    fn diagonals(&self, band: usize) -> (isize, isize) {
+        let corner = self.snd.len() as isize - self.fst.len() as isize;
+        (corner.min(0) - band as isize, corner.max(0) + band as isize)
+    }
+
+    fn fill(&self, core: &pairwise::Builder, band: usize) -> BandedMatrix<isize> {
+        let (fst_len, snd_len) = (self.fst.len() as isize, self.snd.len() as isize);
+        let (lowest, highest) = self.diagonals(band);
+        let cols = |fst_idx: isize| {
+            let first = (fst_idx + lowest).max(0);
+            let last = (fst_idx + highest).min(snd_len);
+            first as usize..(last + 1) as usize
+        };
+
+        let mut mat = BandedMatrix::new(NEG_INF);
+        mat.push_row(
+            0,
+            cols(0).map(|snd_idx| core.border(snd_idx, false, Direction::Top)),
+        );
+        let mut left_gaps = vec![NEG_INF; self.snd.len() + 1];
+        for fst_idx in 1..=fst_len {
+            let row = next_row(core, &mat, &mut left_gaps, cols(fst_idx), None);
+            mat.push_row(cols(fst_idx).start, row);
+        }
+        mat
+    }
+
+    /**
+     * Upper bound on the score of any alignment that leaves the band. Going
+     * below it takes at least `1 - lowest` residues of `fst` in gaps and
+     * enough residues of `snd` to get back to the corner, and likewise
+     * above it, with every other residue scoring at most the best match.
+     */
+    fn outside_bound(&self, band: usize) -> isize {
+        let (fst_len, snd_len) = (self.fst.len() as isize, self.snd.len() as isize);
+        let (lowest, highest) = self.diagonals(band);
+        let best_match = self.matrix.max_score().max(0);
+        let bound = |fst_gaps: isize, snd_gaps: isize| {
+            if fst_gaps > fst_len || snd_gaps > snd_len {
+                NEG_INF
+            } else {
+                best_match * (fst_len - fst_gaps)
+                    + self.linear_gap_penalty * (fst_gaps + snd_gaps)
+                    + 2 * self.gap_open
+            }
+        };
+        let corner = snd_len - fst_len;
+        let below = bound(1 - lowest, corner + 1 - lowest);
+        let above = bound(highest + 1 - corner, highest + 1);
+        below.max(above)
+    }
+}
+
+/**
+ * Computes the cells of the next row in `cols`. With a `floor`, cells
+ * scoring below it are pruned (they score `NEG_INF` and nothing can be
+ * extended from them) and the row continues past `cols` for as long as
+ * the cells stay at or above it. `left_gaps` holds the best gap scores
+ * ending in each column of the previous row and is updated in place.
+ */
+fn next_row(
+    core: &pairwise::Builder,
+    mat: &BandedMatrix<isize>,
+    left_gaps: &mut [isize],
+    cols: Range<usize>,
+    floor: Option<isize>,
+) -> Vec<(isize, Directions)> {
+    let end = if floor.is_some() {
+        left_gaps.len()
+    } else {
+        cols.end
+    };
+    let fst_idx = mat.num_rows();
+    let prev_cols = mat.cols(fst_idx - 1);
+    let mut row = Vec::with_capacity(cols.len());
+    let mut top_gap = NEG_INF;
+    let mut snd_idx = cols.start;
+    while snd_idx < end {
+        let past_end = snd_idx >= cols.end;
+        let (score, dirs) = if snd_idx == 0 {
+            core.border(fst_idx, false, Direction::Left)
+        } else {
+            let matching =
+                mat.score(fst_idx - 1, snd_idx - 1) + core.scoring_matrix(fst_idx, snd_idx);
+            let top_score = row.last().map_or(NEG_INF, |&(score, _)| score);
+            let left_gap = if prev_cols.contains(&snd_idx) {
+                left_gaps[snd_idx]
+            } else {
+                NEG_INF
+            };
+            let (score, dirs, top, left) = core.cell(
+                matching,
+                (top_score, top_gap),
+                (mat.score(fst_idx - 1, snd_idx), left_gap),
+            );
+            top_gap = top;
+            left_gaps[snd_idx] = left;
+            (score, dirs)
+        };
+        if floor.is_some_and(|floor| score < floor) {
+            if past_end {
+                break;
+            }
+            top_gap = NEG_INF;
+            left_gaps[snd_idx] = NEG_INF;
+            row.push((NEG_INF, Directions::empty()));
+        } else {
+            row.push((score, dirs));
+        }
+        snd_idx += 1;
+    }
+    row
+}
+
+/**
+ * Gapped X-drop extension of an ungapped seed in both directions, as in
+ * BLAST. The extension stops exploring a cell once its score falls more
+ * than `x_drop` below the best score seen so far, and the alignment ends
+ * at the best scoring cell on either side.
+ */
+pub struct XDrop<'a> {
+    fst: &'a [u8],
+    snd: &'a [u8],
+    linear_gap_penalty: isize,
+    gap_open: isize,
+    matrix: SubstitutionMatrix,
+    x_drop: isize,
+}
+
+impl<'a> XDrop<'a> {
+    pub fn new(fst: &'a [u8], snd: &'a [u8], linear_gap_penalty: isize, x_drop: isize) -> Self {
+        XDrop {
+            fst,
+            snd,
+            linear_gap_penalty,
+            gap_open: 0,
+            matrix: SubstitutionMatrix::blosum62(),
+            x_drop,
+        }
+    }
+
+    /// Extra penalty charged once for every gap, on top of the per-residue
+    /// `linear_gap_penalty`.
+    pub fn gap_open(mut self, penalty: isize) -> Self {
+        self.gap_open = penalty;
+        self
+    }
+
+    pub fn matrix(mut self, matrix: SubstitutionMatrix) -> Self {
+        self.matrix = matrix;
+        self
+    }
+
+    /// Extends the seed `fst[fst_start..fst_start + len]` aligned without
+    /// gaps to `snd[snd_start..snd_start + len]`.
+    pub fn extend(&self, fst_start: usize, snd_start: usize, len: usize) -> Alignment {
+        let (fst_end, snd_end) = (fst_start + len, snd_start + len);
+        let fst_before: Vec<u8> = self.fst[..fst_start].iter().rev().copied().collect();
+        let snd_before: Vec<u8> = self.snd[..snd_start].iter().rev().copied().collect();
+        let before = self.extend_one_side(&fst_before, &snd_before);
+        let after = self.extend_one_side(&self.fst[fst_end..], &self.snd[snd_end..]);
+
+        let seed = self.fst[fst_start..fst_end]
+            .iter()
+            .zip(&self.snd[snd_start..snd_end]);
+        let seed_score: isize = seed.clone().map(|(&a, &b)| self.matrix.score(a, b)).sum();
+        let ops = before
+            .ops
+            .iter()
+            .rev()
+            .copied()
+            .chain(seed.map(|(&a, &b)| Operation::pair(a, b)))
+            .chain(after.ops)
+            .collect();
+        Alignment::new(
+            before.score + seed_score + after.score,
+            (fst_start - before.fst_end, snd_start - before.snd_end),
+            ops,
+            (self.fst.len(), self.snd.len()),
+        )
+    }
+
+    /// Best scoring alignment of a prefix of `fst` with a prefix of `snd`
+    /// found before the X-drop condition stops the fill.
+    fn extend_one_side(&self, fst: &[u8], snd: &[u8]) -> Alignment {
+        let core = pairwise::Builder::new(fst, snd, self.linear_gap_penalty)
+            .matrix(self.matrix.clone())
+            .gap_open(self.gap_open);
+        let mut best = (0, (0, 0));
+
+        let mut mat = BandedMatrix::new(NEG_INF);
+        let first_row = (0..=snd.len())
+            .map(|snd_idx| core.border(snd_idx, false, Direction::Top))
+            .take_while(|&(score, _)| score >= -self.x_drop);
+        mat.push_row(0, first_row);
+        let mut left_gaps = vec![NEG_INF; snd.len() + 1];
+        for fst_idx in 1..=fst.len() {
+            let prev_cols = mat.cols(fst_idx - 1);
+            let cols = prev_cols.start..prev_cols.end + 1;
+            let row = next_row(
+                &core,
+                &mat,
+                &mut left_gaps,
+                cols.clone(),
+                Some(best.0 - self.x_drop),
+            );
+            let live = |&(score, _): &(isize, Directions)| score > NEG_INF;
+            let (first, last) = match (row.iter().position(live), row.iter().rposition(live)) {
+                (Some(first), Some(last)) => (first, last),
+                _ => break,
+            };
+            for (offset, &(score, _)) in row.iter().enumerate() {
+                if score > best.0 {
+                    best = (score, (fst_idx, cols.start + offset));
+                }
+            }
+            mat.push_row(cols.start + first, row[first..=last].iter().copied());
+        }
+        core.traceback(&mat, best.1).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, check_alignment, random_dna};
+    use rand::rngs::StdRng;
+    use rand::Rng;
+
+    /// Copy of `seq` with roughly one edit in `every` positions.
+    fn mutate(seq: &[u8], rng: &mut StdRng, every: u64) -> Vec<u8> {
+        let mut mutated = Vec::new();
+        for &ch in seq {
+            match rng.gen_range(0..3 * every) {
+                0 => {}
+                1 => mutated.extend([ch, b'A']),
+                2 => mutated.push(if ch == b'C' { b'G' } else { b'C' }),
+                _ => mutated.push(ch),
+            }
+        }
+        mutated
+    }
+
+    #[test]
+    fn test_matches_full_dp() {
+        let mut rng = testing::rng(3);
+        let matrix = SubstitutionMatrix::dna(2, -3);
+        for gap_open in [0, -6] {
+            for _ in 0..10 {
+                let fst = random_dna(&mut rng, 200);
+                let snd = mutate(&fst, &mut rng, 20);
+                let full = pairwise::Builder::new(&fst, &snd, -4)
+                    .matrix(matrix.clone())
+                    .gap_open(gap_open)
+                    .align();
+                let banded = Builder::new(&fst, &snd, -4)
+                    .matrix(matrix.clone())
+                    .gap_open(gap_open)
+                    .align();
+                assert_eq!(full.score, banded.score);
+                check_alignment(&fst, &snd, &banded);
+
+                let fixed = Builder::new(&fst, &snd, -4)
+                    .matrix(matrix.clone())
+                    .gap_open(gap_open)
+                    .band(30)
+                    .align();
+                assert_eq!(full.score, fixed.score);
+                assert_eq!(full.ops, fixed.ops);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unrelated_sequences() {
+        let mut rng = testing::rng(5);
+        let matrix = SubstitutionMatrix::dna(2, -3);
+        let fst = random_dna(&mut rng, 60);
+        let snd = random_dna(&mut rng, 35);
+        let full = pairwise::Builder::new(&fst, &snd, -4)
+            .matrix(matrix.clone())
+            .align();
+        let banded = Builder::new(&fst, &snd, -4).matrix(matrix).align();
+        assert_eq!(full.score, banded.score);
+        check_alignment(&fst, &snd, &banded);
+    }
+
+    #[test]
+    fn test_narrow_band() {
+        let fst = b"ACGTACGTAAAAAAAAACGTACGT";
+        let snd = b"ACGTACGTACGTACGT";
+        let matrix = SubstitutionMatrix::dna(2, -3);
+        let alignment = Builder::new(fst, snd, -1).matrix(matrix).band(0).align();
+        check_alignment(fst, snd, &alignment);
+        assert_eq!(0, alignment.insertions());
+    }
+
+    #[test]
+    fn test_xdrop() {
+        let mut rng = testing::rng(9);
+        let matrix = SubstitutionMatrix::dna(2, -3);
+        let core = random_dna(&mut rng, 150);
+        let fst = [
+            random_dna(&mut rng, 80),
+            core.clone(),
+            random_dna(&mut rng, 80),
+        ]
+        .concat();
+        let snd = [
+            random_dna(&mut rng, 50),
+            mutate(&core, &mut rng, 25),
+            random_dna(&mut rng, 50),
+        ]
+        .concat();
+        let seed_len = (0..)
+            .take_while(|&idx| fst[80 + idx] == snd[50 + idx])
+            .count()
+            .min(10);
+        assert!(seed_len > 0);
+
+        let alignment = XDrop::new(&fst, &snd, -4, 20)
+            .matrix(matrix.clone())
+            .extend(80, 50, seed_len);
+        check_alignment(&fst, &snd, &alignment);
+        assert!(alignment.fst_start >= 70 && alignment.fst_end <= 240);
+        assert!(alignment.fst_end - alignment.fst_start >= 140);
+        assert_eq!(
+            alignment.score,
+            pairwise::alignment_score(&alignment, &fst, &snd, &matrix, -4, 0)
+        );
+
+        // With no drop-off limit the extension is a full local alignment
+        // forced through the seed.
+        let unlimited = XDrop::new(&fst, &snd, -4, 10_000)
+            .matrix(matrix.clone())
+            .extend(80, 50, seed_len);
+        let local = pairwise::Builder::new(&fst, &snd, -4)
+            .matrix(matrix)
+            .mode(pairwise::Mode::Local)
+            .align();
+        assert!(unlimited.score >= alignment.score);
+        assert!(unlimited.score <= local.score);
+    }
+}
@@ -5,12 +5,9 @@ use compbio::consensus_profile::Profile;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let mut profile = Profile::new();
-    let reader = fasta::Reader::new(stdin()).records();
-    reader.for_each(|rec| {
-        let rec = rec.unwrap();
-        let seq = rec.seq();
-        profile.add_seq(seq);
-    });
+    for rec in fasta::Reader::new(stdin()).records() {
+        profile.add_seq(rec?.seq())?;
+    }
     println!("{}", profile);
     Ok(())
 }
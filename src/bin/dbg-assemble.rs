@@ -0,0 +1,40 @@
+use std::io::stdin;
+
+use compbio::assembly;
+use compbio::de_bruijn::{self, Builder};
+use compbio::utils;
+
+fn main() {
+    env_logger::init();
+    let reads = de_bruijn::read_sequences(stdin()).unwrap();
+    let k = utils::flag_value("--kmer").map_or(31, |arg| arg.parse().unwrap());
+    let min_count = utils::flag_value("--min-count").map_or(2, |arg| arg.parse().unwrap());
+    let tip_length = utils::flag_value("--tip-length").map_or(2 * k, |arg| arg.parse().unwrap());
+    let bubble_length =
+        utils::flag_value("--bubble-length").map_or(2 * k, |arg| arg.parse().unwrap());
+    let forward_only = std::env::args().any(|arg| arg == "--forward-only");
+
+    let mut graph = Builder::new(k)
+        .min_count(min_count)
+        .canonical(!forward_only)
+        .build(&reads);
+    let tips = graph.remove_tips(tip_length);
+    let bubbles = graph.pop_bubbles(bubble_length);
+    log::info!("removed {} tips and {} bubble paths", tips, bubbles);
+
+    match utils::flag_value("--output").as_deref() {
+        None | Some("contigs") => print!("{}", assembly::contigs_fasta(&graph.contigs())),
+        Some("unitigs") => {
+            let unitigs = graph.unitigs();
+            let names: Vec<String> = unitigs
+                .iter()
+                .enumerate()
+                .map(|(idx, unitig)| format!("unitig_{} coverage={:.1}", idx + 1, unitig.coverage))
+                .collect();
+            let seqs: Vec<&[u8]> = unitigs.iter().map(|unitig| unitig.seq.as_slice()).collect();
+            print!("{}", utils::fasta(&names, &seqs));
+        }
+        Some("gfa") => print!("{}", graph.gfa()),
+        Some(other) => panic!("unknown output {}, expected contigs, unitigs or gfa", other),
+    }
+}
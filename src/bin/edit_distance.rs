@@ -1,4 +1,4 @@
-use compbio::edit;
+use compbio::myers;
 use seq_io::fasta::Reader;
 use std::io;
 
@@ -11,7 +11,6 @@ fn main() {
 
     let target = &seqs[0];
     let query = &seqs[1];
-    let tbm = edit::wagner_fischer(target, query);
-    let dist = edit::distance(target, query, &tbm);
+    let dist = myers::distance(target, query);
     println!("{}", dist);
 }
@@ -1,4 +1,5 @@
 use compbio::edit;
+use compbio::utils;
 use seq_io::fasta::Reader;
 use std::io;
 
@@ -13,8 +14,8 @@ fn main() {
     let query = &seqs[1];
     let tbm = edit::wagner_fischer(target, query);
     let dist = edit::distance(target, query, &tbm);
-    let (xs, ys) = edit::align(target, query, &tbm);
+    let (xs, ys) = edit::align(target, query, &tbm).aligned(target, query);
     println!("{}", dist);
-    println!("{}", xs);
-    println!("{}", ys);
+    println!("{}", utils::u8_to_string(&xs));
+    println!("{}", utils::u8_to_string(&ys));
 }
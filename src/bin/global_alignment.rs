@@ -1,4 +1,5 @@
 use compbio::glob;
+use compbio::substitution::SubstitutionMatrix;
 use compbio::utils;
 use seq_io::fasta::Reader;
 use std::io;
@@ -12,13 +13,16 @@ fn main() {
         .collect::<Vec<Vec<u8>>>();
     let fst = &seqs[0];
     let snd = &seqs[1];
-    let mut alignment = glob::GlobalAlignment::new(fst.to_owned(), snd.to_owned());
+    let matrix = utils::flag_value("--matrix")
+        .map(|arg| SubstitutionMatrix::from_arg(&arg).unwrap())
+        .unwrap_or_else(SubstitutionMatrix::blosum62);
+    let mut alignment = glob::GlobalAlignment::with_matrix(fst.to_owned(), snd.to_owned(), matrix);
     alignment.align();
     println!(
         "Maximum alignment score: {}",
         alignment.maximum_alignment_score()
     );
-    let (fst_aligned, snd_aligned) = alignment.traceback();
+    let (fst_aligned, snd_aligned) = alignment.traceback().aligned(fst, snd);
     println!(
         "fst aligned sequence: {}",
         utils::u8_to_string(&fst_aligned)
@@ -1,4 +1,5 @@
 use compbio::local;
+use compbio::substitution::SubstitutionMatrix;
 use compbio::utils;
 use seq_io::fasta::Reader;
 use std::io;
@@ -12,15 +13,19 @@ fn main() {
         .collect::<Vec<Vec<u8>>>();
     let fst = &seqs[0];
     let snd = &seqs[1];
-    let builder = local::Builder::new(&fst, &snd, 5);
+    let matrix = utils::flag_value("--matrix")
+        .map(|arg| SubstitutionMatrix::from_arg(&arg).unwrap())
+        .unwrap_or_else(SubstitutionMatrix::pam250);
+    let builder = local::Builder::new(fst, snd, -5).matrix(matrix);
     let alignment = builder.align();
-    println!("Maximum alignment score: {}", alignment.max_score);
+    let (fst_aligned, snd_aligned) = alignment.aligned(fst, snd);
+    println!("Maximum alignment score: {}", alignment.score);
     println!(
         "fst aligned sequence: {}",
-        utils::u8_to_string(&alignment.fst_aligned)
+        utils::u8_to_string(&fst_aligned)
     );
     println!(
         "snd aligned sequence: {}",
-        utils::u8_to_string(&alignment.snd_aligned)
+        utils::u8_to_string(&snd_aligned)
     );
 }
@@ -0,0 +1,50 @@
+use std::io::stdin;
+use std::process;
+
+use bio::io::fasta;
+use compbio::msa::{self, TreeMethod};
+use compbio::substitution::SubstitutionMatrix;
+use compbio::utils;
+
+fn main() {
+    env_logger::init();
+    let records: Vec<fasta::Record> = fasta::Reader::new(stdin())
+        .records()
+        .map(|rec| rec.unwrap())
+        .collect();
+    let names: Vec<&str> = records.iter().map(|rec| rec.id()).collect();
+    let seqs: Vec<Vec<u8>> = records.iter().map(|rec| rec.seq().to_vec()).collect();
+
+    let matrix = utils::flag_value("--matrix")
+        .map(|arg| SubstitutionMatrix::from_arg(&arg).unwrap())
+        .unwrap_or_else(SubstitutionMatrix::blosum62);
+    let gap = utils::flag_value("--gap").map_or(-1, |arg| arg.parse().unwrap());
+    let gap_open = utils::flag_value("--gap-open").map_or(-10, |arg| arg.parse().unwrap());
+    let tree = match utils::flag_value("--tree").as_deref() {
+        None | Some("upgma") => TreeMethod::Upgma,
+        Some("nj") => TreeMethod::NeighborJoining,
+        Some(other) => panic!("unknown guide tree method {}, expected upgma or nj", other),
+    };
+
+    let builder = msa::Builder::new(&seqs, gap)
+        .gap_open(gap_open)
+        .matrix(matrix)
+        .tree(tree);
+    let alignment = match utils::flag_value("--method").as_deref() {
+        None | Some("progressive") => builder.align(),
+        Some("exact") => builder.align_exact().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        }),
+        Some("center-star") => builder.align_center_star(),
+        Some(other) => panic!(
+            "unknown alignment method {}, expected progressive, exact or center-star",
+            other
+        ),
+    };
+    match utils::flag_value("--format").as_deref() {
+        None | Some("fasta") => print!("{}", alignment.fasta(&names)),
+        Some("clustal") => print!("{}", alignment.clustal(&names)),
+        Some(other) => panic!("unknown output format {}, expected fasta or clustal", other),
+    }
+}
@@ -2,7 +2,7 @@ extern crate compbio;
 extern crate env_logger;
 extern crate seq_io;
 
-use compbio::lcs_stree::SuffixTree;
+use compbio::suffix_automaton::longest_common_substring;
 use seq_io::fasta::Reader;
 use std::io;
 
@@ -11,9 +11,10 @@ fn main() {
     let mut reader = Reader::new(io::stdin());
     let seqs = reader
         .records()
-        .map(|rec| String::from_utf8(rec.unwrap().seq).unwrap())
-        .collect::<Vec<String>>();
+        .map(|rec| rec.unwrap().seq)
+        .collect::<Vec<Vec<u8>>>();
+    let seqs = seqs.iter().map(Vec::as_slice).collect::<Vec<&[u8]>>();
 
-    let stree = SuffixTree::from_strings(&seqs);
-    println!("{}", stree.longest_shared_substring2(seqs.len()));
+    let motif = longest_common_substring(&seqs);
+    println!("{}", String::from_utf8_lossy(motif));
 }
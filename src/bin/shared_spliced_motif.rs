@@ -1,4 +1,4 @@
-use compbio::lcs_splicing_tb;
+use compbio::lcs_splicing;
 use seq_io::fasta::Reader;
 use std::io;
 
@@ -11,7 +11,6 @@ fn main() {
 
     let target = &seqs[0];
     let query = &seqs[1];
-    let tbm = lcs_splicing_tb::longest_common_subsequence(target, query);
-    let ssmotif = lcs_splicing_tb::trace(target, query, &tbm);
-    println!("{}", ssmotif.first().unwrap());
+    let lcs = lcs_splicing::longest_common_subsequence(target, query);
+    println!("{}", lcs.as_string());
 }
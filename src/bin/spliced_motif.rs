@@ -6,6 +6,7 @@ use compbio::splicing;
 use itertools::Itertools;
 use seq_io::fasta::Reader;
 use std::io;
+use std::process;
 
 fn main() {
     let mut reader = Reader::new(io::stdin());
@@ -16,6 +17,11 @@ fn main() {
 
     let target = &seqs[0];
     let query = &seqs[1];
-    let smotif = splicing::spliced_motif(target, query);
-    println!("{}", smotif.iter().join(" "));
+    match splicing::spliced_motif(target, query) {
+        Some(smotif) => println!("{}", smotif.iter().join(" ")),
+        None => {
+            eprintln!("the query is not a subsequence of the target");
+            process::exit(1);
+        }
+    }
 }
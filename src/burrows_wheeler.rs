@@ -89,7 +89,7 @@ impl<'a> Iterator for UniqCount<'a> {
     }
 }
 
-pub fn uniq_count(text: &str) -> UniqCount {
+pub fn uniq_count(text: &str) -> UniqCount<'_> {
     let text_iter = text.chars();
     let hmap = HashMap::new();
     UniqCount {
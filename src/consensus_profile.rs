@@ -1,69 +1,202 @@
 use itertools::Itertools;
+use std::error::Error;
 use std::fmt::Display;
 
-use itertools::izip;
+/// IUPAC nucleotide codes indexed by the set of bases they stand for, A,
+/// C, G and T being bits 0 to 3. No base at all is a gap.
+const IUPAC: &[u8; 16] = b"-ACMGRSVTWYHKDBN";
 
+const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+/// Tolerance when comparing counts, which ambiguity codes make fractional.
+const EPSILON: f64 = 1e-9;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProfileError {
+    UnknownResidue { residue: char, position: usize },
+}
+
+impl Display for ProfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileError::UnknownResidue { residue, position } => {
+                write!(f, "unknown residue {:?} at position {}", residue, position)
+            }
+        }
+    }
+}
+
+impl Error for ProfileError {}
+
+/// How `Profile::consensus_by` picks the residue of each column.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConsensusRule {
+    /// The most frequent base, the first of them in ACGT order on ties.
+    First,
+    /// The most frequent base, or the ambiguity code of all of them on
+    /// ties.
+    Ambiguous,
+    /// Ambiguity code of the fewest most frequent bases that make up at
+    /// least this fraction of the column.
+    Threshold(f64),
+}
+
+/// Set of bases a residue stands for, `Some(0)` for a gap.
+fn bases(residue: u8) -> Option<usize> {
+    match residue.to_ascii_uppercase() {
+        b'-' | b'.' => Some(0),
+        b'U' => Some(8),
+        residue => IUPAC
+            .iter()
+            .skip(1)
+            .position(|&code| code == residue)
+            .map(|idx| idx + 1),
+    }
+}
+
+/**
+ * Counts of A, C, G and T in each column of a set of sequences, growing to
+ * fit the longest. Lowercase and U are read as their DNA bases, IUPAC
+ * ambiguity codes such as N share a count between the bases they stand
+ * for, and gaps (`-` or `.`) are counted apart from the bases.
+ */
+#[derive(Clone, Debug, Default)]
 pub struct Profile {
-    a: Vec<usize>,
-    c: Vec<usize>,
-    g: Vec<usize>,
-    t: Vec<usize>,
+    counts: Vec<[f64; 4]>,
+    gaps: Vec<f64>,
 }
 
 impl Profile {
     pub fn new() -> Self {
-        let a = vec![0; 1000];
-        let c = vec![0; 1000];
-        let g = vec![0; 1000];
-        let t = vec![0; 1000];
-        Profile { a, c, g, t }
-    }
-
-    pub fn add_seq(&mut self, seq: &[u8]) {
-        for (idx, &base) in seq.iter().enumerate() {
-            match base {
-                b'A' => self.a[idx] += 1,
-                b'C' => self.c[idx] += 1,
-                b'G' => self.g[idx] += 1,
-                b'T' => self.t[idx] += 1,
-                _ => todo!(),
+        Profile::default()
+    }
+
+    /// Adds the residues of `seq` to the columns from the first on, leaving
+    /// the profile as it was if one is not a nucleotide.
+    pub fn add_seq(&mut self, seq: &[u8]) -> Result<(), ProfileError> {
+        let sets: Vec<usize> = seq
+            .iter()
+            .enumerate()
+            .map(|(position, &residue)| {
+                bases(residue).ok_or(ProfileError::UnknownResidue {
+                    residue: residue as char,
+                    position,
+                })
+            })
+            .try_collect()?;
+        if self.counts.len() < seq.len() {
+            self.counts.resize(seq.len(), [0.0; 4]);
+            self.gaps.resize(seq.len(), 0.0);
+        }
+        for (column, set) in sets.into_iter().enumerate() {
+            if set == 0 {
+                self.gaps[column] += 1.0;
+                continue;
             }
+            let share = 1.0 / set.count_ones() as f64;
+            for base in (0..4).filter(|base| set & (1 << base) != 0) {
+                self.counts[column][base] += share;
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of columns.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Counts of A, C, G and T in `column`.
+    pub fn counts(&self, column: usize) -> [f64; 4] {
+        self.counts[column]
+    }
+
+    pub fn gaps(&self, column: usize) -> f64 {
+        self.gaps[column]
+    }
+
+    /// Fractions of the bases in `column` that are A, C, G and T, all zero
+    /// if it only has gaps.
+    pub fn frequencies(&self, column: usize) -> [f64; 4] {
+        let counts = self.counts[column];
+        let total: f64 = counts.iter().sum();
+        if total < EPSILON {
+            return [0.0; 4];
+        }
+        counts.map(|count| count / total)
+    }
+
+    /// Shannon entropy of the bases in `column`, in bits.
+    pub fn entropy(&self, column: usize) -> f64 {
+        self.frequencies(column)
+            .iter()
+            .filter(|&&freq| freq > 0.0)
+            .map(|freq| -freq * freq.log2())
+            .sum()
+    }
+
+    /// Information content of `column` in bits: how far its entropy falls
+    /// below the 2 bits of a uniform column. Zero for a gap-only column.
+    pub fn information(&self, column: usize) -> f64 {
+        if self.frequencies(column).iter().all(|&freq| freq == 0.0) {
+            return 0.0;
         }
+        2.0 - self.entropy(column)
     }
 
+    /// Most frequent base of each column, the first in ACGT order on ties
+    /// and a gap where there are no bases.
     pub fn consensus(&self) -> String {
-        let bases = ['A', 'C', 'G', 'T'];
-        let mut acc = String::new();
-        for base_counts in izip!(&self.a, &self.c, &self.g, &self.t) {
-            match base_counts {
-                (0, 0, 0, 0) => break,
-                (a, c, g, t) => {
-                    let x = [a, c, g, t]
-                        .into_iter()
-                        .enumerate()
-                        .max_by_key(|(_, &b)| b)
-                        .map(|(i, _)| i)
-                        .unwrap();
-                    acc.push(bases[x]);
+        self.consensus_by(ConsensusRule::First)
+    }
+
+    pub fn consensus_by(&self, rule: ConsensusRule) -> String {
+        (0..self.len())
+            .map(|column| IUPAC[self.consensus_set(column, rule)] as char)
+            .collect()
+    }
+
+    fn consensus_set(&self, column: usize, rule: ConsensusRule) -> usize {
+        let freqs = self.frequencies(column);
+        let max = freqs.iter().copied().fold(0.0, f64::max);
+        if max == 0.0 {
+            return 0;
+        }
+        let tied = (0..4).filter(|&base| max - freqs[base] < EPSILON);
+        match rule {
+            ConsensusRule::First => 1 << tied.min().unwrap(),
+            ConsensusRule::Ambiguous => tied.fold(0, |set, base| set | 1 << base),
+            ConsensusRule::Threshold(threshold) => {
+                let mut order = [0, 1, 2, 3];
+                order.sort_by(|&i, &j| freqs[j].total_cmp(&freqs[i]));
+                let (mut set, mut total) = (0, 0.0);
+                for base in order {
+                    if total >= threshold - EPSILON {
+                        break;
+                    }
+                    set |= 1 << base;
+                    total += freqs[base];
                 }
+                set
             }
         }
-        acc
     }
 }
 
 impl Display for Profile {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let consensus = self.consensus();
-        writeln!(f, "{}", consensus)?;
-        let a = self.a.iter().take(consensus.len()).join(" ");
-        writeln!(f, "A: {}", a)?;
-        let c = self.c.iter().take(consensus.len()).join(" ");
-        writeln!(f, "C: {}", c)?;
-        let g = self.g.iter().take(consensus.len()).join(" ");
-        writeln!(f, "G: {}", g)?;
-        let t = self.t.iter().take(consensus.len()).join(" ");
-        write!(f, "T: {}", t)?;
+        writeln!(f, "{}", self.consensus())?;
+        for (idx, base) in BASES.iter().enumerate() {
+            let counts = self.counts.iter().map(|counts| counts[idx]).join(" ");
+            write!(f, "{}: {}", *base as char, counts)?;
+            if idx + 1 < BASES.len() {
+                writeln!(f)?;
+            }
+        }
         Ok(())
     }
 }
@@ -85,8 +218,73 @@ mod test {
         ];
 
         let mut profile = Profile::new();
-        xs.into_iter().for_each(|x| profile.add_seq(x));
+        xs.into_iter().for_each(|x| profile.add_seq(x).unwrap());
         let consensus = profile.consensus();
         assert_eq!(consensus, String::from("ATGCAACT"));
+        assert_eq!(
+            "ATGCAACT\n\
+             A: 5 1 0 0 5 5 0 0\n\
+             C: 0 0 1 4 2 0 6 1\n\
+             G: 1 1 6 3 0 1 0 0\n\
+             T: 1 5 0 0 0 1 1 6",
+            profile.to_string()
+        );
+    }
+
+    #[test]
+    fn test_growable_and_ambiguous() {
+        let mut profile = Profile::new();
+        let long = vec![b'A'; 1500];
+        profile.add_seq(&long).unwrap();
+        profile.add_seq(b"cNr-u").unwrap();
+        assert_eq!(1500, profile.len());
+        assert_eq!([1.0, 1.0, 0.0, 0.0], profile.counts(0));
+        assert_eq!([1.25, 0.25, 0.25, 0.25], profile.counts(1));
+        assert_eq!([1.5, 0.0, 0.5, 0.0], profile.counts(2));
+        assert_eq!(1.0, profile.gaps(3));
+        assert_eq!([1.0, 0.0, 0.0, 1.0], profile.counts(4));
+        assert_eq!(
+            Err(ProfileError::UnknownResidue {
+                residue: 'X',
+                position: 2
+            }),
+            profile.add_seq(b"ACXT")
+        );
+        assert_eq!([1.0, 1.0, 0.0, 0.0], profile.counts(0));
+
+        // Columns holding only gaps no longer end the consensus.
+        let mut profile = Profile::new();
+        profile.add_seq(b"A-C").unwrap();
+        profile.add_seq(b"A-C").unwrap();
+        assert_eq!("A-C", profile.consensus());
+    }
+
+    #[test]
+    fn test_consensus_rules() {
+        let mut profile = Profile::new();
+        for seq in [b"ACGA", b"GCGA", b"ACTC", b"GCTT"] {
+            profile.add_seq(seq).unwrap();
+        }
+        assert_eq!("ACGA", profile.consensus_by(ConsensusRule::First));
+        assert_eq!("RCKA", profile.consensus_by(ConsensusRule::Ambiguous));
+        assert_eq!("ACGA", profile.consensus_by(ConsensusRule::Threshold(0.5)));
+        assert_eq!("RCKM", profile.consensus_by(ConsensusRule::Threshold(0.75)));
+        assert_eq!("RCKH", profile.consensus_by(ConsensusRule::Threshold(1.0)));
+    }
+
+    #[test]
+    fn test_entropy() {
+        let mut profile = Profile::new();
+        for seq in [b"AAN-", b"ACN-", b"AGN-", b"ATN-"] {
+            profile.add_seq(seq).unwrap();
+        }
+        assert_eq!([0.25; 4], profile.frequencies(1));
+        assert_eq!(0.0, profile.entropy(0));
+        assert_eq!(2.0, profile.information(0));
+        assert_eq!(2.0, profile.entropy(1));
+        assert_eq!(0.0, profile.information(1));
+        assert_eq!(2.0, profile.entropy(2));
+        assert_eq!([0.0; 4], profile.frequencies(3));
+        assert_eq!(0.0, profile.information(3));
     }
 }
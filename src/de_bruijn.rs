@@ -0,0 +1,644 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read};
+
+use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableGraph};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction::{Incoming, Outgoing};
+use seq_io::{fasta, fastq};
+
+use crate::orf::rev_comp;
+
+/// Sequences of the FASTA or FASTQ records in `reader`, told apart by
+/// their first character.
+pub fn read_sequences<R: Read>(reader: R) -> io::Result<Vec<Vec<u8>>> {
+    let mut reader = BufReader::new(reader);
+    match reader.fill_buf()?.first() {
+        Some(b'@') => fastq::Reader::new(reader)
+            .records()
+            .map(|rec| {
+                rec.map(|rec| rec.seq)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            })
+            .collect(),
+        Some(_) => fasta::Reader::new(reader)
+            .records()
+            .map(|rec| {
+                rec.map(|rec| rec.seq)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            })
+            .collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+/**
+ * Builds the de Bruijn graph of the k-mers in a set of reads. By default
+ * a k-mer and its reverse complement are counted together and both are
+ * added to the graph, which then holds each strand of the genome, so
+ * `k` should be odd for no k-mer to be its own reverse complement.
+ * K-mers seen fewer than `min_count` times are taken for sequencing
+ * errors and left out, as are those with residues other than ACGT.
+ */
+pub struct Builder {
+    k: usize,
+    min_count: usize,
+    canonical: bool,
+}
+
+impl Builder {
+    pub fn new(k: usize) -> Self {
+        assert!(k >= 2, "k-mers need at least two residues");
+        Builder {
+            k,
+            min_count: 2,
+            canonical: true,
+        }
+    }
+
+    pub fn min_count(mut self, min_count: usize) -> Self {
+        self.min_count = min_count;
+        self
+    }
+
+    /// Whether to merge the strands; without it only the reads as given
+    /// are used.
+    pub fn canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    fn key(&self, kmer: &[u8]) -> Vec<u8> {
+        let rc = rev_comp(kmer);
+        if self.canonical && rc.as_slice() < kmer {
+            rc
+        } else {
+            kmer.to_vec()
+        }
+    }
+
+    /// Occurrences of each k-mer of the reads, keyed by the smaller of it
+    /// and its reverse complement when merging strands.
+    pub fn count(&self, reads: &[Vec<u8>]) -> HashMap<Vec<u8>, usize> {
+        let mut counts = HashMap::new();
+        for read in reads {
+            let read = read.to_ascii_uppercase();
+            for kmer in read.windows(self.k) {
+                if kmer.iter().all(|ch| b"ACGT".contains(ch)) {
+                    *counts.entry(self.key(kmer)).or_insert(0) += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    /// Graph with a node for every (k - 1)-mer and an edge weighted by its
+    /// count for every solid k-mer, in the order the reads hold them.
+    pub fn build(&self, reads: &[Vec<u8>]) -> DeBruijnGraph {
+        let counts = self.count(reads);
+        let mut graph = DeBruijnGraph {
+            k: self.k,
+            canonical: self.canonical,
+            graph: StableGraph::new(),
+        };
+        let mut nodes = HashMap::new();
+        for read in reads {
+            let read = read.to_ascii_uppercase();
+            for kmer in read.windows(self.k) {
+                match counts.get(&self.key(kmer)) {
+                    Some(&count) if count >= self.min_count => {
+                        graph.add_kmer(&mut nodes, kmer, count);
+                        if self.canonical {
+                            graph.add_kmer(&mut nodes, &rev_comp(kmer), count);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        graph
+    }
+}
+
+/// Maximal non-branching path of the graph, spelled out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unitig {
+    pub seq: Vec<u8>,
+    /// Mean count of its k-mers.
+    pub coverage: f64,
+}
+
+/**
+ * De Bruijn graph with (k - 1)-mers as nodes and k-mers as edges, so that
+ * reads spell walks through it and a genome covered without errors or
+ * long repeats is an Eulerian path. A stable graph keeps the indices
+ * valid while tips and bubbles are removed.
+ */
+pub struct DeBruijnGraph {
+    k: usize,
+    canonical: bool,
+    graph: StableGraph<Vec<u8>, usize>,
+}
+
+impl DeBruijnGraph {
+    fn add_kmer(&mut self, nodes: &mut HashMap<Vec<u8>, NodeIndex>, kmer: &[u8], count: usize) {
+        let mut node = |label: &[u8]| {
+            *nodes
+                .entry(label.to_vec())
+                .or_insert_with(|| self.graph.add_node(label.to_vec()))
+        };
+        let (from, to) = (node(&kmer[..kmer.len() - 1]), node(&kmer[1..]));
+        if self.graph.find_edge(from, to).is_none() {
+            self.graph.add_edge(from, to, count);
+        }
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    pub fn graph(&self) -> &StableGraph<Vec<u8>, usize> {
+        &self.graph
+    }
+
+    fn degree(&self, node: NodeIndex, dir: petgraph::Direction) -> usize {
+        self.graph.edges_directed(node, dir).count()
+    }
+
+    fn is_simple(&self, node: NodeIndex) -> bool {
+        self.degree(node, Incoming) == 1 && self.degree(node, Outgoing) == 1
+    }
+
+    fn source(&self, edge: EdgeIndex) -> NodeIndex {
+        self.graph.edge_endpoints(edge).unwrap().0
+    }
+
+    fn target(&self, edge: EdgeIndex) -> NodeIndex {
+        self.graph.edge_endpoints(edge).unwrap().1
+    }
+
+    fn spell(&self, path: &[EdgeIndex]) -> Vec<u8> {
+        let mut seq = match path.first() {
+            Some(&edge) => self.graph[self.source(edge)].clone(),
+            None => return Vec::new(),
+        };
+        seq.extend(
+            path.iter()
+                .map(|&edge| *self.graph[self.target(edge)].last().unwrap()),
+        );
+        seq
+    }
+
+    /// Smallest canonical k-mer along `edges`, shared by the reverse
+    /// complement of any path or component.
+    fn strand_key(&self, edges: &[EdgeIndex]) -> Vec<u8> {
+        edges
+            .iter()
+            .map(|&edge| {
+                let kmer = self.spell(&[edge]);
+                kmer.clone().min(rev_comp(&kmer))
+            })
+            .min()
+            .unwrap_or_default()
+    }
+
+    fn coverage(&self, path: &[EdgeIndex]) -> f64 {
+        let total: usize = path.iter().map(|&edge| self.graph[edge]).sum();
+        total as f64 / path.len() as f64
+    }
+
+    /// Edges of every maximal non-branching path, the paths leaving a
+    /// branching node first and isolated cycles after.
+    fn paths(&self) -> Vec<Vec<EdgeIndex>> {
+        let mut paths = Vec::new();
+        let mut visited = HashSet::new();
+        for node in self.graph.node_indices() {
+            if self.is_simple(node) {
+                continue;
+            }
+            for edge in self.graph.edges(node) {
+                let mut path = vec![edge.id()];
+                let mut next = edge.target();
+                while self.is_simple(next) {
+                    let edge = self.graph.edges(next).next().unwrap();
+                    path.push(edge.id());
+                    next = edge.target();
+                }
+                visited.extend(path.iter().copied());
+                paths.push(path);
+            }
+        }
+        for edge in self.graph.edge_indices() {
+            if !visited.insert(edge) {
+                continue;
+            }
+            let mut path = vec![edge];
+            loop {
+                let next = self.graph.edges(self.target(*path.last().unwrap())).next();
+                match next.map(|edge| edge.id()) {
+                    Some(next) if visited.insert(next) => path.push(next),
+                    _ => break,
+                }
+            }
+            paths.push(path);
+        }
+        paths
+    }
+
+    /// Paths that stand for the same unitig as an earlier one on the
+    /// other strand, when merging strands.
+    fn is_duplicate(&self, edges: &[EdgeIndex], seen: &mut HashSet<Vec<u8>>) -> bool {
+        self.canonical && !seen.insert(self.strand_key(edges))
+    }
+
+    fn remove_paths(&mut self, paths: &[Vec<EdgeIndex>]) {
+        for &edge in paths.iter().flatten() {
+            self.graph.remove_edge(edge);
+        }
+        self.graph
+            .retain_nodes(|graph, node| graph.neighbors_undirected(node).next().is_some());
+    }
+
+    /**
+     * Removes the dead ends shorter than `max_len` k-mers that branch off
+     * the rest of the graph, which a sequencing error near the end of a
+     * read leaves behind. Where all the branches on one side of a node
+     * are such tips, the best covered one stays. Repeats until no tips
+     * are left and returns how many were removed.
+     */
+    pub fn remove_tips(&mut self, max_len: usize) -> usize {
+        let mut removed = 0;
+        loop {
+            let mut junctions: HashMap<(NodeIndex, bool), Vec<Vec<EdgeIndex>>> = HashMap::new();
+            for path in self.paths() {
+                let (start, end) = (self.source(path[0]), self.target(*path.last().unwrap()));
+                let dead_start = self.degree(start, Incoming) == 0
+                    && self.degree(start, Outgoing) == 1
+                    && self.degree(end, Incoming) >= 2;
+                let dead_end = self.degree(end, Outgoing) == 0
+                    && self.degree(end, Incoming) == 1
+                    && self.degree(start, Outgoing) >= 2;
+                if path.len() < max_len && (dead_start || dead_end) {
+                    let junction = if dead_start {
+                        (end, true)
+                    } else {
+                        (start, false)
+                    };
+                    junctions.entry(junction).or_default().push(path);
+                }
+            }
+            let mut tips = Vec::new();
+            for ((node, incoming), mut paths) in junctions {
+                let dir = if incoming { Incoming } else { Outgoing };
+                if paths.len() == self.degree(node, dir) {
+                    let best = (0..paths.len())
+                        .max_by(|&i, &j| {
+                            let (fst, snd) = (&paths[i], &paths[j]);
+                            self.coverage(fst)
+                                .total_cmp(&self.coverage(snd))
+                                .then(self.spell(snd).cmp(&self.spell(fst)))
+                        })
+                        .unwrap();
+                    paths.swap_remove(best);
+                }
+                tips.extend(paths);
+            }
+            if tips.is_empty() {
+                return removed;
+            }
+            removed += tips.len();
+            self.remove_paths(&tips);
+        }
+    }
+
+    /**
+     * Pops the bubbles made of non-branching paths of at most `max_len`
+     * k-mers that leave the same node and meet again, as a substitution in
+     * a read does, keeping the best covered path of each. Returns how many
+     * paths were removed.
+     */
+    pub fn pop_bubbles(&mut self, max_len: usize) -> usize {
+        let mut removed = 0;
+        loop {
+            let mut ends: HashMap<(NodeIndex, NodeIndex), Vec<Vec<EdgeIndex>>> = HashMap::new();
+            for path in self.paths() {
+                let (start, end) = (self.source(path[0]), self.target(*path.last().unwrap()));
+                if start != end && path.len() <= max_len {
+                    ends.entry((start, end)).or_default().push(path);
+                }
+            }
+            let mut popped = Vec::new();
+            for (_, mut paths) in ends {
+                if paths.len() < 2 {
+                    continue;
+                }
+                paths.sort_by(|fst, snd| {
+                    self.coverage(snd)
+                        .total_cmp(&self.coverage(fst))
+                        .then(self.spell(fst).cmp(&self.spell(snd)))
+                });
+                popped.extend(paths.into_iter().skip(1));
+            }
+            if popped.is_empty() {
+                return removed;
+            }
+            removed += popped.len();
+            self.remove_paths(&popped);
+        }
+    }
+
+    /// Unitigs of the graph, only one of each reverse complement pair when
+    /// merging strands.
+    pub fn unitigs(&self) -> Vec<Unitig> {
+        let mut seen = HashSet::new();
+        self.paths()
+            .into_iter()
+            .filter(|path| !self.is_duplicate(path, &mut seen))
+            .map(|path| Unitig {
+                seq: self.spell(&path),
+                coverage: self.coverage(&path),
+            })
+            .collect()
+    }
+
+    /// Weakly connected components, each with its nodes in index order.
+    fn components(&self) -> Vec<Vec<NodeIndex>> {
+        let mut visited = HashSet::new();
+        let mut components = Vec::new();
+        for node in self.graph.node_indices() {
+            if !visited.insert(node) {
+                continue;
+            }
+            let mut component = vec![node];
+            let mut queue = VecDeque::from([node]);
+            while let Some(node) = queue.pop_front() {
+                for next in self.graph.neighbors_undirected(node) {
+                    if visited.insert(next) {
+                        component.push(next);
+                        queue.push_back(next);
+                    }
+                }
+            }
+            component.sort_unstable();
+            components.push(component);
+        }
+        components
+    }
+
+    /**
+     * Walk through every edge of a component once (Hierholzer), and whether
+     * it is a cycle. A path needs one node with an extra outgoing edge to
+     * start at and one with an extra incoming edge to end at, a cycle
+     * every node balanced; otherwise there is none.
+     */
+    fn eulerian_walk(&self, component: &[NodeIndex]) -> Option<(Vec<EdgeIndex>, bool)> {
+        let (mut starts, mut ends) = (Vec::new(), Vec::new());
+        for &node in component {
+            let (out, inc) = (self.degree(node, Outgoing), self.degree(node, Incoming));
+            match out as isize - inc as isize {
+                0 => (),
+                1 => starts.push(node),
+                -1 => ends.push(node),
+                _ => return None,
+            }
+        }
+        let (start, cycle) = match (starts.as_slice(), ends.as_slice()) {
+            ([start], [_]) => (*start, false),
+            ([], []) => (component[0], true),
+            _ => return None,
+        };
+
+        let mut unused: HashMap<NodeIndex, Vec<EdgeIndex>> = component
+            .iter()
+            .map(|&node| (node, self.graph.edges(node).map(|edge| edge.id()).collect()))
+            .collect();
+        let mut stack = vec![(start, None)];
+        let mut walk = Vec::new();
+        while let Some(&(node, edge)) = stack.last() {
+            match unused.get_mut(&node).unwrap().pop() {
+                Some(next) => stack.push((self.target(next), Some(next))),
+                None => {
+                    stack.pop();
+                    walk.extend(edge);
+                }
+            }
+        }
+        walk.reverse();
+        Some((walk, cycle))
+    }
+
+    /**
+     * Contigs spelled by the Eulerian path or cycle of each component,
+     * a cycle being cut where it started so that it reads as the
+     * circular sequence. Components without either fall back to their
+     * unitigs. When merging strands, only one of each pair of
+     * reverse-complement components is spelled.
+     */
+    pub fn contigs(&self) -> Vec<Vec<u8>> {
+        let paths = self.paths();
+        let mut seen_components = HashSet::new();
+        let mut seen_paths = HashSet::new();
+        let mut contigs = Vec::new();
+        for component in self.components() {
+            let edges: Vec<EdgeIndex> = component
+                .iter()
+                .flat_map(|&node| self.graph.edges(node).map(|edge| edge.id()))
+                .collect();
+            if edges.is_empty() || self.is_duplicate(&edges, &mut seen_components) {
+                continue;
+            }
+            match self.eulerian_walk(&component) {
+                Some((walk, cycle)) => {
+                    let mut contig = self.spell(&walk);
+                    if cycle {
+                        contig.truncate(walk.len());
+                    }
+                    contigs.push(contig);
+                }
+                None => contigs.extend(
+                    paths
+                        .iter()
+                        .filter(|path| component.binary_search(&self.source(path[0])).is_ok())
+                        .filter(|path| !self.is_duplicate(path, &mut seen_paths))
+                        .map(|path| self.spell(path)),
+                ),
+            }
+        }
+        contigs
+    }
+
+    /**
+     * Unitig graph in GFA 1 format, numbering segments in the order of
+     * `unitigs` and tagging them with their total k-mer count. Links
+     * overlap by k - 1 residues. When merging strands a unitig and its
+     * reverse complement are one segment, the latter read as `-`.
+     */
+    pub fn gfa(&self) -> String {
+        let paths = self.paths();
+        let mut segments = HashMap::new();
+        // Segment of each path and whether it reads forwards.
+        let mut oriented = Vec::with_capacity(paths.len());
+        for path in &paths {
+            let key = if self.canonical {
+                self.strand_key(path)
+            } else {
+                self.spell(path)
+            };
+            let next = segments.len() + 1;
+            let id = *segments.entry(key).or_insert(next);
+            oriented.push((id, id == next));
+        }
+
+        let mut gfa = String::from("H\tVN:Z:1.0\n");
+        for (path, &(id, forward)) in paths.iter().zip(&oriented) {
+            if forward {
+                let count: usize = path.iter().map(|&edge| self.graph[edge]).sum();
+                let seq = String::from_utf8_lossy(&self.spell(path)).into_owned();
+                writeln!(gfa, "S\t{}\t{}\tKC:i:{}", id, seq, count).unwrap();
+            }
+        }
+        let mut starting: HashMap<NodeIndex, Vec<usize>> = HashMap::new();
+        for (idx, path) in paths.iter().enumerate() {
+            starting.entry(self.source(path[0])).or_default().push(idx);
+        }
+        let mut links = HashSet::new();
+        for (path, &(from, from_fwd)) in paths.iter().zip(&oriented) {
+            let end = self.target(*path.last().unwrap());
+            for &next in starting.get(&end).into_iter().flatten() {
+                let (to, to_fwd) = oriented[next];
+                // The same link read along the other strand.
+                let other = (to, !to_fwd, from, !from_fwd);
+                if !links.insert((from, from_fwd, to, to_fwd)) || links.contains(&other) {
+                    continue;
+                }
+                let sign = |forward| if forward { '+' } else { '-' };
+                writeln!(
+                    gfa,
+                    "L\t{}\t{}\t{}\t{}\t{}M",
+                    from,
+                    sign(from_fwd),
+                    to,
+                    sign(to_fwd),
+                    self.k - 1
+                )
+                .unwrap();
+            }
+        }
+        gfa
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, random_dna};
+
+    /// Reads of `len` every `step` residues along `genome`, each `depth`
+    /// times.
+    fn tile(genome: &[u8], len: usize, step: usize, depth: usize) -> Vec<Vec<u8>> {
+        (0..=genome.len() - len)
+            .step_by(step)
+            .flat_map(|start| vec![genome[start..start + len].to_vec(); depth])
+            .collect()
+    }
+
+    #[test]
+    fn test_count() {
+        let reads = vec![b"ACGTTNAC".to_vec(), b"aacgt".to_vec()];
+        let counts = Builder::new(3).count(&reads);
+        // Counted together with their reverse complements CGT and GTT.
+        assert_eq!(Some(&4), counts.get(b"ACG".as_slice()));
+        assert_eq!(Some(&2), counts.get(b"AAC".as_slice()));
+        assert_eq!(None, counts.get(b"CGT".as_slice()));
+        assert_eq!(None, counts.get(b"TTN".as_slice()));
+        let forward = Builder::new(3).canonical(false).count(&reads);
+        assert_eq!(Some(&2), forward.get(b"CGT".as_slice()));
+    }
+
+    #[test]
+    fn test_read_sequences() {
+        let fasta = b">a\nACGT\nTT\n>b\nGG\n";
+        let fastq = b"@a\nACGT\n+\nIIII\n@b\nGG\n+\nII\n";
+        assert_eq!(
+            vec![b"ACGTTT".to_vec(), b"GG".to_vec()],
+            read_sequences(&fasta[..]).unwrap()
+        );
+        assert_eq!(
+            vec![b"ACGT".to_vec(), b"GG".to_vec()],
+            read_sequences(&fastq[..]).unwrap()
+        );
+        assert!(read_sequences(&b""[..]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unitigs_and_gfa() {
+        let reads = vec![b"AACGG".to_vec(), b"TACGC".to_vec()];
+        let graph = Builder::new(3).min_count(1).canonical(false).build(&reads);
+        let unitigs: Vec<Vec<u8>> = graph.unitigs().into_iter().map(|u| u.seq).collect();
+        let expected: Vec<Vec<u8>> = ["AAC", "ACG", "CGC", "CGG", "TAC"]
+            .iter()
+            .map(|seq| seq.as_bytes().to_vec())
+            .collect();
+        assert_eq!(expected, unitigs);
+        // Two sources, so no Eulerian path.
+        assert_eq!(expected, graph.contigs());
+        assert_eq!(
+            "H\tVN:Z:1.0\n\
+             S\t1\tAAC\tKC:i:1\n\
+             S\t2\tACG\tKC:i:2\n\
+             S\t3\tCGC\tKC:i:1\n\
+             S\t4\tCGG\tKC:i:1\n\
+             S\t5\tTAC\tKC:i:1\n\
+             L\t1\t+\t2\t+\t2M\n\
+             L\t2\t+\t3\t+\t2M\n\
+             L\t2\t+\t4\t+\t2M\n\
+             L\t5\t+\t2\t+\t2M\n",
+            graph.gfa()
+        );
+    }
+
+    #[test]
+    fn test_assemble_genome() {
+        let genome = random_dna(&mut testing::rng(21), 400);
+        let graph = Builder::new(15).build(&tile(&genome, 40, 5, 2));
+        assert_eq!(vec![genome.clone()], graph.contigs());
+        let unitigs = graph.unitigs();
+        assert_eq!(1, unitigs.len());
+        assert_eq!(genome, unitigs[0].seq);
+
+        // One segment for both strands, linked to nothing.
+        let gfa = graph.gfa();
+        assert_eq!(2, gfa.lines().count());
+
+        // Reads from the other strand add nothing new.
+        let mut reads = tile(&genome, 40, 5, 1);
+        reads.extend(tile(&rev_comp(&genome), 40, 5, 1));
+        assert_eq!(
+            vec![genome.clone()],
+            Builder::new(15).build(&reads).contigs()
+        );
+
+        // A circular genome is a single Eulerian cycle.
+        let mut circular = genome.clone();
+        circular.extend_from_slice(&genome[..39]);
+        let reads = tile(&circular, 40, 5, 2);
+        let contigs = Builder::new(15).canonical(false).build(&reads).contigs();
+        assert_eq!(vec![genome], contigs);
+    }
+
+    #[test]
+    fn test_clean() {
+        let genome = random_dna(&mut testing::rng(5), 300);
+        let mut reads = tile(&genome, 50, 10, 3);
+        // An error near the end of one read leaves a tip, one in the middle
+        // of another a bubble.
+        reads[10][47] = if reads[10][47] == b'A' { b'C' } else { b'A' };
+        reads[20][25] = if reads[20][25] == b'A' { b'C' } else { b'A' };
+        let mut graph = Builder::new(11).min_count(1).build(&reads);
+        assert!(graph.unitigs().len() > 1);
+        assert_eq!(2, graph.remove_tips(22));
+        assert_eq!(2, graph.pop_bubbles(22));
+        assert_eq!(vec![genome], graph.contigs());
+        assert_eq!(0, graph.remove_tips(22));
+    }
+}
@@ -0,0 +1,335 @@
+use std::ops::Range;
+
+use ndarray::Array2;
+
+/// A single traceback move, named after where the previous cell sits in a
+/// matrix indexed `(fst_idx, snd_idx)`: `Top` consumes a residue of `snd`
+/// only, `Left` one of `fst` only, `TopLeft` one of each.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Top,
+    TopLeft,
+    Left,
+}
+
+impl Direction {
+    fn bit(self) -> u8 {
+        match self {
+            Direction::TopLeft => 0b001,
+            Direction::Top => 0b010,
+            Direction::Left => 0b100,
+        }
+    }
+}
+
+/// With affine gaps, whether the best gap of one kind ending in a cell
+/// starts there or continues a gap from the previous cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Gap {
+    Open,
+    Extend,
+}
+
+/**
+ * Every move that ties for the best score in a cell, packed into the low
+ * three bits of a byte. An empty set marks a cell where tracebacks stop.
+ * The next four bits record, for affine gaps, how the best `Top` and `Left`
+ * gaps ending in the cell were reached.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Directions(u8);
+
+impl Directions {
+    /// Order in which tied moves are taken when only one is wanted.
+    const PRIORITY: [Direction; 3] = [Direction::TopLeft, Direction::Top, Direction::Left];
+    const MOVES: u8 = 0b111;
+
+    fn gap_bit(dir: Direction, gap: Gap) -> u8 {
+        match (dir, gap) {
+            (Direction::Top, Gap::Open) => 0b000_1000,
+            (Direction::Top, Gap::Extend) => 0b001_0000,
+            (Direction::Left, Gap::Open) => 0b010_0000,
+            (Direction::Left, Gap::Extend) => 0b100_0000,
+            (Direction::TopLeft, _) => panic!("diagonal moves are never gaps"),
+        }
+    }
+
+    pub fn empty() -> Self {
+        Directions(0)
+    }
+
+    pub fn only(dir: Direction) -> Self {
+        Directions(dir.bit())
+    }
+
+    pub fn insert(&mut self, dir: Direction) {
+        self.0 |= dir.bit();
+    }
+
+    pub fn contains(&self, dir: Direction) -> bool {
+        self.0 & dir.bit() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 & Directions::MOVES == 0
+    }
+
+    /// Highest priority move in the set: diagonal, then top, then left.
+    pub fn first(&self) -> Option<Direction> {
+        self.iter().next()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Direction> + '_ {
+        Directions::PRIORITY
+            .into_iter()
+            .filter(move |&dir| self.contains(dir))
+    }
+
+    pub fn insert_gap(&mut self, dir: Direction, gap: Gap) {
+        self.0 |= Directions::gap_bit(dir, gap);
+    }
+
+    pub fn contains_gap(&self, dir: Direction, gap: Gap) -> bool {
+        self.0 & Directions::gap_bit(dir, gap) != 0
+    }
+
+    /// How the best gap in direction `dir` was reached, preferring to open
+    /// a new gap on ties. `None` if no gap was recorded for this cell.
+    pub fn first_gap(&self, dir: Direction) -> Option<Gap> {
+        [Gap::Open, Gap::Extend]
+            .into_iter()
+            .find(|&gap| self.contains_gap(dir, gap))
+    }
+
+    /// Best score among `(score, move)` candidates, along with every move
+    /// reaching it.
+    pub fn max_of<S: Copy + PartialOrd>(candidates: &[(S, Direction)]) -> (S, Directions) {
+        Directions::best_of(candidates, |new, old| new > old)
+    }
+
+    /// Same as `max_of`, for distances where lower is better.
+    pub fn min_of<S: Copy + PartialOrd>(candidates: &[(S, Direction)]) -> (S, Directions) {
+        Directions::best_of(candidates, |new, old| new < old)
+    }
+
+    fn best_of<S: Copy + PartialOrd>(
+        candidates: &[(S, Direction)],
+        better: impl Fn(S, S) -> bool,
+    ) -> (S, Directions) {
+        let mut iter = candidates.iter();
+        let &(mut best, first) = iter.next().unwrap();
+        let mut dirs = Directions::only(first);
+        for &(score, dir) in iter {
+            if better(score, best) {
+                best = score;
+                dirs = Directions::only(dir);
+            } else if score == best {
+                dirs.insert(dir);
+            }
+        }
+        (best, dirs)
+    }
+}
+
+/**
+ * Dense DP table with one score and one packed set of traceback moves per
+ * cell, indexed `(fst_idx, snd_idx)` where index 0 is the empty prefix.
+ */
+#[derive(Clone, Debug)]
+pub struct DpMatrix<S> {
+    scores: Array2<S>,
+    dirs: Array2<Directions>,
+}
+
+impl<S: Copy + Default> DpMatrix<S> {
+    pub fn new(fst_len: usize, snd_len: usize) -> Self {
+        DpMatrix {
+            scores: Array2::from_elem((fst_len + 1, snd_len + 1), S::default()),
+            dirs: Array2::from_elem((fst_len + 1, snd_len + 1), Directions::empty()),
+        }
+    }
+
+    pub fn score(&self, fst_idx: usize, snd_idx: usize) -> S {
+        self.scores[[fst_idx, snd_idx]]
+    }
+
+    pub fn dirs(&self, fst_idx: usize, snd_idx: usize) -> Directions {
+        self.dirs[[fst_idx, snd_idx]]
+    }
+
+    pub fn set(&mut self, fst_idx: usize, snd_idx: usize, score: S, dirs: Directions) {
+        self.scores[[fst_idx, snd_idx]] = score;
+        self.dirs[[fst_idx, snd_idx]] = dirs;
+    }
+
+    /// Lengths of the two sequences the table was built for.
+    pub fn seq_lens(&self) -> (usize, usize) {
+        let (rows, cols) = self.scores.dim();
+        (rows - 1, cols - 1)
+    }
+}
+
+/// Read access to a DP table, all a traceback needs. Implemented by both
+/// the dense and the banded tables.
+pub trait ScoreTable<S> {
+    fn score(&self, fst_idx: usize, snd_idx: usize) -> S;
+    fn dirs(&self, fst_idx: usize, snd_idx: usize) -> Directions;
+}
+
+impl<S: Copy + Default> ScoreTable<S> for DpMatrix<S> {
+    fn score(&self, fst_idx: usize, snd_idx: usize) -> S {
+        DpMatrix::score(self, fst_idx, snd_idx)
+    }
+
+    fn dirs(&self, fst_idx: usize, snd_idx: usize) -> Directions {
+        DpMatrix::dirs(self, fst_idx, snd_idx)
+    }
+}
+
+/**
+ * DP table that only stores a contiguous run of columns in each row, as
+ * used by banded and X-drop alignment. Rows are added top to bottom, and
+ * cells outside the stored runs read as `outside` with no moves.
+ */
+#[derive(Clone, Debug)]
+pub struct BandedMatrix<S> {
+    rows: Vec<(Range<usize>, usize)>,
+    scores: Vec<S>,
+    dirs: Vec<Directions>,
+    outside: S,
+}
+
+impl<S: Copy> BandedMatrix<S> {
+    pub fn new(outside: S) -> Self {
+        BandedMatrix {
+            rows: Vec::new(),
+            scores: Vec::new(),
+            dirs: Vec::new(),
+            outside,
+        }
+    }
+
+    /// Appends the next row, whose cells start at column `first_col`.
+    pub fn push_row(&mut self, first_col: usize, cells: impl IntoIterator<Item = (S, Directions)>) {
+        let offset = self.scores.len();
+        for (score, dirs) in cells {
+            self.scores.push(score);
+            self.dirs.push(dirs);
+        }
+        let cols = first_col..first_col + self.scores.len() - offset;
+        self.rows.push((cols, offset));
+    }
+
+    /// Columns stored for row `fst_idx`.
+    pub fn cols(&self, fst_idx: usize) -> Range<usize> {
+        self.rows
+            .get(fst_idx)
+            .map_or(0..0, |(cols, _)| cols.clone())
+    }
+
+    /// Number of rows added so far.
+    pub fn num_rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Number of cells stored, a measure of how much work the fill did.
+    pub fn num_cells(&self) -> usize {
+        self.scores.len()
+    }
+
+    fn index(&self, fst_idx: usize, snd_idx: usize) -> Option<usize> {
+        let (cols, offset) = self.rows.get(fst_idx)?;
+        cols.contains(&snd_idx)
+            .then(|| offset + snd_idx - cols.start)
+    }
+}
+
+impl<S: Copy> ScoreTable<S> for BandedMatrix<S> {
+    fn score(&self, fst_idx: usize, snd_idx: usize) -> S {
+        self.index(fst_idx, snd_idx)
+            .map_or(self.outside, |idx| self.scores[idx])
+    }
+
+    fn dirs(&self, fst_idx: usize, snd_idx: usize) -> Directions {
+        self.index(fst_idx, snd_idx)
+            .map_or(Directions::empty(), |idx| self.dirs[idx])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directions() {
+        let mut dirs = Directions::only(Direction::Left);
+        assert_eq!(Some(Direction::Left), dirs.first());
+        dirs.insert(Direction::TopLeft);
+        assert_eq!(Some(Direction::TopLeft), dirs.first());
+        assert!(dirs.contains(Direction::Left));
+        assert!(!dirs.contains(Direction::Top));
+        assert_eq!(2, dirs.iter().count());
+        assert!(Directions::empty().is_empty());
+        assert_eq!(None, Directions::empty().first());
+    }
+
+    #[test]
+    fn test_gap_moves() {
+        let mut dirs = Directions::empty();
+        dirs.insert_gap(Direction::Top, Gap::Extend);
+        assert!(dirs.is_empty());
+        assert_eq!(Some(Gap::Extend), dirs.first_gap(Direction::Top));
+        assert_eq!(None, dirs.first_gap(Direction::Left));
+        dirs.insert_gap(Direction::Top, Gap::Open);
+        assert_eq!(Some(Gap::Open), dirs.first_gap(Direction::Top));
+        dirs.insert(Direction::Left);
+        assert_eq!(vec![Direction::Left], dirs.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_max_min_of() {
+        let candidates = [
+            (3, Direction::Top),
+            (5, Direction::Left),
+            (5, Direction::TopLeft),
+        ];
+        let (best, dirs) = Directions::max_of(&candidates);
+        assert_eq!(5, best);
+        assert!(dirs.contains(Direction::Left) && dirs.contains(Direction::TopLeft));
+        let (best, dirs) = Directions::min_of(&candidates);
+        assert_eq!(3, best);
+        assert_eq!(Directions::only(Direction::Top), dirs);
+    }
+
+    #[test]
+    fn test_dp_matrix() {
+        let mut mat = DpMatrix::<isize>::new(2, 3);
+        assert_eq!((2, 3), mat.seq_lens());
+        mat.set(2, 3, -4, Directions::only(Direction::Top));
+        assert_eq!(-4, mat.score(2, 3));
+        assert_eq!(Some(Direction::Top), mat.dirs(2, 3).first());
+        assert_eq!(0, mat.score(0, 0));
+    }
+
+    #[test]
+    fn test_banded_matrix() {
+        let mut mat = BandedMatrix::new(-100);
+        mat.push_row(
+            0,
+            [
+                (0, Directions::empty()),
+                (-1, Directions::only(Direction::Top)),
+            ],
+        );
+        mat.push_row(1, [(3, Directions::only(Direction::TopLeft))]);
+        assert_eq!(2, mat.num_rows());
+        assert_eq!(3, mat.num_cells());
+        assert_eq!(1..2, mat.cols(1));
+        assert_eq!(-1, mat.score(0, 1));
+        assert_eq!(3, mat.score(1, 1));
+        assert_eq!(-100, mat.score(1, 0));
+        assert_eq!(-100, mat.score(2, 2));
+        assert!(mat.dirs(1, 2).is_empty());
+        assert_eq!(Some(Direction::TopLeft), mat.dirs(1, 1).first());
+    }
+}
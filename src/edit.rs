@@ -1,5 +1,7 @@
 use itertools::Itertools;
-use std::collections::HashMap;
+
+use crate::alignment::{Alignment, Operation};
+use crate::dp::{Direction, Directions, DpMatrix};
 
 #[derive(Copy, Clone)]
 pub enum AlignOp {
@@ -9,122 +11,117 @@ pub enum AlignOp {
     Nothing,
 }
 
-#[derive(Clone)]
-pub struct Cell {
-    dist: usize,
-    op: AlignOp,
-}
-
-impl Cell {
-    fn new(dist: usize) -> Self {
-        Cell {
-            dist,
-            op: AlignOp::Nothing,
-        }
-    }
-
-    fn matches(dist: usize) -> Self {
-        Cell {
-            dist,
-            op: AlignOp::Match,
+impl AlignOp {
+    fn from_dir(dir: Option<Direction>) -> Self {
+        match dir {
+            Some(Direction::Left) => AlignOp::Insertion,
+            Some(Direction::Top) => AlignOp::Deletion,
+            Some(Direction::TopLeft) => AlignOp::Match,
+            None => AlignOp::Nothing,
         }
     }
 
-    fn insertion(dist: usize) -> Self {
-        Cell {
-            dist,
-            op: AlignOp::Insertion,
+    fn dir(self) -> Directions {
+        match self {
+            AlignOp::Insertion => Directions::only(Direction::Left),
+            AlignOp::Deletion => Directions::only(Direction::Top),
+            AlignOp::Match => Directions::only(Direction::TopLeft),
+            AlignOp::Nothing => Directions::empty(),
         }
     }
-
-    fn deletion(dist: usize) -> Self {
-        Cell {
-            dist,
-            op: AlignOp::Deletion,
-        }
-    }
-
-    fn dist(&self) -> usize {
-        self.dist
-    }
-
-    fn op(&self) -> AlignOp {
-        self.op
-    }
 }
 
-type TBMap = HashMap<(usize, usize), Cell>;
+pub type TBMap = DpMatrix<usize>;
 
 fn indices(xs: &[u8], ys: &[u8]) -> impl Iterator<Item = (usize, usize)> {
     (1..=xs.len()).cartesian_product(1..=ys.len())
 }
 
 pub fn wagner_fischer(xs: &[u8], ys: &[u8]) -> TBMap {
-    let mut edmap = HashMap::new();
-    for x in 0..=xs.len() {
-        edmap.insert((x, 0), Cell::new(x));
+    let mut edmap = DpMatrix::new(xs.len(), ys.len());
+    for x in 1..=xs.len() {
+        edmap.set(x, 0, x, AlignOp::Insertion.dir());
     }
 
-    for y in 0..=ys.len() {
-        edmap.insert((0, y), Cell::new(y));
+    for y in 1..=ys.len() {
+        edmap.set(0, y, y, AlignOp::Deletion.dir());
     }
 
     for (i, j) in indices(xs, ys) {
         if xs[i - 1] == ys[j - 1] {
-            let prev = edmap[&(i - 1, j - 1)].dist();
-            edmap.insert((i, j), Cell::matches(prev));
+            let prev = edmap.score(i - 1, j - 1);
+            edmap.set(i, j, prev, AlignOp::Match.dir());
         } else {
-            let min_op = vec![
-                Cell::insertion(edmap[&(i - 1, j)].dist() + 1),
-                Cell::deletion(edmap[&(i, j - 1)].dist() + 1),
-                Cell::matches(edmap[&(i - 1, j - 1)].dist() + 1),
+            let min_op = [
+                (edmap.score(i - 1, j) + 1, AlignOp::Insertion),
+                (edmap.score(i, j - 1) + 1, AlignOp::Deletion),
+                (edmap.score(i - 1, j - 1) + 1, AlignOp::Match),
             ];
-            let min_op = min_op
+            let (dist, op) = min_op
                 .iter()
-                .cloned()
-                .min_by_key(|cell| cell.dist())
+                .copied()
+                .min_by_key(|&(dist, _)| dist)
                 .unwrap();
-            edmap.insert((i, j), min_op);
+            edmap.set(i, j, dist, op.dir());
         }
     }
     edmap
 }
 
 pub fn distance(xs: &[u8], ys: &[u8], hmap: &TBMap) -> usize {
-    hmap[&(xs.len(), ys.len())].dist()
+    hmap.score(xs.len(), ys.len())
+}
+
+/// Edit distance without a traceback, keeping only two rows of the table.
+pub fn distance_only(xs: &[u8], ys: &[u8]) -> usize {
+    let mut prev: Vec<usize> = (0..=ys.len()).collect();
+    let mut curr = vec![0; ys.len() + 1];
+    for i in 1..=xs.len() {
+        curr[0] = i;
+        for j in 1..=ys.len() {
+            curr[j] = if xs[i - 1] == ys[j - 1] {
+                prev[j - 1]
+            } else {
+                1 + prev[j].min(curr[j - 1]).min(prev[j - 1])
+            };
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[ys.len()]
 }
 
-pub fn align(xs: &[u8], ys: &[u8], tbmap: &TBMap) -> (String, String) {
+/**
+ * Optimal alignment of `xs` (as `fst`) with `ys` (as `snd`) read off the
+ * traceback. The score is the negated edit distance. Note that an
+ * `AlignOp::Insertion` consumes a residue of `xs` only, which is a
+ * deletion in the `Alignment`, and vice versa.
+ */
+pub fn align(xs: &[u8], ys: &[u8], tbmap: &TBMap) -> Alignment {
     let mut idx = (xs.len(), ys.len());
-    let mut xs_acc = Vec::new();
-    let mut ys_acc = Vec::new();
+    let mut ops = Vec::new();
 
     while idx.0 != 0 || idx.1 != 0 {
-        match tbmap[&idx].op() {
+        match AlignOp::from_dir(tbmap.dirs(idx.0, idx.1).first()) {
             AlignOp::Deletion => {
-                xs_acc.insert(0, b'-');
-                ys_acc.insert(0, ys[idx.1 - 1]);
+                ops.push(Operation::Insertion);
                 idx.1 -= 1;
             }
             AlignOp::Insertion => {
-                xs_acc.insert(0, xs[idx.0 - 1]);
-                ys_acc.insert(0, b'-');
+                ops.push(Operation::Deletion);
                 idx.0 -= 1;
             }
             AlignOp::Match => {
-                xs_acc.insert(0, xs[idx.0 - 1]);
-                ys_acc.insert(0, ys[idx.1 - 1]);
+                ops.push(Operation::pair(xs[idx.0 - 1], ys[idx.1 - 1]));
                 idx.0 -= 1;
                 idx.1 -= 1;
             }
-            _ => unreachable!(),
+            AlignOp::Nothing => unreachable!(),
         }
     }
+    ops.reverse();
 
-    (
-        String::from_utf8(xs_acc).unwrap(),
-        String::from_utf8(ys_acc).unwrap(),
-    )
+    let dist = distance(xs, ys, tbmap) as isize;
+    Alignment::new(-dist, (0, 0), ops, (xs.len(), ys.len()))
 }
 
 #[cfg(test)]
@@ -138,6 +135,18 @@ mod tests {
         let tbm = wagner_fischer(xs, ys);
         let actual = distance(xs, ys, &tbm);
         assert_eq!(5, actual);
+        assert_eq!(5, distance_only(xs, ys));
+    }
+
+    #[test]
+    fn test_align_to_border() {
+        let xs = b"AAAC";
+        let ys = b"C";
+        let tbm = wagner_fischer(xs, ys);
+        let (xs_prime, ys_prime) = align(xs, ys, &tbm).aligned(xs, ys);
+        assert_eq!(3, distance(xs, ys, &tbm));
+        assert_eq!(b"AAAC".to_vec(), xs_prime);
+        assert_eq!(b"---C".to_vec(), ys_prime);
     }
 
     #[test]
@@ -146,9 +155,12 @@ mod tests {
         let ys = b"PRTTEIN";
         let tbm = wagner_fischer(xs, ys);
         let dist = distance(xs, ys, &tbm);
-        let (xs_prime, ys_prime) = align(xs, ys, &tbm);
+        let alignment = align(xs, ys, &tbm);
+        let (xs_prime, ys_prime) = alignment.aligned(xs, ys);
         assert_eq!(dist, 4);
-        assert_eq!(String::from("PR-TTEIN"), ys_prime);
-        assert_eq!(String::from("PRETTY--"), xs_prime);
+        assert_eq!(-4, alignment.score);
+        assert_eq!(b"PR-TTEIN".to_vec(), ys_prime);
+        assert_eq!(b"PRETTY--".to_vec(), xs_prime);
+        assert_eq!("2=1D2=1X2I", alignment.cigar());
     }
 }
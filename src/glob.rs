@@ -1,196 +1,86 @@
-use std::collections::HashMap;
 use std::collections::HashSet;
 
-use bio::scores::blosum62;
-use itertools::iproduct;
-use log::info;
-
-#[derive(Hash, Eq, PartialEq, Clone, Copy)]
-enum Direction {
-    Top,
-    TopLeft,
-    Left,
-}
-
-impl Direction {
-    fn top() -> HashSet<Direction> {
-        let mut acc = HashSet::new();
-        acc.insert(Direction::Top);
-        acc
-    }
-
-    fn left() -> HashSet<Direction> {
-        let mut acc = HashSet::new();
-        acc.insert(Direction::Left);
-        acc
-    }
-}
-
-struct Cell {
-    score: isize,
-    dirs: HashSet<Direction>,
-}
-
-impl Cell {
-    fn new(score: isize) -> Self {
-        let dirs = HashSet::new();
-        Cell { score, dirs }
-    }
-
-    fn new_with_dir(score: isize, dirs: HashSet<Direction>) -> Self {
-        Cell { score, dirs }
-    }
-
-    fn score(&self) -> isize {
-        self.score
-    }
-
-    fn from_cell_max(iter_cell: &[(isize, Direction)]) -> Self {
-        let mut iter_cell = iter_cell.into_iter();
-        let first = iter_cell.next().unwrap();
-        let mut max_score = first.0;
-        let mut acc = HashSet::new();
-        acc.insert(first.1);
-
-        for &(score, dir) in iter_cell {
-            if score == max_score {
-                acc.insert(dir);
-            } else if score > max_score {
-                max_score = score;
-                acc.clear();
-                acc.insert(dir);
-            }
-        }
-
-        Cell {
-            score: max_score,
-            dirs: acc,
-        }
-    }
-
-    fn get_any_dir(&self) -> Direction {
-        *self.dirs.iter().next().unwrap()
-    }
-}
-
-type AlignmentMatrix = HashMap<(usize, usize), Cell>;
+use crate::alignment::Alignment;
+use crate::dp::DpMatrix;
+use crate::pairwise::{self, AlignmentMatrix, OptimalAlignments};
+use crate::stats::{ShuffleTest, Significance};
+use crate::substitution::SubstitutionMatrix;
 
 pub struct GlobalAlignment {
     fst: Vec<u8>,
     snd: Vec<u8>,
     linear_gap_penalty: isize,
+    matrix: SubstitutionMatrix,
     mat: AlignmentMatrix,
 }
 
 impl GlobalAlignment {
     pub fn new(fst: Vec<u8>, snd: Vec<u8>) -> Self {
-        let mut mat = HashMap::new();
-        let linear_gap_penalty = -5isize;
-        mat.insert((0, 0), Cell::new(0));
-
-        for idx in 1..=fst.len() {
-            mat.insert(
-                (idx, 0usize),
-                Cell::new_with_dir(idx as isize * linear_gap_penalty, Direction::left()),
-            );
-        }
-
-        for idx in 1..=snd.len() {
-            mat.insert(
-                (0usize, idx),
-                Cell::new_with_dir(idx as isize * linear_gap_penalty, Direction::top()),
-            );
-        }
+        GlobalAlignment::with_matrix(fst, snd, SubstitutionMatrix::blosum62())
+    }
 
+    pub fn with_matrix(fst: Vec<u8>, snd: Vec<u8>, matrix: SubstitutionMatrix) -> Self {
         GlobalAlignment {
             fst,
             snd,
-            linear_gap_penalty,
-            mat,
+            linear_gap_penalty: -5,
+            matrix,
+            mat: DpMatrix::new(0, 0),
         }
     }
 
-    fn insert(&mut self, idxs: (usize, usize), cell: Cell) {
-        self.mat.insert(idxs, cell);
+    fn core(&self) -> pairwise::Builder<'_> {
+        pairwise::Builder::new(&self.fst, &self.snd, self.linear_gap_penalty)
+            .matrix(self.matrix.clone())
     }
 
-    fn mat_idx(&self, fst_idx: usize, snd_idx: usize) -> isize {
-        self.cell_idx(fst_idx, snd_idx).score()
+    pub fn align(&mut self) {
+        self.mat = self.core().fill(&HashSet::new()).0;
     }
 
-    fn cell_idx(&self, fst_idx: usize, snd_idx: usize) -> &Cell {
-        &self.mat[&(fst_idx, snd_idx)]
+    pub fn maximum_alignment_score(&self) -> isize {
+        self.mat.score(self.fst.len(), self.snd.len())
     }
 
-    fn scoring_matrix(&self, fst_idx: usize, snd_idx: usize) -> isize {
-        let fst_char = self.fst[fst_idx - 1];
-        let snd_char = self.snd[snd_idx - 1];
-        blosum62::blosum62(fst_char, snd_char) as isize
+    pub fn traceback(&self) -> Alignment {
+        let end = (self.fst.len(), self.snd.len());
+        self.core().traceback(&self.mat, end).0
     }
 
-    pub fn align(&mut self) {
-        let indices = iproduct![1..=self.fst.len(), 1..=self.snd.len()];
-        for (fst_idx, snd_idx) in indices {
-            info!(
-                "Determining aligment for cell at pos {}, {}",
-                fst_idx, snd_idx
-            );
-            info!("Indexing top cell at pos {}, {}", fst_idx - 1, snd_idx);
-            let top = self.mat_idx(fst_idx - 1, snd_idx) + self.linear_gap_penalty;
-            info!("Indexing left cell at pos {}, {}", fst_idx, snd_idx - 1);
-            let left = self.mat_idx(fst_idx, snd_idx - 1) + self.linear_gap_penalty;
-            info!(
-                "Indexing top left cell at pos {}, {}",
-                fst_idx - 1,
-                snd_idx - 1
-            );
-            let matching =
-                self.mat_idx(fst_idx - 1, snd_idx - 1) + self.scoring_matrix(fst_idx, snd_idx);
-            let cell_result = Cell::from_cell_max(&[
-                (top, Direction::Top),
-                (left, Direction::Left),
-                (matching, Direction::TopLeft),
-            ]);
-            info!(
-                "Inserting cell with score {} as pos {}, {}",
-                cell_result.score(),
-                fst_idx,
-                snd_idx
-            );
-            self.insert((fst_idx, snd_idx), cell_result);
-        }
+    pub fn count_optimal(&self) -> u128 {
+        self.core().count_optimal()
     }
 
-    pub fn maximum_alignment_score(&self) -> isize {
-        self.mat_idx(self.fst.len(), self.snd.len())
+    /// Every optimal alignment, lazily, starting with the one `traceback`
+    /// returns.
+    pub fn optimal_alignments(&self) -> OptimalAlignments<'_> {
+        self.core().optimal_alignments()
     }
 
-    pub fn traceback(&self) -> (Vec<u8>, Vec<u8>) {
-        let mut pos = (self.fst.len(), self.snd.len());
-        // TODO Use VecDeque instead to add letters in the correct order
-        let mut fst_align = Vec::new();
-        let mut snd_align = Vec::new();
+    /// Significance of the optimal score against alignments of `fst` with
+    /// shuffles of `snd`.
+    pub fn significance(&self, test: &ShuffleTest) -> Significance {
+        self.core().significance(test)
+    }
+}
 
-        while pos.0 != 0 || pos.1 != 0 {
-            match self.cell_idx(pos.0, pos.1).get_any_dir() {
-                Direction::Top => {
-                    fst_align.push(b'-');
-                    snd_align.push(self.snd[pos.1 - 1]);
-                    pos.1 -= 1;
-                }
-                Direction::Left => {
-                    fst_align.push(self.fst[pos.0 - 1]);
-                    snd_align.push(b'-');
-                    pos.0 -= 1;
-                }
-                Direction::TopLeft => {
-                    fst_align.push(self.fst[pos.0 - 1]);
-                    snd_align.push(self.snd[pos.1 - 1]);
-                    pos.0 -= 1;
-                    pos.1 -= 1;
-                }
-            }
-        }
-        (fst_align, snd_align)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_global_alignment() {
+        let mut alignment = GlobalAlignment::new(b"PLEASANTLY".to_vec(), b"MEANLY".to_vec());
+        alignment.align();
+        assert_eq!(8, alignment.maximum_alignment_score());
+        let traceback = alignment.traceback();
+        assert_eq!(8, traceback.score);
+        assert_eq!((0, 10), (traceback.fst_start, traceback.fst_end));
+        assert_eq!((0, 6), (traceback.snd_start, traceback.snd_end));
+        assert_eq!("1D1X1=2D2=1D2=", traceback.cigar());
+
+        let all: Vec<_> = alignment.optimal_alignments().collect();
+        assert_eq!(all.len() as u128, alignment.count_optimal());
+        assert_eq!(traceback, all[0]);
     }
 }
@@ -0,0 +1,239 @@
+use std::fmt::Write;
+
+/// Node of a `GuideTree`. Leaves are indices into the distance matrix the
+/// tree was built from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Leaf(usize),
+    Join {
+        left: usize,
+        right: usize,
+        left_len: f64,
+        right_len: f64,
+    },
+}
+
+/**
+ * Rooted binary tree over a set of sequences, built from their pairwise
+ * distances. Nodes are stored so that children always come before their
+ * parent: the first nodes are the leaves in input order and the last one
+ * is the root, so walking the nodes in order visits every join after the
+ * two subtrees it joins.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct GuideTree {
+    nodes: Vec<Node>,
+}
+
+impl GuideTree {
+    /**
+     * UPGMA: repeatedly joins the two closest clusters, placing the join
+     * at half their distance, with the distance from the new cluster to
+     * the others being the size weighted mean of its parts.
+     */
+    pub fn upgma(dist: &[Vec<f64>]) -> Self {
+        let mut tree = GuideTree::leaves(dist.len());
+        let mut dist = GuideTree::node_distances(dist);
+        let mut sizes = vec![1.0; dist.len()];
+        let mut heights = vec![0.0; dist.len()];
+        let mut active: Vec<usize> = (0..tree.nodes.len()).collect();
+        while active.len() > 1 {
+            let (left, right) = GuideTree::closest(&active, |i, j| dist[i][j]);
+            let height = dist[left][right] / 2.0;
+            let joined = tree.join(left, right, height - heights[left], height - heights[right]);
+            active.retain(|&node| node != left && node != right);
+            for &other in &active {
+                let mean = (dist[left][other] * sizes[left] + dist[right][other] * sizes[right])
+                    / (sizes[left] + sizes[right]);
+                dist[joined][other] = mean;
+                dist[other][joined] = mean;
+            }
+            sizes[joined] = sizes[left] + sizes[right];
+            heights[joined] = height;
+            active.push(joined);
+        }
+        tree
+    }
+
+    /**
+     * Saitou and Nei's neighbor joining, which unlike UPGMA does not
+     * assume the sequences evolve at the same rate. The unrooted result is
+     * rooted halfway along the last edge joined. Negative branch lengths
+     * are clamped to zero.
+     */
+    pub fn neighbor_joining(dist: &[Vec<f64>]) -> Self {
+        let mut tree = GuideTree::leaves(dist.len());
+        let mut dist = GuideTree::node_distances(dist);
+        let mut active: Vec<usize> = (0..tree.nodes.len()).collect();
+        while active.len() > 2 {
+            let others = (active.len() - 2) as f64;
+            let sums: Vec<f64> = (0..dist.len())
+                .map(|node| active.iter().map(|&other| dist[node][other]).sum())
+                .collect();
+            let (left, right) =
+                GuideTree::closest(&active, |i, j| others * dist[i][j] - sums[i] - sums[j]);
+            let left_len = dist[left][right] / 2.0 + (sums[left] - sums[right]) / (2.0 * others);
+            let right_len = dist[left][right] - left_len;
+            let joined = tree.join(left, right, left_len.max(0.0), right_len.max(0.0));
+            active.retain(|&node| node != left && node != right);
+            for &other in &active {
+                let rest = (dist[left][other] + dist[right][other] - dist[left][right]) / 2.0;
+                dist[joined][other] = rest;
+                dist[other][joined] = rest;
+            }
+            active.push(joined);
+        }
+        if let [left, right] = active[..] {
+            let half = dist[left][right] / 2.0;
+            tree.join(left, right, half, half);
+        }
+        tree
+    }
+
+    fn leaves(count: usize) -> Self {
+        let mut nodes = Vec::with_capacity((2 * count).saturating_sub(1));
+        nodes.extend((0..count).map(Node::Leaf));
+        GuideTree { nodes }
+    }
+
+    /// Copy of `dist` with room for the distances to every join.
+    fn node_distances(dist: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        let count = (2 * dist.len()).saturating_sub(1);
+        let mut nodes = vec![vec![0.0; count]; count];
+        for (row, dists) in nodes.iter_mut().zip(dist) {
+            row[..dists.len()].copy_from_slice(dists);
+        }
+        nodes
+    }
+
+    /// Pair of active nodes minimizing `key`, the first one found on ties.
+    fn closest(active: &[usize], key: impl Fn(usize, usize) -> f64) -> (usize, usize) {
+        let mut best = (f64::INFINITY, (active[0], active[1]));
+        for (idx, &i) in active.iter().enumerate() {
+            for &j in &active[idx + 1..] {
+                let value = key(i, j);
+                if value < best.0 {
+                    best = (value, (i, j));
+                }
+            }
+        }
+        best.1
+    }
+
+    fn join(&mut self, left: usize, right: usize, left_len: f64, right_len: f64) -> usize {
+        self.nodes.push(Node::Join {
+            left,
+            right,
+            left_len,
+            right_len,
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Every node, children before parents.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Index of the root node, `None` for a tree over no sequences.
+    pub fn root(&self) -> Option<usize> {
+        self.nodes.len().checked_sub(1)
+    }
+
+    /// Newick representation, with `names` labelling the leaves.
+    pub fn newick<S: AsRef<str>>(&self, names: &[S]) -> String {
+        let mut newick = String::new();
+        if let Some(root) = self.root() {
+            self.write_newick(root, names, &mut newick);
+        }
+        newick.push(';');
+        newick
+    }
+
+    fn write_newick<S: AsRef<str>>(&self, node: usize, names: &[S], out: &mut String) {
+        match self.nodes[node] {
+            Node::Leaf(leaf) => out.push_str(names[leaf].as_ref()),
+            Node::Join {
+                left,
+                right,
+                left_len,
+                right_len,
+            } => {
+                out.push('(');
+                self.write_newick(left, names, out);
+                write!(out, ":{},", left_len).unwrap();
+                self.write_newick(right, names, out);
+                write!(out, ":{})", right_len).unwrap();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 5] = ["a", "b", "c", "d", "e"];
+
+    fn matrix(rows: [[f64; 5]; 5]) -> Vec<Vec<f64>> {
+        rows.iter().map(|row| row.to_vec()).collect()
+    }
+
+    #[test]
+    fn test_upgma() {
+        let dist = matrix([
+            [0.0, 17.0, 21.0, 31.0, 23.0],
+            [17.0, 0.0, 30.0, 34.0, 21.0],
+            [21.0, 30.0, 0.0, 28.0, 39.0],
+            [31.0, 34.0, 28.0, 0.0, 43.0],
+            [23.0, 21.0, 39.0, 43.0, 0.0],
+        ]);
+        let tree = GuideTree::upgma(&dist);
+        assert_eq!(9, tree.nodes().len());
+        assert_eq!(
+            "((e:11,(a:8.5,b:8.5):2.5):5.5,(c:14,d:14):2.5);",
+            tree.newick(&NAMES)
+        );
+    }
+
+    #[test]
+    fn test_neighbor_joining() {
+        let dist = matrix([
+            [0.0, 5.0, 9.0, 9.0, 8.0],
+            [5.0, 0.0, 10.0, 10.0, 9.0],
+            [9.0, 10.0, 0.0, 8.0, 7.0],
+            [9.0, 10.0, 8.0, 0.0, 3.0],
+            [8.0, 9.0, 7.0, 3.0, 0.0],
+        ]);
+        let tree = GuideTree::neighbor_joining(&dist);
+        let lens: Vec<f64> = (0..5)
+            .map(|leaf| {
+                tree.nodes()
+                    .iter()
+                    .find_map(|node| match *node {
+                        Node::Join { left, left_len, .. } if left == leaf => Some(left_len),
+                        Node::Join {
+                            right, right_len, ..
+                        } if right == leaf => Some(right_len),
+                        _ => None,
+                    })
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(vec![2.0, 3.0, 4.0, 2.0, 1.0], lens);
+        assert_eq!(Some(8), tree.root());
+    }
+
+    #[test]
+    fn test_small_trees() {
+        assert_eq!(None, GuideTree::upgma(&[]).root());
+        assert_eq!(";", GuideTree::neighbor_joining(&[]).newick::<&str>(&[]));
+        assert_eq!("a;", GuideTree::upgma(&[vec![0.0]]).newick(&NAMES));
+        let pair = vec![vec![0.0, 0.5], vec![0.5, 0.0]];
+        assert_eq!("(a:0.25,b:0.25);", GuideTree::upgma(&pair).newick(&NAMES));
+        assert_eq!(
+            "(a:0.25,b:0.25);",
+            GuideTree::neighbor_joining(&pair).newick(&NAMES)
+        );
+    }
+}
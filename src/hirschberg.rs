@@ -0,0 +1,288 @@
+use std::iter;
+
+use crate::alignment::{Alignment, Operation};
+use crate::pairwise::{self, NEG_INF};
+use crate::substitution::SubstitutionMatrix;
+
+/**
+ * Global alignment in linear space (Myers-Miller, Hirschberg's divide and
+ * conquer extended to affine gaps). Scores match `pairwise::Builder` in
+ * `Mode::Global` with the same penalties, at roughly twice the time but
+ * with memory linear in the length of the sequences.
+ */
+pub struct Builder<'a> {
+    fst: &'a [u8],
+    snd: &'a [u8],
+    linear_gap_penalty: isize,
+    gap_open: isize,
+    matrix: SubstitutionMatrix,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(fst: &'a [u8], snd: &'a [u8], linear_gap_penalty: isize) -> Self {
+        Builder {
+            fst,
+            snd,
+            linear_gap_penalty,
+            gap_open: 0,
+            matrix: SubstitutionMatrix::blosum62(),
+        }
+    }
+
+    /// Extra penalty charged once for every gap, on top of the per-residue
+    /// `linear_gap_penalty`.
+    pub fn gap_open(mut self, penalty: isize) -> Self {
+        self.gap_open = penalty;
+        self
+    }
+
+    pub fn matrix(mut self, matrix: SubstitutionMatrix) -> Self {
+        self.matrix = matrix;
+        self
+    }
+
+    pub fn align(&self) -> Alignment {
+        let mut ops = Vec::with_capacity(self.fst.len() + self.snd.len());
+        self.diff(self.fst, self.snd, self.gap_open, self.gap_open, &mut ops);
+        let mut alignment = Alignment::new(0, (0, 0), ops, (self.fst.len(), self.snd.len()));
+        alignment.score = pairwise::alignment_score(
+            &alignment,
+            self.fst,
+            self.snd,
+            &self.matrix,
+            self.linear_gap_penalty,
+            self.gap_open,
+        );
+        alignment
+    }
+
+    fn gap(&self, len: usize) -> isize {
+        if len == 0 {
+            0
+        } else {
+            self.gap_open + len as isize * self.linear_gap_penalty
+        }
+    }
+
+    /**
+     * Appends the operations of an optimal alignment of `fst` and `snd`.
+     * `open_before` and `open_after` are the opening penalties for a gap in
+     * `snd` (a run of deletions) touching the start or the end: zero when
+     * that gap continues one the caller already opened.
+     */
+    fn diff(
+        &self,
+        fst: &[u8],
+        snd: &[u8],
+        open_before: isize,
+        open_after: isize,
+        ops: &mut Vec<Operation>,
+    ) {
+        let (fst_len, snd_len) = (fst.len(), snd.len());
+        if snd_len == 0 {
+            ops.extend(iter::repeat_n(Operation::Deletion, fst_len));
+        } else if fst_len == 0 {
+            ops.extend(iter::repeat_n(Operation::Insertion, snd_len));
+        } else if fst_len == 1 {
+            self.diff_single(fst[0], snd, open_before, open_after, ops);
+        } else {
+            let fst_mid = fst_len / 2;
+            let (snd_mid, through_gap) = self.midpoint(fst, snd, open_before, open_after);
+            if through_gap {
+                // The gap crossing the middle row takes `fst[fst_mid - 1]`
+                // and `fst[fst_mid]`, and is opened exactly once.
+                self.diff(&fst[..fst_mid - 1], &snd[..snd_mid], open_before, 0, ops);
+                ops.extend([Operation::Deletion, Operation::Deletion]);
+                self.diff(&fst[fst_mid + 1..], &snd[snd_mid..], 0, open_after, ops);
+            } else {
+                let fst_halves = fst.split_at(fst_mid);
+                let snd_halves = snd.split_at(snd_mid);
+                self.diff(fst_halves.0, snd_halves.0, open_before, self.gap_open, ops);
+                self.diff(fst_halves.1, snd_halves.1, self.gap_open, open_after, ops);
+            }
+        }
+    }
+
+    /// Base case of `diff` with a single residue left in `fst`: it is either
+    /// matched with one residue of `snd` or deleted next to an insertion of
+    /// all of `snd`.
+    fn diff_single(
+        &self,
+        residue: u8,
+        snd: &[u8],
+        open_before: isize,
+        open_after: isize,
+        ops: &mut Vec<Operation>,
+    ) {
+        let snd_len = snd.len();
+        let mut best = open_before.max(open_after) + self.linear_gap_penalty + self.gap(snd_len);
+        let mut best_idx = None;
+        for (idx, &ch) in snd.iter().enumerate() {
+            let score =
+                self.gap(idx) + self.matrix.score(residue, ch) + self.gap(snd_len - idx - 1);
+            if score > best {
+                best = score;
+                best_idx = Some(idx);
+            }
+        }
+        match best_idx {
+            Some(idx) => {
+                ops.extend(iter::repeat_n(Operation::Insertion, idx));
+                ops.push(Operation::pair(residue, snd[idx]));
+                ops.extend(iter::repeat_n(Operation::Insertion, snd_len - idx - 1));
+            }
+            None if open_after > open_before => {
+                ops.extend(iter::repeat_n(Operation::Insertion, snd_len));
+                ops.push(Operation::Deletion);
+            }
+            None => {
+                ops.push(Operation::Deletion);
+                ops.extend(iter::repeat_n(Operation::Insertion, snd_len));
+            }
+        }
+    }
+
+    /**
+     * Column where an optimal path crosses the middle row of `fst`, and
+     * whether it crosses inside a gap in `snd` rather than between two
+     * ops. Scores the top half forwards and the bottom half backwards,
+     * keeping one row of each.
+     */
+    fn midpoint(
+        &self,
+        fst: &[u8],
+        snd: &[u8],
+        open_before: isize,
+        open_after: isize,
+    ) -> (usize, bool) {
+        let fst_mid = fst.len() / 2;
+        let (scores, gaps) = self.last_row(&fst[..fst_mid], snd, open_before);
+        let fst_rev: Vec<u8> = fst[fst_mid..].iter().rev().copied().collect();
+        let snd_rev: Vec<u8> = snd.iter().rev().copied().collect();
+        let (rev_scores, rev_gaps) = self.last_row(&fst_rev, &snd_rev, open_after);
+
+        let snd_len = snd.len();
+        let mut best = scores[0] + rev_scores[snd_len];
+        let mut mid = (0, false);
+        for idx in 0..=snd_len {
+            let score = scores[idx] + rev_scores[snd_len - idx];
+            if score > best {
+                best = score;
+                mid = (idx, false);
+            }
+        }
+        for idx in 0..=snd_len {
+            let score = gaps[idx] + rev_gaps[snd_len - idx] - self.gap_open;
+            if score > best {
+                best = score;
+                mid = (idx, true);
+            }
+        }
+        mid
+    }
+
+    /**
+     * Last row of the affine DP for `fst` against `snd`: the best score of
+     * each prefix of `snd`, and the best score among paths ending in a gap
+     * in `snd`. A gap down the first column is opened with `open_before`.
+     */
+    fn last_row(&self, fst: &[u8], snd: &[u8], open_before: isize) -> (Vec<isize>, Vec<isize>) {
+        let snd_len = snd.len();
+        let mut scores: Vec<isize> = (0..=snd_len).map(|idx| self.gap(idx)).collect();
+        let mut gaps = vec![NEG_INF; snd_len + 1];
+        let open = self.gap_open + self.linear_gap_penalty;
+        for (fst_idx, &a) in fst.iter().enumerate() {
+            let mut diagonal = scores[0];
+            scores[0] = open_before + (fst_idx + 1) as isize * self.linear_gap_penalty;
+            gaps[0] = scores[0];
+            let mut left = scores[0];
+            let mut top_gap = NEG_INF;
+            for (snd_idx, &b) in snd.iter().enumerate() {
+                let idx = snd_idx + 1;
+                top_gap = (top_gap + self.linear_gap_penalty).max(left + open);
+                gaps[idx] = (gaps[idx] + self.linear_gap_penalty).max(scores[idx] + open);
+                left = (diagonal + self.matrix.score(a, b))
+                    .max(top_gap)
+                    .max(gaps[idx]);
+                diagonal = scores[idx];
+                scores[idx] = left;
+            }
+        }
+        (scores, gaps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, check_alignment, random_dna};
+    use seq_io::fasta::Reader;
+    use std::fs::File;
+
+    fn quadratic(
+        fst: &[u8],
+        snd: &[u8],
+        matrix: &SubstitutionMatrix,
+        gap: isize,
+        gap_open: isize,
+    ) -> isize {
+        pairwise::Builder::new(fst, snd, gap)
+            .matrix(matrix.clone())
+            .gap_open(gap_open)
+            .align()
+            .score
+    }
+
+    #[test]
+    fn test_rosalind_sample() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/extra/global_alignment_test.txt"
+        );
+        let mut reader = Reader::new(File::open(path).unwrap());
+        let seqs: Vec<Vec<u8>> = reader.records().map(|rec| rec.unwrap().seq).collect();
+        let (fst, snd) = (&seqs[0], &seqs[1]);
+
+        let expected = pairwise::Builder::new(fst, snd, -5).align();
+        let alignment = Builder::new(fst, snd, -5).align();
+        assert_eq!(8, alignment.score);
+        assert_eq!(expected.ops, alignment.ops);
+        check_alignment(fst, snd, &alignment);
+    }
+
+    #[test]
+    fn test_matches_quadratic() {
+        let mut rng = testing::rng(11);
+        let matrix = SubstitutionMatrix::dna(2, -3);
+        for (fst_len, snd_len) in [(0, 5), (1, 7), (7, 1), (2, 9), (30, 22), (41, 60)] {
+            for gap_open in [0, -6] {
+                let fst = random_dna(&mut rng, fst_len);
+                let snd = random_dna(&mut rng, snd_len);
+                let alignment = Builder::new(&fst, &snd, -4)
+                    .matrix(matrix.clone())
+                    .gap_open(gap_open)
+                    .align();
+                assert_eq!(
+                    quadratic(&fst, &snd, &matrix, -4, gap_open),
+                    alignment.score
+                );
+                check_alignment(&fst, &snd, &alignment);
+            }
+        }
+    }
+
+    #[test]
+    fn test_long_gap() {
+        let fst = b"MEANLYPRTEINSTRINGWWWWWWWWWWPLEASANTLY";
+        let snd = b"MEANLYPRTEINSTRINGPLEASANTLY";
+        let matrix = SubstitutionMatrix::blosum62();
+        let alignment = Builder::new(fst, snd, -1).gap_open(-11).align();
+        assert_eq!(quadratic(fst, snd, &matrix, -1, -11), alignment.score);
+        assert_eq!(10, alignment.deletions());
+        assert_eq!(1, alignment.gap_opens());
+        assert_eq!(
+            alignment.score,
+            pairwise::alignment_score(&alignment, fst, snd, &matrix, -1, -11)
+        );
+    }
+}
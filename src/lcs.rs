@@ -1,7 +1,9 @@
 use itertools::{iproduct, Itertools};
-use ndarray::{Array2, ArrayD, IntoDimension};
+use ndarray::Array2;
 use std::collections::HashMap;
 
+use crate::suffix_automaton;
+
 pub fn lc_suffix<'a>(xs: &'a [u8], ys: &'a [u8]) -> &'a [u8] {
     let mut lcs_idx = 0;
     let mut lcs_length = 0;
@@ -21,41 +23,10 @@ pub fn lc_suffix<'a>(xs: &'a [u8], ys: &'a [u8]) -> &'a [u8] {
     &xs[lcs_idx - lcs_length..lcs_idx]
 }
 
+/// Longest substring common to every one of `xss`, the first one in
+/// `xss[0]` on ties.
 pub fn kcommon<'a>(xss: &'a [&'a [u8]]) -> &'a [u8] {
-    let mut lcs_idx = 0;
-    let mut lcs_length = 0;
-    let mut matrix =
-        ArrayD::<usize>::zeros(xss.iter().map(|xs| xs.len() + 1).collect::<Vec<usize>>());
-    let indices: Vec<Vec<usize>> = xss
-        .iter()
-        .map(|xs| 1..=xs.len())
-        .multi_cartesian_product()
-        .collect();
-
-    for index in indices {
-        let mut iter = xss
-            .iter()
-            .zip(index.iter())
-            .map(|(xs, &idx)| unsafe { xs.get_unchecked(idx - 1) });
-        let init = iter.next().unwrap();
-        let dim = index.clone().into_dimension();
-
-        if iter.all(|x| x == init) {
-            let prev = index
-                .iter()
-                .map(|x| x - 1)
-                .collect::<Vec<usize>>()
-                .into_dimension();
-            matrix[&dim] = 1 + matrix[prev];
-        }
-
-        if matrix[&dim] > lcs_length {
-            lcs_length = matrix[dim];
-            lcs_idx = index[0];
-        }
-    }
-
-    &xss[0][lcs_idx - lcs_length..lcs_idx]
+    suffix_automaton::longest_common_substring(xss)
 }
 
 pub fn kcommon_ht<'a>(xss: &'a [&'a [u8]]) -> &'a [u8] {
@@ -93,6 +64,7 @@ pub fn kcommon_ht<'a>(xss: &'a [&'a [u8]]) -> &'a [u8] {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{self, random_dna};
 
     #[test]
     fn test_lcs() {
@@ -131,4 +103,21 @@ mod tests {
         let answer = kcommon_ht(strs);
         assert_eq!(b"ABC", answer);
     }
+
+    #[test]
+    fn test_kcommon_agrees_with_ht() {
+        let mut rng = testing::rng(12);
+        for len in [1, 4, 8, 12] {
+            for _ in 0..10 {
+                let seqs: Vec<Vec<u8>> = (0..3).map(|_| random_dna(&mut rng, len)).collect();
+                let seqs: Vec<&[u8]> = seqs.iter().map(Vec::as_slice).collect();
+                let expected = kcommon_ht(&seqs);
+                let answer = kcommon(&seqs);
+                assert_eq!(expected, answer);
+                if !answer.is_empty() {
+                    assert_eq!(expected.as_ptr(), answer.as_ptr());
+                }
+            }
+        }
+    }
 }
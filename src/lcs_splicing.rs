@@ -1,66 +1,322 @@
-use itertools::Itertools;
 use std::collections::HashMap;
 use std::collections::HashSet;
 
+use itertools::{iproduct, Itertools};
+
+use crate::dp::{Direction, Directions, DpMatrix};
+
 fn empty_hset() -> HashSet<String> {
     let mut hset = HashSet::new();
     hset.insert(String::new());
     hset
 }
 
-pub fn longest_common_subsequence(fst: &[u8], snd: &[u8]) -> HashSet<String> {
-    let mut indices = Vec::new();
+/// Fills the LCS length table. A matching pair only ever extends the
+/// diagonal; otherwise every neighbour with the longer length is kept.
+fn lcs_table(fst: &[u8], snd: &[u8]) -> DpMatrix<usize> {
+    let mut table = DpMatrix::new(fst.len(), snd.len());
+    for (i, j) in iproduct![1..=fst.len(), 1..=snd.len()] {
+        if fst[i - 1] == snd[j - 1] {
+            let len = table.score(i - 1, j - 1) + 1;
+            table.set(i, j, len, Directions::only(Direction::TopLeft));
+        } else {
+            let (len, dirs) = Directions::max_of(&[
+                (table.score(i - 1, j), Direction::Left),
+                (table.score(i, j - 1), Direction::Top),
+            ]);
+            table.set(i, j, len, dirs);
+        }
+    }
+    table
+}
+
+/// Every optimal subsequence of `fst[..i]` and `snd[..j]`, following all
+/// the recorded moves back from `(i, j)`.
+fn collect(
+    fst: &[u8],
+    table: &DpMatrix<usize>,
+    (i, j): (usize, usize),
+    memo: &mut HashMap<(usize, usize), HashSet<String>>,
+) -> HashSet<String> {
+    if i == 0 || j == 0 {
+        return empty_hset();
+    }
+    if let Some(found) = memo.get(&(i, j)) {
+        return found.clone();
+    }
+    let mut acc = HashSet::new();
+    for dir in table.dirs(i, j).iter() {
+        match dir {
+            Direction::TopLeft => acc.extend(
+                collect(fst, table, (i - 1, j - 1), memo)
+                    .into_iter()
+                    .map(|mut pat| {
+                        pat.push(fst[i - 1] as char);
+                        pat
+                    }),
+            ),
+            Direction::Left => acc.extend(collect(fst, table, (i - 1, j), memo)),
+            Direction::Top => acc.extend(collect(fst, table, (i, j - 1), memo)),
+        }
+    }
+    memo.insert((i, j), acc.clone());
+    acc
+}
 
-    for i in 1..=fst.len() {
-        for j in 1..=snd.len() {
-            indices.push((i, j));
+/**
+ * One longest common subsequence of `fst` and `snd`, traced back through
+ * the full table from its last cell. Memory is quadratic, so for long
+ * inputs use `linear_space`, or `hunt_szymanski` when few positions match.
+ */
+pub fn longest_common_subsequence(fst: &[u8], snd: &[u8]) -> Lcs {
+    let table = lcs_table(fst, snd);
+    let mut pairs = Vec::with_capacity(table.score(fst.len(), snd.len()));
+    let (mut i, mut j) = (fst.len(), snd.len());
+    while i > 0 && j > 0 {
+        match table.dirs(i, j).first() {
+            Some(Direction::TopLeft) => {
+                pairs.push((i - 1, j - 1));
+                i -= 1;
+                j -= 1;
+            }
+            Some(Direction::Left) => i -= 1,
+            Some(Direction::Top) => j -= 1,
+            None => unreachable!(),
         }
     }
+    Lcs::from_pairs(fst, pairs.into_iter().rev())
+}
 
-    let mut lcs_hmap: HashMap<(usize, usize), HashSet<String>> = HashMap::new();
-    for (i, j) in indices {
-        if i == 0 || j == 0 {
-            lcs_hmap.insert((i, j), empty_hset());
-        } else if fst[i - 1] == snd[j - 1] {
-            let prev = match lcs_hmap.get(&(i - 1, j - 1)) {
-                Some(s) => s.clone(),
-                None => empty_hset(),
-            };
-            let prev = prev
-                .iter()
-                .cloned()
-                .map(|mut pat| {
-                    pat.push(fst[i - 1] as char);
-                    pat
-                })
-                .collect();
-            lcs_hmap.insert((i, j), prev);
-        } else {
-            let left = lcs_hmap
-                .get(&(i - 1, j))
-                .map_or(empty_hset(), |s| s.clone());
-            let right = lcs_hmap
-                .get(&(i, j - 1))
-                .map_or(empty_hset(), |s| s.clone());
-            let longer = left
-                .into_iter()
-                .chain(right.into_iter())
-                .sorted_by_key(|v| v.len());
-            let longer = longer.into_iter().group_by(|v| v.len());
-            let longer = longer.into_iter().last().unwrap();
-            lcs_hmap.insert((i, j), longer.1.collect());
+/// Every distinct longest common subsequence of `fst` and `snd`. There can
+/// be exponentially many, so this is only practical for short inputs.
+pub fn all_longest_common_subsequences(fst: &[u8], snd: &[u8]) -> HashSet<String> {
+    let table = lcs_table(fst, snd);
+    collect(fst, &table, (fst.len(), snd.len()), &mut HashMap::new())
+}
+
+/// One longest common subsequence, with the positions it is taken from in
+/// both sequences.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Lcs {
+    pub seq: Vec<u8>,
+    pub fst_indices: Vec<usize>,
+    pub snd_indices: Vec<usize>,
+}
+
+impl Lcs {
+    fn from_pairs(fst: &[u8], pairs: impl Iterator<Item = (usize, usize)>) -> Self {
+        let mut lcs = Lcs::default();
+        for (i, j) in pairs {
+            lcs.seq.push(fst[i]);
+            lcs.fst_indices.push(i);
+            lcs.snd_indices.push(j);
+        }
+        lcs
+    }
+
+    /// The subsequence as text.
+    pub fn as_string(&self) -> String {
+        String::from_utf8_lossy(&self.seq).into_owned()
+    }
+
+    /// Positions in `fst` as Rosalind's SSEQ problem prints them: 1-based
+    /// and separated by spaces.
+    pub fn fst_sseq(&self) -> String {
+        sseq(&self.fst_indices)
+    }
+
+    /// Positions in `snd`, formatted like `fst_sseq`.
+    pub fn snd_sseq(&self) -> String {
+        sseq(&self.snd_indices)
+    }
+}
+
+fn sseq(indices: &[usize]) -> String {
+    indices.iter().map(|idx| idx + 1).join(" ")
+}
+
+/**
+ * Hunt-Szymanski: only the `r` pairs of matching positions are visited,
+ * keeping for every length `k` the smallest position of `snd` that ends a
+ * common subsequence of length `k` so far, which takes O((r + n) log n).
+ * Fast when few positions match, as with proteins; on DNA, where a
+ * quarter of all pairs match, `linear_space` is the better choice.
+ *
+ * Of all the LCSs, this returns the one ending earliest in `snd`, then in
+ * `fst`, and recursively so for the rest of it.
+ */
+pub fn hunt_szymanski(fst: &[u8], snd: &[u8]) -> Lcs {
+    let mut positions = vec![Vec::new(); 256];
+    for (j, &ch) in snd.iter().enumerate().rev() {
+        positions[ch as usize].push(j);
+    }
+    // `thresholds[k]` is the smallest end in `snd` of a common subsequence
+    // of length `k + 1`, and `ends[k]` the last match of that subsequence,
+    // as an index into `matches`.
+    let mut thresholds: Vec<usize> = Vec::new();
+    let mut ends: Vec<usize> = Vec::new();
+    let mut matches: Vec<(usize, usize, Option<usize>)> = Vec::new();
+    for (i, &ch) in fst.iter().enumerate() {
+        // Going down `snd` keeps matches in the same row from extending
+        // each other.
+        for &j in &positions[ch as usize] {
+            let len = thresholds.partition_point(|&end| end < j);
+            if thresholds.get(len) == Some(&j) {
+                continue;
+            }
+            matches.push((i, j, len.checked_sub(1).map(|prev| ends[prev])));
+            if len == thresholds.len() {
+                thresholds.push(j);
+                ends.push(matches.len() - 1);
+            } else {
+                thresholds[len] = j;
+                ends[len] = matches.len() - 1;
+            }
+        }
+    }
+
+    let mut pairs = Vec::with_capacity(ends.len());
+    let mut curr = ends.last().copied();
+    while let Some(idx) = curr {
+        let (i, j, prev) = matches[idx];
+        pairs.push((i, j));
+        curr = prev;
+    }
+    Lcs::from_pairs(fst, pairs.into_iter().rev())
+}
+
+/**
+ * Bit-parallel LCS lengths (Allison-Dix, in Hyyrö's formulation). Bit `i`
+ * of the result is clear when `fst[..=i]` has a longer LCS with `snd` than
+ * `fst[..i]`, so each residue of `snd` costs a few word operations per 64
+ * residues of `fst`.
+ */
+fn lcs_bits(fst: &[u8], snd: &[u8]) -> Vec<u64> {
+    let blocks = fst.len().div_ceil(64);
+    let mut peq = vec![0u64; 256 * blocks];
+    for (idx, &ch) in fst.iter().enumerate() {
+        peq[ch as usize * blocks + idx / 64] |= 1 << (idx % 64);
+    }
+    let mut bits = vec![!0u64; blocks];
+    for &ch in snd {
+        let eqs = &peq[ch as usize * blocks..][..blocks];
+        let mut carry = false;
+        for (word, &eq) in bits.iter_mut().zip(eqs) {
+            let matched = *word & eq;
+            let (sum, overflow) = word.overflowing_add(matched);
+            let (sum, carried) = sum.overflowing_add(carry as u64);
+            carry = overflow || carried;
+            *word = sum | (*word & !matched);
+        }
+    }
+    bits
+}
+
+/// Length of the LCS of `fst` and `snd`, computed bit-parallel.
+pub fn lcs_length(fst: &[u8], snd: &[u8]) -> usize {
+    let ones: usize = lcs_bits(fst, snd)
+        .iter()
+        .map(|word| word.count_ones() as usize)
+        .sum();
+    fst.len().div_ceil(64) * 64 - ones
+}
+
+/// LCS length of every prefix of `fst` with `snd`.
+fn prefix_lengths(fst: &[u8], snd: &[u8]) -> Vec<usize> {
+    let bits = lcs_bits(fst, snd);
+    let mut lens = Vec::with_capacity(fst.len() + 1);
+    lens.push(0);
+    for idx in 0..fst.len() {
+        let grows = bits[idx / 64] & (1 << (idx % 64)) == 0;
+        lens.push(lens[idx] + grows as usize);
+    }
+    lens
+}
+
+/**
+ * LCS in linear space, by Hirschberg's divide and conquer: `snd` is split
+ * in half, the bit-parallel lengths of the two halves against every
+ * prefix and suffix of `fst` give the best place to split `fst`, and each
+ * side is solved on its own.
+ */
+pub fn linear_space(fst: &[u8], snd: &[u8]) -> Lcs {
+    let mut pairs = Vec::new();
+    split_lcs(fst, snd, (0, 0), &mut pairs);
+    Lcs::from_pairs(fst, pairs.into_iter())
+}
+
+fn split_lcs(fst: &[u8], snd: &[u8], offsets: (usize, usize), pairs: &mut Vec<(usize, usize)>) {
+    if fst.is_empty() || snd.is_empty() {
+        return;
+    }
+    if let [ch] = snd {
+        if let Some(i) = fst.iter().position(|x| x == ch) {
+            pairs.push((offsets.0 + i, offsets.1));
         }
+        return;
     }
-    lcs_hmap[&(fst.len(), snd.len())].iter().cloned().collect()
+    let mid = snd.len() / 2;
+    let before = prefix_lengths(fst, &snd[..mid]);
+    let rev_fst: Vec<u8> = fst.iter().rev().copied().collect();
+    let rev_snd: Vec<u8> = snd[mid..].iter().rev().copied().collect();
+    let after = prefix_lengths(&rev_fst, &rev_snd);
+    let split = (0..=fst.len())
+        .max_by_key(|&i| (before[i] + after[fst.len() - i], std::cmp::Reverse(i)))
+        .unwrap();
+    split_lcs(&fst[..split], &snd[..mid], offsets, pairs);
+    let offsets = (offsets.0 + split, offsets.1 + mid);
+    split_lcs(&fst[split..], &snd[mid..], offsets, pairs);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lcs_splicing_tb;
+    use crate::splicing;
+    use crate::testing::{self, random_dna};
+
+    fn check_lcs(fst: &[u8], snd: &[u8], lcs: &Lcs) {
+        let len = lcs_table(fst, snd).score(fst.len(), snd.len());
+        assert_eq!(len, lcs.seq.len());
+        assert_eq!(len, lcs.fst_indices.len());
+        assert_eq!(len, lcs.snd_indices.len());
+        assert!(lcs.fst_indices.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(lcs.snd_indices.windows(2).all(|pair| pair[0] < pair[1]));
+        for (idx, &ch) in lcs.seq.iter().enumerate() {
+            assert_eq!(ch, fst[lcs.fst_indices[idx]]);
+            assert_eq!(ch, snd[lcs.snd_indices[idx]]);
+        }
+    }
+
+    fn is_subsequence(sub: &[u8], seq: &[u8]) -> bool {
+        let mut rest = seq.iter();
+        sub.iter().all(|ch| rest.any(|x| x == ch))
+    }
+
+    /// Every longest subsequence of `fst` that is also one of `snd`, by
+    /// trying all the subsequences of `fst`.
+    fn brute_force(fst: &[u8], snd: &[u8]) -> HashSet<String> {
+        let common: Vec<Vec<u8>> = (0..1u32 << fst.len())
+            .map(|mask| {
+                let picked = fst
+                    .iter()
+                    .enumerate()
+                    .filter(|&(idx, _)| mask & (1 << idx) != 0);
+                picked.map(|(_, &ch)| ch).collect::<Vec<u8>>()
+            })
+            .filter(|sub| is_subsequence(sub, snd))
+            .collect();
+        let len = common.iter().map(Vec::len).max().unwrap();
+        common
+            .into_iter()
+            .filter(|sub| sub.len() == len)
+            .map(|sub| String::from_utf8(sub).unwrap())
+            .collect()
+    }
 
     #[test]
     fn test_longest_common_subsequence() {
-        let actual = longest_common_subsequence(b"AGCAT", b"GAC");
+        let actual = all_longest_common_subsequences(b"AGCAT", b"GAC");
         assert_eq!(
             ["AC".to_owned(), "GC".to_owned(), "GA".to_owned()]
                 .iter()
@@ -68,5 +324,78 @@ mod tests {
                 .collect::<HashSet<String>>(),
             actual
         );
+
+        let lcs = longest_common_subsequence(b"AACCTTGG", b"ACACTGTGA");
+        check_lcs(b"AACCTTGG", b"ACACTGTGA", &lcs);
+        assert_eq!("ACCTGG", lcs.as_string());
+        assert_eq!("2 3 4 6 7 8", lcs.fst_sseq());
+        assert_eq!("1 2 4 5 6 8", lcs.snd_sseq());
+        // The last residues of both sequences used to be skipped.
+        assert_eq!("GT", longest_common_subsequence(b"AGT", b"CGT").as_string());
+        assert_eq!(Lcs::default(), longest_common_subsequence(b"", b"A"));
+    }
+
+    #[test]
+    fn test_paths_agree() {
+        let mut rng = testing::rng(5);
+        for len in 0..=10 {
+            for _ in 0..8 {
+                let fst = random_dna(&mut rng, len);
+                let snd = random_dna(&mut rng, 10 - len / 2);
+                let expected = brute_force(&fst, &snd);
+                let longest = expected.iter().next().unwrap().len();
+                assert_eq!(expected, all_longest_common_subsequences(&fst, &snd));
+                let single = [
+                    longest_common_subsequence(&fst, &snd),
+                    hunt_szymanski(&fst, &snd),
+                    linear_space(&fst, &snd),
+                ];
+                for lcs in &single {
+                    check_lcs(&fst, &snd, lcs);
+                    assert!(expected.contains(&lcs.as_string()));
+                }
+                let tbm = lcs_splicing_tb::longest_common_subsequence(&fst, &snd);
+                let others = [
+                    lcs_splicing_tb::trace(&fst, &snd, &tbm).remove(0),
+                    splicing::shared_spliced_motif(&fst, &snd),
+                ];
+                for sub in &others {
+                    assert!(is_subsequence(sub.as_bytes(), &fst));
+                    assert!(is_subsequence(sub.as_bytes(), &snd));
+                    assert_eq!(longest, sub.len());
+                }
+            }
+        }
+
+        // The traceback used to skip the last residues of both sequences.
+        let tbm = lcs_splicing_tb::longest_common_subsequence(b"AGT", b"CGT");
+        assert_eq!(vec!["GT"], lcs_splicing_tb::trace(b"AGT", b"CGT", &tbm));
+        assert_eq!("AG", splicing::shared_spliced_motif(b"ACG", b"ATG"));
+    }
+
+    #[test]
+    fn test_lcs_routines() {
+        let expected = Lcs {
+            seq: b"GA".to_vec(),
+            fst_indices: vec![1, 3],
+            snd_indices: vec![0, 1],
+        };
+        assert_eq!(expected, hunt_szymanski(b"AGCAT", b"GAC"));
+        check_lcs(b"AGCAT", b"GAC", &linear_space(b"AGCAT", b"GAC"));
+        assert_eq!(2, lcs_length(b"AGCAT", b"GAC"));
+        assert_eq!(Lcs::default(), hunt_szymanski(b"", b"GAC"));
+        assert_eq!(Lcs::default(), linear_space(b"AAA", b"CC"));
+        assert_eq!(0, lcs_length(b"AGCAT", b""));
+
+        let mut rng = testing::rng(17);
+        for (fst_len, snd_len) in [(1, 1), (10, 7), (63, 64), (64, 65), (150, 130), (300, 20)] {
+            let fst = random_dna(&mut rng, fst_len);
+            let snd = random_dna(&mut rng, snd_len);
+            check_lcs(&fst, &snd, &hunt_szymanski(&fst, &snd));
+            check_lcs(&fst, &snd, &linear_space(&fst, &snd));
+            let len = lcs_table(&fst, &snd).score(fst_len, snd_len);
+            assert_eq!(len, lcs_length(&fst, &snd));
+            assert_eq!(len, lcs_length(&snd, &fst));
+        }
     }
 }
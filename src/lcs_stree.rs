@@ -135,15 +135,10 @@ impl SuffixTree {
             .externals(Outgoing)
             .flat_map(|nidx| self.tree.neighbors_directed(nidx, Incoming))
             .filter_map(|nidx| {
-                if let Some(eref) = self
-                    .tree
+                self.tree
                     .edges_directed(nidx, Outgoing)
                     .find(|eref| eref.weight().contains("$0") && eref.weight().len() > 2)
-                {
-                    Some((nidx, eref.id()))
-                } else {
-                    None
-                }
+                    .map(|eref| (nidx, eref.id()))
             })
             .collect()
     }
@@ -233,11 +228,7 @@ enum Match {
 
 impl PartialOrd for Match {
     fn partial_cmp(&self, other: &Match) -> Option<Ordering> {
-        match (*self, *other) {
-            (Match::Full, _) | (_, Match::None) => Some(Ordering::Greater),
-            (_, Match::Full) | (Match::None, _) => Some(Ordering::Less),
-            (Match::Partial(ref x), Match::Partial(ref y)) => x.partial_cmp(y),
-        }
+        Some(self.cmp(other))
     }
 }
 
@@ -264,7 +255,7 @@ fn match_idx(xs: &str, ys: &str) -> Match {
     }
 }
 
-pub fn suffixes(text: &str) -> Suffixes {
+pub fn suffixes(text: &str) -> Suffixes<'_> {
     Suffixes { text, len: 0 }
 }
 
@@ -1,16 +1,37 @@
+pub mod alignment;
+pub mod assembly;
+pub mod banded;
 pub mod burrows_wheeler;
+pub mod de_bruijn;
+pub mod dp;
 pub mod edit;
 pub mod glob;
+pub mod guide_tree;
+pub mod hirschberg;
 pub mod lcs;
 pub mod lcs_splicing;
 pub mod lcs_splicing_tb;
 pub mod lcs_stree;
 pub mod local;
+pub mod motif_search;
+pub mod msa;
+pub mod myers;
 pub mod orf;
+pub mod pairwise;
 pub mod perfect_matchings_rna;
+pub mod profile_hmm;
+pub mod pwm;
+pub mod shuffle;
 pub mod splicing;
+pub mod stats;
+pub mod striped;
+pub mod substitution;
 pub mod suffix_array;
+pub mod suffix_automaton;
 pub mod suffix_tree;
+pub mod supersequence;
+#[cfg(test)]
+mod testing;
 pub mod trie;
 pub mod utils;
 pub mod consensus_profile;
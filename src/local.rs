@@ -1,84 +1,15 @@
-use std::collections::HashMap;
 use std::collections::HashSet;
 
-use bio::scores::pam250;
-use itertools::iproduct;
-use log::info;
-
-#[derive(Hash, Eq, PartialEq, Clone, Copy)]
-enum Direction {
-    Top,
-    TopLeft,
-    Left,
-}
-
-impl Direction {
-    fn top() -> HashSet<Direction> {
-        let mut acc = HashSet::new();
-        acc.insert(Direction::Top);
-        acc
-    }
-
-    fn left() -> HashSet<Direction> {
-        let mut acc = HashSet::new();
-        acc.insert(Direction::Left);
-        acc
-    }
-}
-
-struct Cell {
-    score: isize,
-    dirs: HashSet<Direction>,
-}
-
-impl Cell {
-    fn new(score: isize) -> Self {
-        let dirs = HashSet::new();
-        Cell { score, dirs }
-    }
-
-    fn new_with_dir(score: isize, dirs: HashSet<Direction>) -> Self {
-        Cell { score, dirs }
-    }
-
-    fn score(&self) -> isize {
-        self.score
-    }
-
-    fn from_cell_max(iter_cell: &[(isize, Direction)]) -> Self {
-        let mut iter_cell = iter_cell.into_iter();
-        let first = iter_cell.next().unwrap();
-        let mut max_score = first.0;
-        let mut acc = HashSet::new();
-        acc.insert(first.1);
-
-        for &(score, dir) in iter_cell {
-            if score == max_score {
-                acc.insert(dir);
-            } else if score > max_score {
-                max_score = score;
-                acc.clear();
-                acc.insert(dir);
-            }
-        }
-
-        Cell {
-            score: max_score,
-            dirs: acc,
-        }
-    }
-
-    fn get_any_dir(&self) -> Direction {
-        *self.dirs.iter().next().unwrap()
-    }
-}
-
-type AlignmentMatrix = HashMap<(usize, usize), Cell>;
+use crate::alignment::Alignment;
+use crate::pairwise::{self, Mode, OptimalAlignments};
+use crate::stats::{ShuffleTest, Significance};
+use crate::substitution::SubstitutionMatrix;
 
 pub struct Builder<'a> {
     fst: &'a [u8],
     snd: &'a [u8],
     linear_gap_penalty: isize,
+    matrix: SubstitutionMatrix,
 }
 
 impl<'a> Builder<'a> {
@@ -87,137 +18,141 @@ impl<'a> Builder<'a> {
             fst,
             snd,
             linear_gap_penalty,
+            matrix: SubstitutionMatrix::pam250(),
         }
     }
 
-    fn initialize_alignment_matrix(&self) -> AlignmentMatrix {
-        let mut mat = HashMap::new();
-        mat.insert((0, 0), Cell::new(0));
-
-        for idx in 1..=self.fst.len() {
-            mat.insert(
-                (idx, 0usize),
-                Cell::new_with_dir(idx as isize * self.linear_gap_penalty, Direction::left()),
-            );
-        }
-
-        for idx in 1..=self.snd.len() {
-            mat.insert(
-                (0usize, idx),
-                Cell::new_with_dir(idx as isize * self.linear_gap_penalty, Direction::top()),
-            );
-        }
-        mat
-    }
-
-    pub fn align(&self) -> LocalAlignment {
-        let mut mat = self.initialize_alignment_matrix();
-        let indices = iproduct![1..=self.fst.len(), 1..=self.snd.len()];
-        for (fst_idx, snd_idx) in indices {
-            info!(
-                "Determining aligment for cell at pos {}, {}",
-                fst_idx, snd_idx
-            );
-            info!("Indexing top cell at pos {}, {}", fst_idx - 1, snd_idx);
-            let top = Builder::score_idx(fst_idx - 1, snd_idx, &mat) + self.linear_gap_penalty;
-            info!("Indexing left cell at pos {}, {}", fst_idx, snd_idx - 1);
-            let left = Builder::score_idx(fst_idx, snd_idx - 1, &mat) + self.linear_gap_penalty;
-            info!(
-                "Indexing top left cell at pos {}, {}",
-                fst_idx - 1,
-                snd_idx - 1
-            );
-            let matching = Builder::score_idx(fst_idx - 1, snd_idx - 1, &mat)
-                + self.scoring_matrix(fst_idx, snd_idx);
-            let cell_result = Cell::from_cell_max(&[
-                (top, Direction::Top),
-                (left, Direction::Left),
-                (matching, Direction::TopLeft),
-            ]);
-            info!(
-                "Inserting cell with score {} as pos {}, {}",
-                cell_result.score(),
-                fst_idx,
-                snd_idx
-            );
-            Builder::insert((fst_idx, snd_idx), cell_result, &mut mat);
-        }
-        let (fst_aligned, snd_aligned) = self.traceback(&mat);
-        let max_score = self.maximum_alignment_score(&mat);
-        LocalAlignment::new(fst_aligned, snd_aligned, mat, max_score)
+    /// Scores residue pairs with `matrix` instead of the default PAM250.
+    pub fn matrix(mut self, matrix: SubstitutionMatrix) -> Self {
+        self.matrix = matrix;
+        self
     }
 
-    fn insert(idxs: (usize, usize), cell: Cell, mat: &mut AlignmentMatrix) {
-        mat.insert(idxs, cell);
+    fn core(&self) -> pairwise::Builder<'a> {
+        pairwise::Builder::new(self.fst, self.snd, self.linear_gap_penalty)
+            .matrix(self.matrix.clone())
+            .mode(Mode::Local)
     }
 
-    fn scoring_matrix(&self, fst_idx: usize, snd_idx: usize) -> isize {
-        let fst_char = self.fst[fst_idx - 1];
-        let snd_char = self.snd[snd_idx - 1];
-        pam250::pam250(fst_char, snd_char) as isize
+    /// Best scoring local alignment. If nothing scores above zero, the
+    /// alignment is empty and starts and ends at zero on both sequences.
+    pub fn align(&self) -> Alignment {
+        self.core().align()
     }
 
-    fn score_idx(fst_idx: usize, snd_idx: usize, mat: &AlignmentMatrix) -> isize {
-        Builder::cell_idx(fst_idx, snd_idx, mat).score()
+    /// Number of distinct best scoring local alignments.
+    pub fn count_optimal(&self) -> u128 {
+        self.core().count_optimal()
     }
 
-    fn cell_idx(fst_idx: usize, snd_idx: usize, mat: &AlignmentMatrix) -> &Cell {
-        &mat[&(fst_idx, snd_idx)]
+    /// Lazily enumerates every best scoring local alignment, starting with
+    /// the one `align` returns.
+    pub fn optimal_alignments(&self) -> OptimalAlignments<'a> {
+        self.core().optimal_alignments()
     }
 
-    fn maximum_alignment_score(&self, _mat: &AlignmentMatrix) -> isize {
-        unimplemented!()
+    /// Significance of the best local score against alignments of `fst`
+    /// with shuffles of `snd`.
+    pub fn significance(&self, test: &ShuffleTest) -> Significance {
+        self.core().significance(test)
     }
 
-    fn traceback(&self, mat: &AlignmentMatrix) -> (Vec<u8>, Vec<u8>) {
-        let mut pos = (self.fst.len(), self.snd.len());
-        // TODO Use VecDeque instead to add letters in the correct order
-        let mut fst_align = Vec::new();
-        let mut snd_align = Vec::new();
-
-        while pos.0 != 0 || pos.1 != 0 {
-            match Builder::cell_idx(pos.0, pos.1, mat).get_any_dir() {
-                Direction::Top => {
-                    fst_align.push(b'-');
-                    snd_align.push(self.snd[pos.1 - 1]);
-                    pos.1 -= 1;
-                }
-                Direction::Left => {
-                    fst_align.push(self.fst[pos.0 - 1]);
-                    snd_align.push(b'-');
-                    pos.0 -= 1;
-                }
-                Direction::TopLeft => {
-                    fst_align.push(self.fst[pos.0 - 1]);
-                    snd_align.push(self.snd[pos.1 - 1]);
-                    pos.0 -= 1;
-                    pos.1 -= 1;
-                }
+    /// Up to `k` non-overlapping local alignments, best first, following
+    /// Waterman-Eggert: after each alignment is reported, every cell on its
+    /// path is forced to zero and the matrix is recomputed, so later hits
+    /// can never reuse a pair of positions from an earlier one.
+    pub fn align_top_k(&self, k: usize) -> Vec<Alignment> {
+        let core = self.core();
+        let mut masked = HashSet::new();
+        let mut acc = Vec::new();
+        while acc.len() < k {
+            let (mat, max_pos) = core.fill(&masked);
+            if mat.score(max_pos.0, max_pos.1) <= 0 {
+                break;
             }
+            let (alignment, path) = core.traceback(&mat, max_pos);
+            masked.extend(path);
+            acc.push(alignment);
         }
-        (fst_align, snd_align)
+        acc
     }
 }
 
-pub struct LocalAlignment {
-    pub fst_aligned: Vec<u8>,
-    pub snd_aligned: Vec<u8>,
-    pub max_score: isize,
-    mat: AlignmentMatrix,
-}
-
-impl LocalAlignment {
-    fn new(
-        fst_aligned: Vec<u8>,
-        snd_aligned: Vec<u8>,
-        mat: AlignmentMatrix,
-        max_score: isize,
-    ) -> Self {
-        LocalAlignment {
-            fst_aligned,
-            snd_aligned,
-            mat,
-            max_score,
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bio::alignment::pairwise::Aligner;
+    use bio::scores::pam250;
+
+    #[test]
+    fn test_local_alignment() {
+        let fst = b"MEANLYPRTEINSTRING";
+        let snd = b"PLEASANTLYEINSTEIN";
+        let alignment = Builder::new(fst, snd, -5).align();
+
+        let score = |a: u8, b: u8| pam250::pam250(a, b);
+        let mut aligner = Aligner::new(0, -5, &score);
+        let expected = aligner.local(fst, snd);
+        assert_eq!(expected.score as isize, alignment.score);
+        assert_eq!(
+            (expected.xstart, expected.xend),
+            (alignment.fst_start, alignment.fst_end)
+        );
+        assert_eq!(
+            (expected.ystart, expected.yend),
+            (alignment.snd_start, alignment.snd_end)
+        );
+    }
+
+    #[test]
+    fn test_local_alignment_coordinates() {
+        let fst = b"CCCWWWCCC";
+        let snd = b"GGGGWWWGG";
+        let alignment = Builder::new(fst, snd, -5).align();
+        assert_eq!(51, alignment.score);
+        assert_eq!("4S3=2S", alignment.cigar());
+        assert_eq!((3, 6), (alignment.fst_start, alignment.fst_end));
+        assert_eq!((4, 7), (alignment.snd_start, alignment.snd_end));
+    }
+
+    #[test]
+    fn test_local_alignment_nothing_shared() {
+        let alignment = Builder::new(b"WWW", b"GGG", -5).align();
+        assert_eq!(0, alignment.score);
+        assert!(alignment.ops.is_empty());
+    }
+
+    #[test]
+    fn test_optimal_alignments() {
+        let builder = Builder::new(b"WW", b"WWGGGGGWW", -5);
+        assert_eq!(2, builder.count_optimal());
+        let hits: Vec<Alignment> = builder.optimal_alignments().collect();
+        assert_eq!(builder.align(), hits[0]);
+        assert_eq!((0, 2), (hits[0].snd_start, hits[0].snd_end));
+        assert_eq!((7, 9), (hits[1].snd_start, hits[1].snd_end));
+    }
+
+    #[test]
+    fn test_align_top_k() {
+        let fst = b"WWWAAAAAACCC";
+        let snd = b"CCCGGGGGGWWW";
+        let hits = Builder::new(fst, snd, -5).align_top_k(2);
+        assert_eq!(2, hits.len());
+        assert_eq!(51, hits[0].score);
+        assert_eq!((0, 3), (hits[0].fst_start, hits[0].fst_end));
+        assert_eq!((9, 12), (hits[0].snd_start, hits[0].snd_end));
+        assert_eq!(36, hits[1].score);
+        assert_eq!((9, 12), (hits[1].fst_start, hits[1].fst_end));
+        assert_eq!((0, 3), (hits[1].snd_start, hits[1].snd_end));
+    }
+
+    #[test]
+    fn test_local_alignment_dna_matrix() {
+        let alignment = Builder::new(b"TTTACGTAAA", b"CCACGTCC", -2)
+            .matrix(SubstitutionMatrix::dna(1, -1))
+            .align();
+        assert_eq!(4, alignment.score);
+        assert_eq!((2, 6), (alignment.snd_start, alignment.snd_end));
+        assert_eq!(1.0, alignment.identity());
     }
 }
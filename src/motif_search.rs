@@ -0,0 +1,540 @@
+use std::error::Error;
+use std::fmt::Display;
+
+use itertools::Itertools;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::consensus_profile::Profile;
+
+const BASES: &[u8; 4] = b"ACGT";
+
+/// Longest motif `median_string` looks for, as it tries all 4^k patterns.
+pub const MAX_MEDIAN_K: usize = 10;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MotifError {
+    NoSequences,
+    ZeroK,
+    KTooLong { k: usize, len: usize },
+    NoKmer { seq: usize },
+    MedianTooLong { k: usize },
+}
+
+impl Display for MotifError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MotifError::NoSequences => write!(f, "no sequences to search"),
+            MotifError::ZeroK => write!(f, "motif length must be at least 1"),
+            MotifError::KTooLong { k, len } => {
+                write!(f, "motif length {} exceeds a sequence of length {}", k, len)
+            }
+            MotifError::NoKmer { seq } => {
+                write!(f, "sequence {} has no k-mer of only A, C, G and T", seq)
+            }
+            MotifError::MedianTooLong { k } => write!(
+                f,
+                "median string takes motifs of at most {} bases, got {}",
+                MAX_MEDIAN_K, k
+            ),
+        }
+    }
+}
+
+impl Error for MotifError {}
+
+/// One k-mer from each sequence, with how far they are from agreeing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Motifs {
+    pub motifs: Vec<Vec<u8>>,
+    pub score: usize,
+}
+
+impl Motifs {
+    fn new(motifs: Vec<Vec<u8>>) -> Self {
+        let score = score(&motifs);
+        Motifs { motifs, score }
+    }
+
+    pub fn profile(&self) -> Profile {
+        profile(&self.motifs)
+    }
+
+    pub fn consensus(&self) -> String {
+        self.profile().consensus()
+    }
+}
+
+/// Residues of `motifs` that differ from the most common one of their
+/// column, summed over the columns.
+pub fn score(motifs: &[Vec<u8>]) -> usize {
+    let len = motifs.first().map_or(0, Vec::len);
+    (0..len)
+        .map(|col| {
+            let counts = motifs.iter().map(|motif| motif[col]).counts();
+            motifs.len() - counts.values().max().unwrap()
+        })
+        .sum()
+}
+
+/// Profile of `motifs`, leaving out those with residues that are not
+/// nucleotides.
+fn profile(motifs: &[Vec<u8>]) -> Profile {
+    let mut profile = Profile::new();
+    for motif in motifs {
+        // A rejected motif leaves the profile as it was.
+        let _ = profile.add_seq(motif);
+    }
+    profile
+}
+
+fn base_index(base: u8) -> Option<usize> {
+    BASES.iter().position(|&b| b == base)
+}
+
+fn hamming(fst: &[u8], snd: &[u8]) -> usize {
+    fst.iter().zip(snd).filter(|(x, y)| x != y).count()
+}
+
+/**
+ * Motif finding over a set of DNA sequences: looks for the k-mer of each
+ * sequence that together agree the most, scored by `score`. All but the
+ * median string search build a profile from the current motifs, counting
+ * `pseudocount` extra of every base in each column (Laplace's rule of
+ * succession by default), and pick the k-mers it makes likely. The
+ * randomized searches start over `restarts` times from random k-mers
+ * drawn from `seed`.
+ */
+pub struct Builder {
+    seqs: Vec<Vec<u8>>,
+    k: usize,
+    pseudocount: f64,
+    seed: u64,
+    restarts: usize,
+    iterations: usize,
+}
+
+impl Builder {
+    /// Sequences are read case-insensitively; k-mers holding anything but
+    /// A, C, G or T are never picked, so each sequence needs one without.
+    pub fn new(seqs: &[Vec<u8>], k: usize) -> Result<Self, MotifError> {
+        if seqs.is_empty() {
+            return Err(MotifError::NoSequences);
+        }
+        if k == 0 {
+            return Err(MotifError::ZeroK);
+        }
+        if let Some(seq) = seqs.iter().find(|seq| seq.len() < k) {
+            return Err(MotifError::KTooLong { k, len: seq.len() });
+        }
+        let seqs: Vec<Vec<u8>> = seqs.iter().map(|seq| seq.to_ascii_uppercase()).collect();
+        let builder = Builder {
+            seqs,
+            k,
+            pseudocount: 1.0,
+            seed: 0,
+            restarts: 20,
+            iterations: 200,
+        };
+        let no_kmer = |seq: &Vec<u8>| builder.kmers(seq).next().is_none();
+        if let Some(seq) = builder.seqs.iter().position(no_kmer) {
+            return Err(MotifError::NoKmer { seq });
+        }
+        Ok(builder)
+    }
+
+    pub fn pseudocount(mut self, pseudocount: f64) -> Self {
+        self.pseudocount = pseudocount;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn restarts(mut self, restarts: usize) -> Self {
+        self.restarts = restarts.max(1);
+        self
+    }
+
+    /// Motifs Gibbs sampling resamples in each restart.
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// K-mers of `seq` made only of A, C, G and T.
+    fn kmers<'a>(&self, seq: &'a [u8]) -> impl Iterator<Item = &'a [u8]> {
+        seq.windows(self.k)
+            .filter(|kmer| kmer.iter().all(|&base| base_index(base).is_some()))
+    }
+
+    /**
+     * The k-mer with the least total distance to the sequences, each
+     * sequence contributing its closest k-mer, along with those closest
+     * k-mers. Tries all 4^k patterns, so only suits short motifs and
+     * fails for k above `MAX_MEDIAN_K`; the first pattern in ACGT order
+     * wins ties.
+     */
+    pub fn median_string(&self) -> Result<(Vec<u8>, Motifs), MotifError> {
+        if self.k > MAX_MEDIAN_K {
+            return Err(MotifError::MedianTooLong { k: self.k });
+        }
+        let closest = |pattern: &[u8], seq: &[u8]| {
+            self.kmers(seq)
+                .min_by_key(|kmer| hamming(pattern, kmer))
+                .unwrap()
+                .to_vec()
+        };
+        let distance = |pattern: &[u8]| -> usize {
+            self.seqs
+                .iter()
+                .map(|seq| hamming(pattern, &closest(pattern, seq)))
+                .sum()
+        };
+        let median: Vec<u8> = (0..self.k)
+            .map(|_| BASES.iter().copied())
+            .multi_cartesian_product()
+            .min_by_key(|pattern| distance(pattern))
+            .unwrap();
+        let motifs = self.seqs.iter().map(|seq| closest(&median, seq)).collect();
+        Ok((median, Motifs::new(motifs)))
+    }
+
+    /// Chance of each base in each column of a profile of `motifs`,
+    /// uniform in a column with neither counts nor pseudocounts.
+    fn probabilities(&self, motifs: &[Vec<u8>]) -> Vec<[f64; 4]> {
+        let mut counts = vec![[0.0; 4]; self.k];
+        for motif in motifs {
+            for (col, &base) in counts.iter_mut().zip(motif) {
+                if let Some(idx) = base_index(base) {
+                    col[idx] += 1.0;
+                }
+            }
+        }
+        counts
+            .into_iter()
+            .map(|counts| {
+                let total = counts.iter().sum::<f64>() + 4.0 * self.pseudocount;
+                if total > 0.0 {
+                    counts.map(|count| (count + self.pseudocount) / total)
+                } else {
+                    [0.25; 4]
+                }
+            })
+            .collect()
+    }
+
+    fn probability(probs: &[[f64; 4]], kmer: &[u8]) -> f64 {
+        probs
+            .iter()
+            .zip(kmer)
+            .map(|(col, &base)| base_index(base).map_or(0.0, |idx| col[idx]))
+            .product()
+    }
+
+    /// Most probable k-mer of `seq` under `probs`, the first on ties.
+    fn most_probable(&self, probs: &[[f64; 4]], seq: &[u8]) -> Vec<u8> {
+        let mut kmers = self.kmers(seq);
+        let mut best = kmers.next().unwrap();
+        let mut best_prob = Builder::probability(probs, best);
+        for kmer in kmers {
+            let prob = Builder::probability(probs, kmer);
+            if prob > best_prob {
+                (best, best_prob) = (kmer, prob);
+            }
+        }
+        best.to_vec()
+    }
+
+    /**
+     * Greedy motif search: for each k-mer of the first sequence, picks
+     * the most probable k-mer of every following sequence under the
+     * profile of the motifs picked so far, and keeps the best set.
+     */
+    pub fn greedy(&self) -> Motifs {
+        let first = |seq: &Vec<u8>| self.kmers(seq).next().unwrap().to_vec();
+        let mut best = Motifs::new(self.seqs.iter().map(first).collect());
+        for kmer in self.kmers(&self.seqs[0]) {
+            let mut motifs = vec![kmer.to_vec()];
+            for seq in &self.seqs[1..] {
+                let probs = self.probabilities(&motifs);
+                motifs.push(self.most_probable(&probs, seq));
+            }
+            let motifs = Motifs::new(motifs);
+            if motifs.score < best.score {
+                best = motifs;
+            }
+        }
+        best
+    }
+
+    fn random_motifs(&self, rng: &mut StdRng) -> Vec<Vec<u8>> {
+        self.seqs
+            .iter()
+            .map(|seq| {
+                let kmers: Vec<&[u8]> = self.kmers(seq).collect();
+                kmers[rng.gen_range(0..kmers.len())].to_vec()
+            })
+            .collect()
+    }
+
+    /**
+     * Randomized motif search: from random motifs, replaces them all with
+     * the most probable k-mers under their profile for as long as that
+     * improves the score.
+     */
+    pub fn randomized(&self) -> Motifs {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut best: Option<Motifs> = None;
+        for _ in 0..self.restarts {
+            let mut motifs = Motifs::new(self.random_motifs(&mut rng));
+            loop {
+                let probs = self.probabilities(&motifs.motifs);
+                let next = Motifs::new(
+                    self.seqs
+                        .iter()
+                        .map(|seq| self.most_probable(&probs, seq))
+                        .collect(),
+                );
+                if next.score >= motifs.score {
+                    break;
+                }
+                motifs = next;
+            }
+            if best.as_ref().is_none_or(|best| motifs.score < best.score) {
+                best = Some(motifs);
+            }
+        }
+        best.unwrap()
+    }
+
+    /**
+     * Gibbs sampling: from random motifs, repeatedly drops the motif of a
+     * random sequence and draws a new one from that sequence with chance
+     * proportional to its probability under the profile of the others.
+     * Slower to converge than the randomized search, but less prone to
+     * throwing away a motif that is already right.
+     */
+    pub fn gibbs(&self) -> Motifs {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut best: Option<Motifs> = None;
+        for _ in 0..self.restarts {
+            let mut motifs = self.random_motifs(&mut rng);
+            let mut restart_best = Motifs::new(motifs.clone());
+            for _ in 0..self.iterations {
+                let idx = rng.gen_range(0..self.seqs.len());
+                let others: Vec<Vec<u8>> = motifs
+                    .iter()
+                    .enumerate()
+                    .filter(|&(other, _)| other != idx)
+                    .map(|(_, motif)| motif.clone())
+                    .collect();
+                let probs = self.probabilities(&others);
+                let kmers: Vec<&[u8]> = self.kmers(&self.seqs[idx]).collect();
+                let weights = kmers.iter().map(|kmer| Builder::probability(&probs, kmer));
+                let pick = match WeightedIndex::new(weights) {
+                    Ok(dist) => dist.sample(&mut rng),
+                    Err(_) => rng.gen_range(0..kmers.len()),
+                };
+                motifs[idx] = kmers[pick].to_vec();
+                let current = Motifs::new(motifs.clone());
+                if current.score < restart_best.score {
+                    restart_best = current;
+                }
+            }
+            if best
+                .as_ref()
+                .is_none_or(|best| restart_best.score < best.score)
+            {
+                best = Some(restart_best);
+            }
+        }
+        best.unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, random_dna};
+
+    fn seqs(seqs: &[&str]) -> Vec<Vec<u8>> {
+        seqs.iter().map(|seq| seq.as_bytes().to_vec()).collect()
+    }
+
+    fn as_strings(motifs: &Motifs) -> Vec<String> {
+        motifs
+            .motifs
+            .iter()
+            .map(|motif| String::from_utf8(motif.clone()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_score() {
+        let motifs = seqs(&["TCGGGGGTTTTT", "CCGGTGACTTAC", "ACGGGGATTTTC"]);
+        assert_eq!(7, score(&motifs));
+        assert_eq!(0, score(&[]));
+        let motifs = Motifs::new(motifs);
+        assert_eq!("ACGGGGATTTTC", motifs.consensus());
+    }
+
+    #[test]
+    fn test_median_string() {
+        // The sample of Rosalind's BA2B problem, which accepts ACG or GAC.
+        let dna = seqs(&[
+            "AAATTGACGCAT",
+            "GACGACCACGTT",
+            "CGTCAGCGCCTG",
+            "GCTGAGCACCGG",
+            "AGTACGGGACAG",
+        ]);
+        let (median, motifs) = Builder::new(&dna, 3).unwrap().median_string().unwrap();
+        assert_eq!(b"ACG".to_vec(), median);
+        let distance: usize = motifs.motifs.iter().map(|m| hamming(&median, m)).sum();
+        assert_eq!(2, distance);
+    }
+
+    #[test]
+    fn test_greedy() {
+        // The samples of Rosalind's BA2D and BA2E problems.
+        let dna = seqs(&[
+            "GGCGTTCAGGCA",
+            "AAGAATCAGTCA",
+            "CAAGGAGTTCGC",
+            "CACGTCAATCAC",
+            "CAATAATATTCG",
+        ]);
+        let plain = Builder::new(&dna, 3).unwrap().pseudocount(0.0).greedy();
+        assert_eq!(vec!["CAG", "CAG", "CAA", "CAA", "CAA"], as_strings(&plain));
+        let laplace = Builder::new(&dna, 3).unwrap().greedy();
+        assert_eq!(
+            vec!["TTC", "ATC", "TTC", "ATC", "TTC"],
+            as_strings(&laplace)
+        );
+        assert_eq!(laplace.score, score(&laplace.motifs));
+    }
+
+    /// The sample of Rosalind's BA2F and BA2G problems, and the motifs
+    /// they give as the answer.
+    fn ba2f() -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        let dna = seqs(&[
+            "CGCCCCTCTCGGGGGTGTTCAGTAAACGGCCA",
+            "GGGCGAGGTATGTGTAAGTGCCAAGGTGCCAG",
+            "TAGTACCGAGACCGAAAGAAGTATACAGGCGT",
+            "TAGATCAAGTTTCAGGTGCACGTCGGTGAACC",
+            "AATCCACCAGCTCCACGTGCAATGTTGGCCTA",
+        ]);
+        let answer = seqs(&["TCTCGGGG", "CCAAGGTG", "TACAGGCG", "TTCAGGTG", "TCCACGTG"]);
+        (dna, answer)
+    }
+
+    #[test]
+    fn test_randomized() {
+        let (dna, answer) = ba2f();
+        let builder = Builder::new(&dna, 8).unwrap().restarts(1000).seed(3);
+        let motifs = builder.randomized();
+        assert!(motifs.score <= score(&answer));
+        assert_eq!(motifs, builder.randomized());
+        for (motif, seq) in motifs.motifs.iter().zip(&dna) {
+            assert!(seq.windows(8).any(|kmer| kmer == motif.as_slice()));
+        }
+    }
+
+    #[test]
+    fn test_gibbs() {
+        let (dna, answer) = ba2f();
+        let builder = Builder::new(&dna, 8).unwrap().restarts(50).seed(7);
+        let motifs = builder.gibbs();
+        assert!(motifs.score <= score(&answer));
+        assert_eq!(motifs, builder.gibbs());
+        assert_eq!(8, motifs.profile().len());
+    }
+
+    #[test]
+    fn test_planted_motif() {
+        let mut rng = testing::rng(31);
+        let motif = b"GATTACAGC";
+        let dna: Vec<Vec<u8>> = (0..10)
+            .map(|idx| {
+                let mut seq = random_dna(&mut rng, 60);
+                let start = (idx * 13) % 50;
+                seq[start..start + 9].copy_from_slice(motif);
+                // One substitution in every other copy.
+                if idx % 2 == 1 {
+                    let pos = start + idx % 9;
+                    seq[pos] = if seq[pos] == b'A' { b'C' } else { b'A' };
+                }
+                seq
+            })
+            .collect();
+        let builder = Builder::new(&dna, 9).unwrap().seed(1);
+        for motifs in [builder.randomized(), builder.gibbs(), builder.greedy()] {
+            assert_eq!("GATTACAGC", motifs.consensus());
+            assert!(motifs.score <= 5);
+        }
+    }
+
+    #[test]
+    fn test_other_residues() {
+        let dna = seqs(&["ACGTXXGTAC", "ACGTACGTAC", "TTGTACGTAC"]);
+        let builder = Builder::new(&dna, 3).unwrap().seed(1);
+        let searches = [
+            builder.median_string().unwrap().1,
+            builder.greedy(),
+            builder.randomized(),
+            builder.gibbs(),
+        ];
+        for motifs in searches {
+            assert!(motifs
+                .motifs
+                .iter()
+                .flatten()
+                .all(|base| BASES.contains(base)));
+        }
+        // Only the first k-mer of the first sequence can be picked.
+        let dna = seqs(&["ACGXX", "GGACG"]);
+        assert_eq!(
+            b"ACG".to_vec(),
+            Builder::new(&dna, 3).unwrap().greedy().motifs[0]
+        );
+    }
+
+    #[test]
+    fn test_single_sequence() {
+        let dna = seqs(&["ACGTACGTAC"]);
+        for pseudocount in [1.0, 0.0] {
+            let builder = Builder::new(&dna, 3).unwrap().pseudocount(pseudocount);
+            for motifs in [builder.greedy(), builder.randomized(), builder.gibbs()] {
+                assert_eq!(0, motifs.score);
+                assert_eq!(1, motifs.motifs.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Some(MotifError::NoSequences), Builder::new(&[], 3).err());
+        let dna = seqs(&["ACGT", "ANNT"]);
+        assert_eq!(Some(MotifError::ZeroK), Builder::new(&dna, 0).err());
+        assert_eq!(
+            Some(MotifError::KTooLong { k: 5, len: 4 }),
+            Builder::new(&dna, 5).err()
+        );
+        assert_eq!(
+            Some(MotifError::NoKmer { seq: 1 }),
+            Builder::new(&dna, 3).err()
+        );
+        let long = seqs(&["ACGTACGTACGT"]);
+        let builder = Builder::new(&long, MAX_MEDIAN_K + 1).unwrap();
+        assert_eq!(
+            Some(MotifError::MedianTooLong {
+                k: MAX_MEDIAN_K + 1
+            }),
+            builder.median_string().err()
+        );
+    }
+}
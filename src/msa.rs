@@ -0,0 +1,897 @@
+use std::error::Error;
+use std::fmt::{Display, Write};
+use std::iter;
+
+use itertools::Itertools;
+use ndarray::{ArrayD, IxDyn};
+
+use crate::alignment::{Alignment, Operation};
+use crate::consensus_profile::{Profile, ProfileError};
+use crate::guide_tree::{GuideTree, Node};
+use crate::pairwise::{self, NEG_INF};
+use crate::substitution::SubstitutionMatrix;
+use crate::utils;
+
+/// Residues per line in the Clustal output.
+const LINE_WIDTH: usize = 60;
+
+/// Spaces between the longest name and the residues in Clustal output.
+const NAME_PADDING: usize = 6;
+
+/// Most sequences `align_exact` takes, as it tries every subset of them
+/// for each column.
+pub const MAX_EXACT_SEQUENCES: usize = 8;
+
+/// Most cells of the `align_exact` table, the product of the sequence
+/// lengths plus one.
+pub const MAX_EXACT_CELLS: usize = 1 << 24;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MsaError {
+    AffineGaps,
+    TooManySequences(usize),
+    TableTooLarge,
+}
+
+impl Display for MsaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MsaError::AffineGaps => {
+                write!(
+                    f,
+                    "exact alignment only takes linear gaps, set gap open to 0"
+                )
+            }
+            MsaError::TooManySequences(count) => write!(
+                f,
+                "exact alignment takes at most {} sequences, got {}",
+                MAX_EXACT_SEQUENCES, count
+            ),
+            MsaError::TableTooLarge => write!(
+                f,
+                "exact alignment table would exceed {} cells",
+                MAX_EXACT_CELLS
+            ),
+        }
+    }
+}
+
+impl Error for MsaError {}
+
+/// How the guide tree deciding the merge order is built.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeMethod {
+    Upgma,
+    NeighborJoining,
+}
+
+/**
+ * Progressive multiple sequence alignment. Every pair of sequences is
+ * aligned globally to get their distances, a guide tree is built from the
+ * distances, and the tree is then walked from the leaves up, aligning the
+ * two groups under each join profile to profile.
+ *
+ * Columns are scored by sum of pairs: every pair of residues across the
+ * two groups scores with the substitution matrix and every residue facing
+ * a gap scores `linear_gap_penalty`. The gap open penalty is charged once
+ * for every pair of rows whenever a gap is opened in a group, which is
+ * exact when both groups are single sequences.
+ */
+pub struct Builder<'a> {
+    seqs: &'a [Vec<u8>],
+    linear_gap_penalty: isize,
+    gap_open: isize,
+    matrix: SubstitutionMatrix,
+    tree: TreeMethod,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(seqs: &'a [Vec<u8>], linear_gap_penalty: isize) -> Self {
+        Builder {
+            seqs,
+            linear_gap_penalty,
+            gap_open: 0,
+            matrix: SubstitutionMatrix::blosum62(),
+            tree: TreeMethod::Upgma,
+        }
+    }
+
+    /// Extra penalty charged once for every gap, on top of the per-residue
+    /// `linear_gap_penalty`.
+    pub fn gap_open(mut self, penalty: isize) -> Self {
+        self.gap_open = penalty;
+        self
+    }
+
+    pub fn matrix(mut self, matrix: SubstitutionMatrix) -> Self {
+        self.matrix = matrix;
+        self
+    }
+
+    pub fn tree(mut self, tree: TreeMethod) -> Self {
+        self.tree = tree;
+        self
+    }
+
+    /// One minus the identity of the optimal global alignment, for every
+    /// pair of sequences.
+    pub fn distances(&self) -> Vec<Vec<f64>> {
+        let count = self.seqs.len();
+        let mut dist = vec![vec![0.0; count]; count];
+        for (i, fst) in self.seqs.iter().enumerate() {
+            for (j, snd) in self.seqs.iter().enumerate().skip(i + 1) {
+                let alignment = pairwise::Builder::new(fst, snd, self.linear_gap_penalty)
+                    .gap_open(self.gap_open)
+                    .matrix(self.matrix.clone())
+                    .align();
+                dist[i][j] = 1.0 - alignment.identity();
+                dist[j][i] = dist[i][j];
+            }
+        }
+        dist
+    }
+
+    pub fn guide_tree(&self) -> GuideTree {
+        let dist = self.distances();
+        match self.tree {
+            TreeMethod::Upgma => GuideTree::upgma(&dist),
+            TreeMethod::NeighborJoining => GuideTree::neighbor_joining(&dist),
+        }
+    }
+
+    /// Aligns the sequences along the guide tree. The rows of the result
+    /// are in the same order as the input sequences.
+    pub fn align(&self) -> Msa {
+        let tree = self.guide_tree();
+        let mut groups: Vec<Option<Group>> = Vec::with_capacity(tree.nodes().len());
+        for node in tree.nodes() {
+            let group = match *node {
+                Node::Leaf(leaf) => Group {
+                    members: vec![leaf],
+                    rows: vec![self.seqs[leaf].clone()],
+                },
+                Node::Join { left, right, .. } => {
+                    let left = groups[left].take().unwrap();
+                    let right = groups[right].take().unwrap();
+                    self.merge(left, right)
+                }
+            };
+            groups.push(Some(group));
+        }
+
+        let mut rows = vec![Vec::new(); self.seqs.len()];
+        if let Some(Some(root)) = groups.pop() {
+            for (member, row) in root.members.into_iter().zip(root.rows) {
+                rows[member] = row;
+            }
+        }
+        let score = self.sum_of_pairs(&rows);
+        Msa { rows, score }
+    }
+
+    /**
+     * Alignment with the best sum of pairs score, by dynamic programming
+     * over all the sequences at once: one table dimension per sequence
+     * and one move per non-empty subset of sequences contributing a
+     * residue to the next column, i.e. 7 moves for three sequences. Time
+     * and memory are the product of the sequence lengths, so this is only
+     * meant for three or so short sequences, and fails beyond
+     * `MAX_EXACT_SEQUENCES` sequences or `MAX_EXACT_CELLS` cells.
+     *
+     * The table only has linear gaps, so a non-zero `gap_open` is an error.
+     */
+    pub fn align_exact(&self) -> Result<Msa, MsaError> {
+        if self.gap_open != 0 {
+            return Err(MsaError::AffineGaps);
+        }
+        let count = self.seqs.len();
+        if count == 0 {
+            return Ok(Msa {
+                rows: Vec::new(),
+                score: 0,
+            });
+        }
+        if count > MAX_EXACT_SEQUENCES {
+            return Err(MsaError::TooManySequences(count));
+        }
+        let dims: Vec<usize> = self.seqs.iter().map(|seq| seq.len() + 1).collect();
+        let cells = dims
+            .iter()
+            .try_fold(1usize, |cells, &dim| cells.checked_mul(dim));
+        if cells.is_none_or(|cells| cells > MAX_EXACT_CELLS) {
+            return Err(MsaError::TableTooLarge);
+        }
+        // Full columns first, so they win ties.
+        let moves: Vec<Vec<bool>> = (1..1usize << count)
+            .rev()
+            .map(|mask| (0..count).map(|seq| mask >> seq & 1 == 1).collect())
+            .collect();
+        let prev = |index: &[usize], step: &[bool]| -> Option<Vec<usize>> {
+            index
+                .iter()
+                .zip(step)
+                .map(|(&idx, &consumes)| idx.checked_sub(usize::from(consumes)))
+                .collect()
+        };
+
+        let mut table = ArrayD::<isize>::from_elem(IxDyn(&dims), NEG_INF);
+        for index in dims.iter().map(|&dim| 0..dim).multi_cartesian_product() {
+            let best = moves
+                .iter()
+                .filter_map(|step| {
+                    let from = prev(&index, step)?;
+                    Some(table[IxDyn(&from)] + self.column_score(&index, step))
+                })
+                .max()
+                .unwrap_or(0);
+            table[IxDyn(&index)] = best;
+        }
+
+        let mut rows = vec![Vec::new(); count];
+        let mut index: Vec<usize> = dims.iter().map(|dim| dim - 1).collect();
+        while index.iter().any(|&idx| idx > 0) {
+            let here = table[IxDyn(&index)];
+            let (step, from) = moves
+                .iter()
+                .find_map(|step| {
+                    let from = prev(&index, step)?;
+                    let score = table[IxDyn(&from)] + self.column_score(&index, step);
+                    (score == here).then_some((step, from))
+                })
+                .unwrap();
+            for (seq, row) in rows.iter_mut().enumerate() {
+                row.push(match step[seq] {
+                    true => self.seqs[seq][index[seq] - 1],
+                    false => b'-',
+                });
+            }
+            index = from;
+        }
+        rows.iter_mut().for_each(|row| row.reverse());
+        let score = self.sum_of_pairs(&rows);
+        Ok(Msa { rows, score })
+    }
+
+    /// Sum of pairs score, with linear gaps, of the column reached at
+    /// `index` by the move `step`.
+    fn column_score(&self, index: &[usize], step: &[bool]) -> isize {
+        let column: Vec<Option<u8>> = step
+            .iter()
+            .enumerate()
+            .map(|(seq, &consumes)| consumes.then(|| self.seqs[seq][index[seq] - 1]))
+            .collect();
+        column
+            .iter()
+            .tuple_combinations()
+            .map(|pair| match pair {
+                (Some(a), Some(b)) => self.matrix.score(*a, *b),
+                (None, None) => 0,
+                _ => self.linear_gap_penalty,
+            })
+            .sum()
+    }
+
+    /**
+     * Center star alignment: the sequence with the best total pairwise
+     * score against the others is the center, every other sequence is
+     * aligned to it on its own, and the pairwise alignments are merged
+     * keeping every gap they put in the center. For scores derived from a
+     * metric, the result costs at most twice the optimum.
+     */
+    pub fn align_center_star(&self) -> Msa {
+        let global = |fst: &'a [u8], snd: &'a [u8]| {
+            pairwise::Builder::new(fst, snd, self.linear_gap_penalty)
+                .gap_open(self.gap_open)
+                .matrix(self.matrix.clone())
+        };
+        let center = (0..self.seqs.len())
+            .max_by_key(|&center| {
+                let total: isize = (0..self.seqs.len())
+                    .filter(|&other| other != center)
+                    .map(|other| global(&self.seqs[center], &self.seqs[other]).score())
+                    .sum();
+                // The first of several equally good centers.
+                (total, std::cmp::Reverse(center))
+            })
+            .unwrap_or(0);
+        let alignments: Vec<Option<Alignment>> = (0..self.seqs.len())
+            .map(|other| {
+                (other != center).then(|| global(&self.seqs[center], &self.seqs[other]).align())
+            })
+            .collect();
+
+        // Most residues any sequence puts before each residue of the
+        // center, and after the last one.
+        let center_len = self.seqs.get(center).map_or(0, |seq| seq.len());
+        let mut slots = vec![0; center_len + 1];
+        for alignment in alignments.iter().flatten() {
+            for (slot, inserted) in inserted_runs(alignment).into_iter().enumerate() {
+                slots[slot] = slots[slot].max(inserted);
+            }
+        }
+
+        let rows: Vec<Vec<u8>> = alignments
+            .iter()
+            .zip(self.seqs)
+            .map(|(alignment, seq)| {
+                let mut row = Vec::new();
+                let mut residues = seq.iter().copied();
+                let mut ops = alignment
+                    .as_ref()
+                    .map(|alignment| alignment.ops.iter().peekable());
+                for (slot, &width) in slots.iter().enumerate() {
+                    let mut inserted = 0;
+                    if let Some(ops) = ops.as_mut() {
+                        while ops.next_if_eq(&&Operation::Insertion).is_some() {
+                            row.push(residues.next().unwrap());
+                            inserted += 1;
+                        }
+                    }
+                    row.extend(iter::repeat_n(b'-', width - inserted));
+                    if slot == center_len {
+                        break;
+                    }
+                    let consumes = match ops.as_mut() {
+                        Some(ops) => ops.next().unwrap().consumes_snd(),
+                        None => true,
+                    };
+                    row.push(match consumes {
+                        true => residues.next().unwrap(),
+                        false => b'-',
+                    });
+                }
+                row
+            })
+            .collect();
+        let score = self.sum_of_pairs(&rows);
+        Msa { rows, score }
+    }
+
+    /// Sum over every pair of rows of the score of the pairwise alignment
+    /// they induce, leaving out columns where both have a gap.
+    fn sum_of_pairs(&self, rows: &[Vec<u8>]) -> isize {
+        let mut score = 0;
+        for (i, fst_row) in rows.iter().enumerate() {
+            for snd_row in &rows[i + 1..] {
+                let ops = fst_row
+                    .iter()
+                    .zip(snd_row)
+                    .filter_map(|(&a, &b)| match (a, b) {
+                        (b'-', b'-') => None,
+                        (_, b'-') => Some(Operation::Deletion),
+                        (b'-', _) => Some(Operation::Insertion),
+                        _ => Some(Operation::pair(a, b)),
+                    })
+                    .collect();
+                let fst = ungapped(fst_row);
+                let snd = ungapped(snd_row);
+                let alignment = Alignment::new(0, (0, 0), ops, (fst.len(), snd.len()));
+                score += pairwise::alignment_score(
+                    &alignment,
+                    &fst,
+                    &snd,
+                    &self.matrix,
+                    self.linear_gap_penalty,
+                    self.gap_open,
+                );
+            }
+        }
+        score
+    }
+
+    /**
+     * Gotoh alignment of two groups, treating each as a sequence of
+     * columns. `matches`, `fst_gaps` and `snd_gaps` hold the best scores
+     * of prefix alignments ending in a column pair, in a gap inserted
+     * into `snd` and in a gap inserted into `fst`.
+     */
+    fn merge(&self, fst: Group, snd: Group) -> Group {
+        let fst_cols = columns(&fst.rows);
+        let snd_cols = columns(&snd.rows);
+        let (fst_rows, snd_rows) = (fst.rows.len() as isize, snd.rows.len() as isize);
+        let (fst_len, snd_len) = (fst_cols.len(), snd_cols.len());
+        let width = snd_len + 1;
+        let open = self.gap_open * fst_rows * snd_rows;
+        // Every residue of a column facing a gap in each row of the other
+        // group.
+        let fst_gap = |i: usize| self.linear_gap_penalty * fst_cols[i - 1].residues * snd_rows;
+        let snd_gap = |j: usize| self.linear_gap_penalty * snd_cols[j - 1].residues * fst_rows;
+        let pair = |i: usize, j: usize| {
+            let (a, b) = (&fst_cols[i - 1], &snd_cols[j - 1]);
+            let substitutions: isize = a
+                .counts
+                .iter()
+                .flat_map(|&(x, x_count)| {
+                    b.counts
+                        .iter()
+                        .map(move |&(y, y_count)| x_count * y_count * self.matrix.score(x, y))
+                })
+                .sum();
+            let facing_gaps =
+                a.residues * (snd_rows - b.residues) + b.residues * (fst_rows - a.residues);
+            substitutions + self.linear_gap_penalty * facing_gaps
+        };
+
+        let cells = (fst_len + 1) * width;
+        let mut matches = vec![NEG_INF; cells];
+        let mut fst_gaps = vec![NEG_INF; cells];
+        let mut snd_gaps = vec![NEG_INF; cells];
+        matches[0] = 0;
+        for i in 1..=fst_len {
+            let prev = if i == 1 {
+                open
+            } else {
+                fst_gaps[(i - 1) * width]
+            };
+            fst_gaps[i * width] = prev + fst_gap(i);
+        }
+        for j in 1..=snd_len {
+            let prev = if j == 1 { open } else { snd_gaps[j - 1] };
+            snd_gaps[j] = prev + snd_gap(j);
+        }
+        for i in 1..=fst_len {
+            for j in 1..=snd_len {
+                let here = i * width + j;
+                let (up, left, diag) = (here - width, here - 1, here - width - 1);
+                matches[here] = matches[diag].max(fst_gaps[diag]).max(snd_gaps[diag]) + pair(i, j);
+                fst_gaps[here] = (matches[up] + open)
+                    .max(fst_gaps[up])
+                    .max(snd_gaps[up] + open)
+                    + fst_gap(i);
+                snd_gaps[here] = (matches[left] + open)
+                    .max(snd_gaps[left])
+                    .max(fst_gaps[left] + open)
+                    + snd_gap(j);
+            }
+        }
+
+        // Walk back from the corner, preferring a column pair, then a gap
+        // in `snd`, and extending gaps rather than opening new ones.
+        let (mut i, mut j) = (fst_len, snd_len);
+        let corner = i * width + j;
+        let best = matches[corner].max(fst_gaps[corner]).max(snd_gaps[corner]);
+        let mut state = if matches[corner] == best {
+            Step::Pair
+        } else if fst_gaps[corner] == best {
+            Step::FstOnly
+        } else {
+            Step::SndOnly
+        };
+        let mut steps = Vec::with_capacity(fst_len + snd_len);
+        while i > 0 || j > 0 {
+            steps.push(state);
+            let here = i * width + j;
+            state = match state {
+                Step::Pair => {
+                    let diag = here - width - 1;
+                    let prev = matches[here] - pair(i, j);
+                    i -= 1;
+                    j -= 1;
+                    if matches[diag] == prev {
+                        Step::Pair
+                    } else if fst_gaps[diag] == prev {
+                        Step::FstOnly
+                    } else {
+                        Step::SndOnly
+                    }
+                }
+                Step::FstOnly => {
+                    let up = here - width;
+                    let prev = fst_gaps[here] - fst_gap(i);
+                    i -= 1;
+                    if fst_gaps[up] == prev {
+                        Step::FstOnly
+                    } else if matches[up] + open == prev {
+                        Step::Pair
+                    } else {
+                        Step::SndOnly
+                    }
+                }
+                Step::SndOnly => {
+                    let left = here - 1;
+                    let prev = snd_gaps[here] - snd_gap(j);
+                    j -= 1;
+                    if snd_gaps[left] == prev {
+                        Step::SndOnly
+                    } else if matches[left] + open == prev {
+                        Step::Pair
+                    } else {
+                        Step::FstOnly
+                    }
+                }
+            };
+        }
+        steps.reverse();
+
+        let mut rows: Vec<Vec<u8>> = fst
+            .rows
+            .into_iter()
+            .map(|row| insert_gaps(row, &steps, Step::SndOnly))
+            .collect();
+        rows.extend(
+            snd.rows
+                .into_iter()
+                .map(|row| insert_gaps(row, &steps, Step::FstOnly)),
+        );
+        let mut members = fst.members;
+        members.extend(snd.members);
+        Group { members, rows }
+    }
+}
+
+/// Aligned rows for some of the input sequences, `members[k]` being the
+/// input index of `rows[k]`.
+struct Group {
+    members: Vec<usize>,
+    rows: Vec<Vec<u8>>,
+}
+
+/// Residue counts of one column of a group.
+struct Column {
+    counts: Vec<(u8, isize)>,
+    residues: isize,
+}
+
+/// Column of the merged alignment while tracing back a profile alignment.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Pair,
+    FstOnly,
+    SndOnly,
+}
+
+fn columns(rows: &[Vec<u8>]) -> Vec<Column> {
+    let len = rows.first().map_or(0, |row| row.len());
+    (0..len)
+        .map(|col| {
+            let mut counts: Vec<(u8, isize)> = Vec::new();
+            for ch in rows.iter().map(|row| row[col]).filter(|&ch| ch != b'-') {
+                let ch = ch.to_ascii_uppercase();
+                match counts.iter_mut().find(|(other, _)| *other == ch) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((ch, 1)),
+                }
+            }
+            let residues = counts.iter().map(|&(_, count)| count).sum();
+            Column { counts, residues }
+        })
+        .collect()
+}
+
+/// `row` with a gap at every step where only the other group has a column.
+fn insert_gaps(row: Vec<u8>, steps: &[Step], other_only: Step) -> Vec<u8> {
+    let mut residues = row.into_iter();
+    steps
+        .iter()
+        .map(|&step| match step == other_only {
+            true => b'-',
+            false => residues.next().unwrap(),
+        })
+        .collect()
+}
+
+/// Number of residues `alignment` inserts before each residue of `fst`,
+/// and after the last one.
+fn inserted_runs(alignment: &Alignment) -> Vec<usize> {
+    let mut runs = vec![0; alignment.fst_len + 1];
+    let mut slot = 0;
+    for op in &alignment.ops {
+        match op.consumes_fst() {
+            true => slot += 1,
+            false => runs[slot] += 1,
+        }
+    }
+    runs
+}
+
+fn ungapped(row: &[u8]) -> Vec<u8> {
+    row.iter().copied().filter(|&ch| ch != b'-').collect()
+}
+
+/// Multiple sequence alignment, one row per input sequence with `-` for
+/// gaps. `score` is the sum of pairs score of the alignment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Msa {
+    pub rows: Vec<Vec<u8>>,
+    pub score: isize,
+}
+
+impl Msa {
+    /// Number of columns.
+    pub fn len(&self) -> usize {
+        self.rows.first().map_or(0, |row| row.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Aligned FASTA, with `names` as the record ids.
+    pub fn fasta<S: AsRef<str>>(&self, names: &[S]) -> String {
+        utils::fasta(names, &self.rows)
+    }
+
+    /**
+     * Clustal format, in blocks of 60 columns. The line under each block
+     * marks fully conserved columns with `*`; the weaker `:` and `.`
+     * conservation groups are not written.
+     */
+    pub fn clustal<S: AsRef<str>>(&self, names: &[S]) -> String {
+        let width = names
+            .iter()
+            .map(|name| name.as_ref().len())
+            .max()
+            .unwrap_or(0)
+            + NAME_PADDING;
+        let conserved: Vec<u8> = (0..self.len())
+            .map(|col| {
+                let first = self.rows[0][col];
+                let same = self
+                    .rows
+                    .iter()
+                    .all(|row| row[col] != b'-' && row[col].eq_ignore_ascii_case(&first));
+                if same {
+                    b'*'
+                } else {
+                    b' '
+                }
+            })
+            .collect();
+
+        let mut clustal = String::from("CLUSTAL multiple sequence alignment\n\n");
+        for start in (0..self.len()).step_by(LINE_WIDTH) {
+            let end = (start + LINE_WIDTH).min(self.len());
+            clustal.push('\n');
+            for (name, row) in names.iter().zip(&self.rows) {
+                let line = String::from_utf8_lossy(&row[start..end]);
+                writeln!(clustal, "{:<width$}{}", name.as_ref(), line).unwrap();
+            }
+            let marks = String::from_utf8_lossy(&conserved[start..end]);
+            writeln!(clustal, "{:<width$}{}", "", marks).unwrap();
+        }
+        clustal
+    }
+
+    /// Column profile of the alignment, which needs to be of nucleotides.
+    pub fn profile(&self) -> Result<Profile, ProfileError> {
+        let mut profile = Profile::new();
+        for row in &self.rows {
+            profile.add_seq(row)?;
+        }
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, random_dna};
+    use rand::Rng;
+
+    fn check_rows(seqs: &[Vec<u8>], msa: &Msa) {
+        assert_eq!(seqs.len(), msa.rows.len());
+        for (seq, row) in seqs.iter().zip(&msa.rows) {
+            assert_eq!(msa.len(), row.len());
+            assert_eq!(*seq, ungapped(row));
+        }
+        for col in 0..msa.len() {
+            assert!(msa.rows.iter().any(|row| row[col] != b'-'));
+        }
+    }
+
+    #[test]
+    fn test_two_sequences_match_pairwise() {
+        let mut rng = testing::rng(53);
+        let matrix = SubstitutionMatrix::dna(2, -3);
+        for gap_open in [0, -5] {
+            for _ in 0..5 {
+                let seqs = vec![random_dna(&mut rng, 30), random_dna(&mut rng, 25)];
+                let msa = Builder::new(&seqs, -2)
+                    .gap_open(gap_open)
+                    .matrix(matrix.clone())
+                    .align();
+                check_rows(&seqs, &msa);
+                let expected = pairwise::Builder::new(&seqs[0], &seqs[1], -2)
+                    .gap_open(gap_open)
+                    .matrix(matrix.clone())
+                    .score();
+                assert_eq!(expected, msa.score);
+            }
+        }
+    }
+
+    #[test]
+    fn test_progressive() {
+        let seqs: Vec<Vec<u8>> = [
+            "ACGTACGTTTGACCA",
+            "ACGTACGTGACCA",
+            "ACGAACGTTTGACCA",
+            "TTACGTACGTTTGACCA",
+            "ACGTACGTTTGA",
+        ]
+        .iter()
+        .map(|seq| seq.as_bytes().to_vec())
+        .collect();
+        let matrix = SubstitutionMatrix::dna(2, -3);
+        for method in [TreeMethod::Upgma, TreeMethod::NeighborJoining] {
+            let msa = Builder::new(&seqs, -2)
+                .gap_open(-3)
+                .matrix(matrix.clone())
+                .tree(method)
+                .align();
+            check_rows(&seqs, &msa);
+            assert_eq!(17, msa.len());
+            assert_eq!(b"--ACGTACGTTTGACCA".to_vec(), msa.rows[0]);
+            assert_eq!(b"TTACGTACGTTTGACCA".to_vec(), msa.rows[3]);
+            assert_eq!("TTACGTACGTTTGACCA", msa.profile().unwrap().consensus());
+        }
+
+        let mut rng = testing::rng(59);
+        let base = random_dna(&mut rng, 80);
+        let seqs: Vec<Vec<u8>> = (0..6)
+            .map(|idx| {
+                let mut seq = base.clone();
+                seq.remove(idx * 7);
+                seq[idx * 11] = b'A';
+                seq
+            })
+            .collect();
+        let msa = Builder::new(&seqs, -2)
+            .gap_open(-3)
+            .matrix(matrix)
+            .tree(TreeMethod::NeighborJoining)
+            .align();
+        check_rows(&seqs, &msa);
+    }
+
+    /// Best sum of pairs score over every alignment of three sequences,
+    /// trying each of the 7 columns at every step.
+    fn brute_force(seqs: &[&[u8]; 3], matrix: &SubstitutionMatrix, gap: isize) -> isize {
+        if seqs.iter().all(|seq| seq.is_empty()) {
+            return 0;
+        }
+        let mut best = isize::MIN;
+        for mask in 1..8 {
+            let takes = |seq: usize| mask >> seq & 1 == 1;
+            if (0..3).any(|seq| takes(seq) && seqs[seq].is_empty()) {
+                continue;
+            }
+            let column: Vec<Option<u8>> =
+                (0..3).map(|seq| takes(seq).then(|| seqs[seq][0])).collect();
+            let score: isize = [(0, 1), (0, 2), (1, 2)]
+                .iter()
+                .map(|&(a, b)| match (column[a], column[b]) {
+                    (Some(x), Some(y)) => matrix.score(x, y),
+                    (None, None) => 0,
+                    _ => gap,
+                })
+                .sum();
+            let rest = [0, 1, 2].map(|seq| &seqs[seq][usize::from(takes(seq))..]);
+            best = best.max(score + brute_force(&rest, matrix, gap));
+        }
+        best
+    }
+
+    #[test]
+    fn test_exact() {
+        let mut rng = testing::rng(61);
+        let matrix = SubstitutionMatrix::dna(2, -3);
+        for lens in [[4, 3, 3], [5, 2, 4], [0, 3, 2]] {
+            let seqs: Vec<Vec<u8>> = lens.iter().map(|&len| random_dna(&mut rng, len)).collect();
+            let exact = Builder::new(&seqs, -2)
+                .matrix(matrix.clone())
+                .align_exact()
+                .unwrap();
+            check_rows(&seqs, &exact);
+            let refs = [0, 1, 2].map(|seq| seqs[seq].as_slice());
+            assert_eq!(brute_force(&refs, &matrix, -2), exact.score);
+        }
+
+        for _ in 0..5 {
+            let base = random_dna(&mut rng, 25);
+            let seqs: Vec<Vec<u8>> = (0..3)
+                .map(|_| {
+                    let mut seq = base.clone();
+                    seq.drain(..rng.gen_range(0..4));
+                    seq.extend(random_dna(&mut rng, 3));
+                    seq
+                })
+                .collect();
+            let builder = Builder::new(&seqs, -2).matrix(matrix.clone());
+            let exact = builder.align_exact().unwrap();
+            check_rows(&seqs, &exact);
+            assert!(exact.score >= builder.align().score);
+            assert!(exact.score >= builder.align_center_star().score);
+        }
+
+        let pair = vec![random_dna(&mut rng, 20), random_dna(&mut rng, 18)];
+        let exact = Builder::new(&pair, -2)
+            .matrix(matrix.clone())
+            .align_exact()
+            .unwrap();
+        let expected = pairwise::Builder::new(&pair[0], &pair[1], -2)
+            .matrix(matrix)
+            .score();
+        assert_eq!(expected, exact.score);
+
+        assert_eq!(
+            Err(MsaError::AffineGaps),
+            Builder::new(&pair, -2).gap_open(-5).align_exact()
+        );
+        let many = vec![b"A".to_vec(); MAX_EXACT_SEQUENCES + 1];
+        assert_eq!(
+            Err(MsaError::TooManySequences(MAX_EXACT_SEQUENCES + 1)),
+            Builder::new(&many, -2).align_exact()
+        );
+        let long = vec![vec![b'A'; 300]; 3];
+        assert_eq!(
+            Err(MsaError::TableTooLarge),
+            Builder::new(&long, -2).align_exact()
+        );
+    }
+
+    #[test]
+    fn test_center_star() {
+        let seqs: Vec<Vec<u8>> = ["ACGTTGCA", "ACGTGCA", "ACGTTGCAA", "CACGTTGCA"]
+            .iter()
+            .map(|seq| seq.as_bytes().to_vec())
+            .collect();
+        let matrix = SubstitutionMatrix::dna(2, -3);
+        let msa = Builder::new(&seqs, -2)
+            .matrix(matrix.clone())
+            .align_center_star();
+        check_rows(&seqs, &msa);
+        assert_eq!(b"-ACGTTGC-A".to_vec(), msa.rows[0]);
+        assert_eq!(b"-ACGTTGCAA".to_vec(), msa.rows[2]);
+        assert_eq!(b"CACGTTGC-A".to_vec(), msa.rows[3]);
+
+        let mut rng = testing::rng(67);
+        let base = random_dna(&mut rng, 60);
+        let seqs: Vec<Vec<u8>> = (0..5)
+            .map(|idx| {
+                let mut seq = base.clone();
+                seq.insert(idx * 9, b'T');
+                seq.remove(50 - idx * 4);
+                seq
+            })
+            .collect();
+        let msa = Builder::new(&seqs, -2)
+            .gap_open(-3)
+            .matrix(matrix)
+            .align_center_star();
+        check_rows(&seqs, &msa);
+        assert!(Builder::new(&[], -1).align_center_star().is_empty());
+    }
+
+    #[test]
+    fn test_output() {
+        let msa = Msa {
+            rows: vec![b"AC-GT".to_vec(), b"ACTGA".to_vec()],
+            score: 0,
+        };
+        assert_eq!(">one\nAC-GT\n>two\nACTGA\n", msa.fasta(&["one", "two"]));
+        assert_eq!(
+            "CLUSTAL multiple sequence alignment\n\n\n\
+             one      AC-GT\n\
+             two      ACTGA\n         \
+             ** * \n",
+            msa.clustal(&["one", "two"])
+        );
+
+        let long = Msa {
+            rows: vec![vec![b'A'; 70]; 2],
+            score: 0,
+        };
+        let fasta = long.fasta(&["x", "y"]);
+        assert_eq!(
+            vec![2, 60, 10, 2, 60, 10],
+            fasta.lines().map(str::len).collect::<Vec<_>>()
+        );
+        let clustal = long.clustal(&["x", "y"]);
+        assert_eq!(2, clustal.matches("\n\nx").count());
+
+        let empty = Builder::new(&[], -1).align();
+        assert!(empty.is_empty());
+        assert_eq!(0, empty.score);
+    }
+}
@@ -0,0 +1,312 @@
+/**
+ * Myers' bit-vector edit distance, with Hyyrö's block extension for
+ * patterns longer than a machine word. Each column of the Wagner-Fischer
+ * table is kept as vertical deltas packed in `u64` words, so a column
+ * costs a handful of word operations per 64 pattern residues.
+ */
+pub struct Pattern {
+    pattern: Vec<u8>,
+    blocks: usize,
+    /// For each byte, one bit per pattern position holding that byte,
+    /// `blocks` words per byte.
+    peq: Vec<u64>,
+}
+
+/// Best occurrence of a pattern in a text. `start..end` is the shortest
+/// substring of the text at `distance` from the pattern.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+    pub distance: usize,
+}
+
+/// Whether the first row of the table charges for skipped text.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TopRow {
+    Global,
+    Free,
+}
+
+impl Pattern {
+    pub fn new(pattern: &[u8]) -> Self {
+        let blocks = pattern.len().div_ceil(64).max(1);
+        let mut peq = vec![0; 256 * blocks];
+        for (idx, &ch) in pattern.iter().enumerate() {
+            peq[ch as usize * blocks + idx / 64] |= 1 << (idx % 64);
+        }
+        Pattern {
+            pattern: pattern.to_vec(),
+            blocks,
+            peq,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pattern.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pattern.is_empty()
+    }
+
+    /// Edit distance between the whole pattern and the whole text, the
+    /// same as `edit::wagner_fischer`.
+    pub fn distance(&self, text: &[u8]) -> usize {
+        self.run(text, TopRow::Global, |_, _| true)
+    }
+
+    /**
+     * Edit distance if it is at most `max`. Gives up as soon as the
+     * distance is known to be larger, i.e. when the last row is already
+     * further above `max` than there are columns left to bring it down.
+     */
+    pub fn distance_within(&self, text: &[u8], max: usize) -> Option<usize> {
+        if self.len().abs_diff(text.len()) > max {
+            return None;
+        }
+        let mut within = true;
+        let dist = self.run(text, TopRow::Global, |col, score| {
+            within = score <= max + (text.len() - col);
+            within
+        });
+        within.then_some(dist).filter(|&dist| dist <= max)
+    }
+
+    /// Substring of `text` closest to the pattern, ending as early as
+    /// possible on ties.
+    pub fn find_best(&self, text: &[u8]) -> Match {
+        let mut best = (self.len(), 0);
+        self.run(text, TopRow::Free, |col, score| {
+            if score < best.0 {
+                best = (score, col);
+            }
+            true
+        });
+        let (distance, end) = best;
+
+        // Aligning backwards from `end`, the first column at `distance`
+        // gives the shortest match ending there.
+        let rev_pattern: Vec<u8> = self.pattern.iter().rev().copied().collect();
+        let rev_text: Vec<u8> = text[..end].iter().rev().copied().collect();
+        let mut match_len = 0;
+        if distance < self.len() {
+            Pattern::new(&rev_pattern).run(&rev_text, TopRow::Global, |col, score| {
+                match_len = col;
+                score != distance
+            });
+        }
+        Match {
+            start: end - match_len,
+            end,
+            distance,
+        }
+    }
+
+    /**
+     * Runs over the columns of the table, calling `visit` with each column
+     * index and the score in the last row until it returns false. Returns
+     * the last score computed.
+     */
+    fn run(
+        &self,
+        text: &[u8],
+        top_row: TopRow,
+        mut visit: impl FnMut(usize, usize) -> bool,
+    ) -> usize {
+        let mut score = self.len();
+        if !visit(0, score) {
+            return score;
+        }
+        if self.is_empty() {
+            return match top_row {
+                TopRow::Global => text.len(),
+                TopRow::Free => 0,
+            };
+        }
+        let last_bit = 1 << ((self.len() - 1) % 64);
+        let mut pv = vec![!0u64; self.blocks];
+        let mut mv = vec![0u64; self.blocks];
+        for (col, &ch) in text.iter().enumerate() {
+            let eqs = &self.peq[ch as usize * self.blocks..][..self.blocks];
+            let mut carry = match top_row {
+                TopRow::Global => 1,
+                TopRow::Free => 0,
+            };
+            for block in 0..self.blocks {
+                let high = if block + 1 == self.blocks {
+                    last_bit
+                } else {
+                    1 << 63
+                };
+                carry = advance_block(&mut pv[block], &mut mv[block], eqs[block], carry, high);
+            }
+            score = score.wrapping_add_signed(carry as isize);
+            if !visit(col + 1, score) {
+                break;
+            }
+        }
+        score
+    }
+}
+
+/**
+ * Advances one block of vertical deltas by a column. `hin` is the
+ * horizontal delta entering the top of the block and the result is the
+ * one leaving through the row marked by `high`.
+ */
+fn advance_block(pv: &mut u64, mv: &mut u64, eq: u64, hin: i8, high: u64) -> i8 {
+    let xv = eq | *mv;
+    let eq = if hin < 0 { eq | 1 } else { eq };
+    let xh = ((eq & *pv).wrapping_add(*pv) ^ *pv) | eq;
+    let mut ph = *mv | !(xh | *pv);
+    let mut mh = *pv & xh;
+    let hout = if ph & high != 0 {
+        1
+    } else if mh & high != 0 {
+        -1
+    } else {
+        0
+    };
+    ph <<= 1;
+    mh <<= 1;
+    if hin < 0 {
+        mh |= 1;
+    } else if hin > 0 {
+        ph |= 1;
+    }
+    *pv = mh | !(xv | ph);
+    *mv = ph & xv;
+    hout
+}
+
+/// Edit distance between `xs` and `ys`, using the shorter one as the
+/// pattern.
+pub fn distance(xs: &[u8], ys: &[u8]) -> usize {
+    let (pattern, text) = if xs.len() <= ys.len() {
+        (xs, ys)
+    } else {
+        (ys, xs)
+    };
+    Pattern::new(pattern).distance(text)
+}
+
+/// Edit distance between `xs` and `ys` if it is at most `max`.
+pub fn distance_within(xs: &[u8], ys: &[u8], max: usize) -> Option<usize> {
+    let (pattern, text) = if xs.len() <= ys.len() {
+        (xs, ys)
+    } else {
+        (ys, xs)
+    };
+    Pattern::new(pattern).distance_within(text, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit;
+    use crate::testing::{self, random_dna};
+
+    /// Plain DP for the best substring match, as a reference.
+    fn best_match_distance(pattern: &[u8], text: &[u8]) -> usize {
+        let mut prev = vec![0; text.len() + 1];
+        for (idx, &p) in pattern.iter().enumerate() {
+            let mut curr = vec![idx + 1; text.len() + 1];
+            for (col, &t) in text.iter().enumerate() {
+                let diagonal = prev[col] + usize::from(p != t);
+                curr[col + 1] = diagonal.min(prev[col + 1] + 1).min(curr[col] + 1);
+            }
+            prev = curr;
+        }
+        prev.into_iter().min().unwrap()
+    }
+
+    fn wagner_fischer_distance(xs: &[u8], ys: &[u8]) -> usize {
+        edit::distance(xs, ys, &edit::wagner_fischer(xs, ys))
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(5, distance(b"PLEASANTLY", b"MEANLY"));
+        assert_eq!(4, distance(b"PRETTY", b"PRTTEIN"));
+        assert_eq!(3, distance(b"", b"ACG"));
+        assert_eq!(3, distance(b"ACG", b""));
+        assert_eq!(0, distance(b"", b""));
+    }
+
+    #[test]
+    fn test_matches_wagner_fischer() {
+        let mut rng = testing::rng(17);
+        for (fst_len, snd_len) in [
+            (1, 1),
+            (10, 14),
+            (63, 64),
+            (64, 64),
+            (65, 70),
+            (150, 131),
+            (200, 260),
+        ] {
+            for _ in 0..5 {
+                let fst = random_dna(&mut rng, fst_len);
+                let snd = random_dna(&mut rng, snd_len);
+                let expected = wagner_fischer_distance(&fst, &snd);
+                assert_eq!(expected, Pattern::new(&fst).distance(&snd));
+                assert_eq!(expected, Pattern::new(&snd).distance(&fst));
+                assert_eq!(expected, distance(&fst, &snd));
+            }
+        }
+    }
+
+    #[test]
+    fn test_distance_within() {
+        let mut rng = testing::rng(23);
+        for len in [20, 70, 140] {
+            let fst = random_dna(&mut rng, len);
+            let mut snd = fst.clone();
+            snd[len / 2] = if snd[len / 2] == b'A' { b'C' } else { b'A' };
+            snd.remove(len / 3);
+            snd.insert(len / 4, b'T');
+            let expected = wagner_fischer_distance(&fst, &snd);
+            assert!(expected <= 3);
+            assert_eq!(Some(expected), distance_within(&fst, &snd, 3));
+            assert_eq!(Some(expected), distance_within(&fst, &snd, expected));
+            assert_eq!(None, distance_within(&fst, &snd, expected - 1));
+
+            let unrelated = random_dna(&mut rng, len);
+            let dist = wagner_fischer_distance(&fst, &unrelated);
+            assert_eq!(None, distance_within(&fst, &unrelated, dist - 1));
+            assert_eq!(Some(dist), distance_within(&fst, &unrelated, dist));
+        }
+        assert_eq!(None, distance_within(b"ACGT", b"A", 2));
+    }
+
+    #[test]
+    fn test_find_best() {
+        let found = Pattern::new(b"GATTACA").find_best(b"CCCCGATACACCCC");
+        assert_eq!(
+            Match {
+                start: 4,
+                end: 10,
+                distance: 1
+            },
+            found
+        );
+
+        let mut rng = testing::rng(29);
+        for (pattern_len, text_len) in [(5, 40), (30, 100), (90, 300)] {
+            for _ in 0..5 {
+                let pattern = random_dna(&mut rng, pattern_len);
+                let text = random_dna(&mut rng, text_len);
+                let found = Pattern::new(&pattern).find_best(&text);
+                assert_eq!(best_match_distance(&pattern, &text), found.distance);
+                assert_eq!(
+                    found.distance,
+                    wagner_fischer_distance(&pattern, &text[found.start..found.end])
+                );
+            }
+        }
+        let empty = Pattern::new(b"").find_best(b"ACGT");
+        assert_eq!(0, empty.distance);
+        assert_eq!(empty.start, empty.end);
+    }
+}
@@ -142,7 +142,7 @@ fn split_orfs(dna: &[u8]) -> Vec<Vec<u8>> {
             .skip_while(|&&x| x != Some(b'M')) // Trim until start codon
             .map(|x| x.unwrap())
             .collect();
-        if unwrapped.len() == 0 {
+        if unwrapped.is_empty() {
             continue;
         } else {
             acc.push(unwrapped);
@@ -160,7 +160,7 @@ fn all_frames(dna: &[u8]) -> Vec<Vec<u8>> {
     fst
 }
 
-fn rev_comp(dna: &[u8]) -> Vec<u8> {
+pub(crate) fn rev_comp(dna: &[u8]) -> Vec<u8> {
     dna.iter()
         .rev()
         .map(|x| match x {
@@ -228,6 +228,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::byte_char_slices)]
     fn test_split_orf() {
         let dna = b"CCCATGTGAATGCCC";
         let orfs = split_orfs(dna);
@@ -0,0 +1,814 @@
+use std::collections::HashSet;
+use std::iter;
+use std::vec;
+
+use itertools::iproduct;
+use log::info;
+
+use crate::alignment::{Alignment, Operation};
+use crate::dp::{Direction, Directions, DpMatrix, Gap, ScoreTable};
+use crate::stats::{ShuffleTest, Significance};
+use crate::substitution::SubstitutionMatrix;
+
+pub(crate) type AlignmentMatrix = DpMatrix<isize>;
+
+/// Stand-in for an impossible score that can still have penalties added.
+pub(crate) const NEG_INF: isize = isize::MIN / 4;
+
+/// Which unaligned ends of the two sequences are left unpenalized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EndGaps {
+    pub fst_prefix: bool,
+    pub fst_suffix: bool,
+    pub snd_prefix: bool,
+    pub snd_suffix: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Both sequences aligned end to end.
+    Global,
+    /// Best scoring pair of substrings (Smith-Waterman).
+    Local,
+    /// Global, except for the chosen free end gaps.
+    SemiGlobal(EndGaps),
+    /// All of `snd` against a substring of `fst`, e.g. a read against a
+    /// reference window.
+    Fitting,
+    /// A suffix of `fst` against a prefix of `snd`, e.g. two overlapping
+    /// reads.
+    Overlap,
+}
+
+impl Mode {
+    fn end_gaps(self) -> EndGaps {
+        match self {
+            Mode::Global => EndGaps::default(),
+            Mode::Local => EndGaps {
+                fst_prefix: true,
+                fst_suffix: true,
+                snd_prefix: true,
+                snd_suffix: true,
+            },
+            Mode::SemiGlobal(end_gaps) => end_gaps,
+            Mode::Fitting => EndGaps {
+                fst_prefix: true,
+                fst_suffix: true,
+                ..EndGaps::default()
+            },
+            Mode::Overlap => EndGaps {
+                fst_prefix: true,
+                snd_suffix: true,
+                ..EndGaps::default()
+            },
+        }
+    }
+}
+
+/**
+ * DP core shared by every pairwise alignment mode. The mode only changes
+ * how the first row and column are initialized, whether scores are floored
+ * at zero, and which cell the traceback starts from.
+ *
+ * Gaps are affine (Gotoh): a gap of length `k` scores
+ * `gap_open + k * linear_gap_penalty`, and the default `gap_open` of zero
+ * gives plain linear gaps.
+ */
+#[derive(Clone)]
+pub struct Builder<'a> {
+    fst: &'a [u8],
+    snd: &'a [u8],
+    linear_gap_penalty: isize,
+    gap_open: isize,
+    matrix: SubstitutionMatrix,
+    mode: Mode,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(fst: &'a [u8], snd: &'a [u8], linear_gap_penalty: isize) -> Self {
+        Builder {
+            fst,
+            snd,
+            linear_gap_penalty,
+            gap_open: 0,
+            matrix: SubstitutionMatrix::blosum62(),
+            mode: Mode::Global,
+        }
+    }
+
+    /// Extra penalty charged once for every gap, on top of the per-residue
+    /// `linear_gap_penalty`.
+    pub fn gap_open(mut self, penalty: isize) -> Self {
+        self.gap_open = penalty;
+        self
+    }
+
+    pub fn matrix(mut self, matrix: SubstitutionMatrix) -> Self {
+        self.matrix = matrix;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Score of a gap of `len` residues.
+    pub(crate) fn gap(&self, len: usize) -> isize {
+        if len == 0 {
+            0
+        } else {
+            self.gap_open + len as isize * self.linear_gap_penalty
+        }
+    }
+
+    /// First row or column, where the only way in is one long gap.
+    pub(crate) fn border(&self, len: usize, free: bool, dir: Direction) -> (isize, Directions) {
+        if free || len == 0 {
+            (0, Directions::empty())
+        } else {
+            let mut dirs = Directions::only(dir);
+            dirs.insert_gap(dir, if len == 1 { Gap::Open } else { Gap::Extend });
+            (self.gap(len), dirs)
+        }
+    }
+
+    /**
+     * Scores one cell given the best diagonal move (substitution score
+     * included) and, for each gap direction, the plain score and the gap
+     * score of the neighbouring cell. Returns the cell's score and moves
+     * along with its `Top` and `Left` gap scores.
+     */
+    pub(crate) fn cell(
+        &self,
+        matching: isize,
+        (top_score, top_gap): (isize, isize),
+        (left_score, left_gap): (isize, isize),
+    ) -> (isize, Directions, isize, isize) {
+        let open = top_score + self.gap_open + self.linear_gap_penalty;
+        let extend = top_gap + self.linear_gap_penalty;
+        let top = open.max(extend);
+        let top_moves = [(open, Gap::Open), (extend, Gap::Extend)];
+
+        let open = left_score + self.gap_open + self.linear_gap_penalty;
+        let extend = left_gap + self.linear_gap_penalty;
+        let left = open.max(extend);
+        let left_moves = [(open, Gap::Open), (extend, Gap::Extend)];
+
+        let (score, mut dirs) = Directions::max_of(&[
+            (top, Direction::Top),
+            (left, Direction::Left),
+            (matching, Direction::TopLeft),
+        ]);
+        if self.mode == Mode::Local && score <= 0 {
+            dirs = Directions::empty();
+        }
+        for (gap_score, gap) in top_moves {
+            if gap_score == top {
+                dirs.insert_gap(Direction::Top, gap);
+            }
+        }
+        for (gap_score, gap) in left_moves {
+            if gap_score == left {
+                dirs.insert_gap(Direction::Left, gap);
+            }
+        }
+        let score = if self.mode == Mode::Local {
+            score.max(0)
+        } else {
+            score
+        };
+        (score, dirs, top, left)
+    }
+
+    /// Fills the matrix, treating every cell in `masked` as a zero scoring
+    /// start cell, and returns it along with the cell the traceback should
+    /// start from.
+    pub(crate) fn fill(
+        &self,
+        masked: &HashSet<(usize, usize)>,
+    ) -> (AlignmentMatrix, (usize, usize)) {
+        let end_gaps = self.mode.end_gaps();
+        let mut mat = DpMatrix::new(self.fst.len(), self.snd.len());
+        for snd_idx in 1..=self.snd.len() {
+            let (score, dirs) = self.border(snd_idx, end_gaps.snd_prefix, Direction::Top);
+            mat.set(0, snd_idx, score, dirs);
+        }
+
+        // Best gap scores for paths ending in a `Left` move, one per column
+        // of the previous row, and in a `Top` move for the previous column.
+        let mut left_gaps = vec![NEG_INF; self.snd.len() + 1];
+        for fst_idx in 1..=self.fst.len() {
+            let (score, dirs) = self.border(fst_idx, end_gaps.fst_prefix, Direction::Left);
+            mat.set(fst_idx, 0, score, dirs);
+            let mut top_gap = NEG_INF;
+            for (snd_idx, left_gap) in left_gaps.iter_mut().enumerate().skip(1) {
+                if !masked.is_empty() && masked.contains(&(fst_idx, snd_idx)) {
+                    top_gap = NEG_INF;
+                    *left_gap = NEG_INF;
+                    continue;
+                }
+                let matching =
+                    mat.score(fst_idx - 1, snd_idx - 1) + self.scoring_matrix(fst_idx, snd_idx);
+                let (score, dirs, top, left) = self.cell(
+                    matching,
+                    (mat.score(fst_idx, snd_idx - 1), top_gap),
+                    (mat.score(fst_idx - 1, snd_idx), *left_gap),
+                );
+                info!(
+                    "Inserting cell with score {} as pos {}, {}",
+                    score, fst_idx, snd_idx
+                );
+                mat.set(fst_idx, snd_idx, score, dirs);
+                top_gap = top;
+                *left_gap = left;
+            }
+        }
+        let end = self.end_cell(&mat);
+        (mat, end)
+    }
+
+    /// Cells the alignment is allowed to end in, in order of preference:
+    /// the bottom right corner first, or row by row in local mode.
+    fn end_candidates(&self) -> Box<dyn Iterator<Item = (usize, usize)>> {
+        let (fst_len, snd_len) = (self.fst.len(), self.snd.len());
+        if self.mode == Mode::Local {
+            return Box::new(iproduct![0..=fst_len, 0..=snd_len]);
+        }
+        let end_gaps = self.mode.end_gaps();
+        let last_column = (0..fst_len)
+            .filter(move |_| end_gaps.fst_suffix)
+            .map(move |idx| (idx, snd_len));
+        let last_row = (0..snd_len)
+            .filter(move |_| end_gaps.snd_suffix)
+            .map(move |idx| (fst_len, idx));
+        Box::new(
+            iter::once((fst_len, snd_len))
+                .chain(last_column)
+                .chain(last_row),
+        )
+    }
+
+    /// Every highest scoring cell an alignment can end in, preferred one
+    /// first. In local mode, an alignment that can't score above zero is
+    /// empty and ends at the origin.
+    fn end_cells(&self, mat: &AlignmentMatrix) -> Vec<(usize, usize)> {
+        let score = |&(fst_idx, snd_idx): &(usize, usize)| mat.score(fst_idx, snd_idx);
+        let best = self.end_candidates().map(|pos| score(&pos)).max().unwrap();
+        if self.mode == Mode::Local && best <= 0 {
+            return vec![(0, 0)];
+        }
+        self.end_candidates()
+            .filter(|pos| score(pos) == best)
+            .collect()
+    }
+
+    fn end_cell(&self, mat: &AlignmentMatrix) -> (usize, usize) {
+        self.end_cells(mat)[0]
+    }
+
+    pub fn align(&self) -> Alignment {
+        let (mat, end) = self.fill(&HashSet::new());
+        self.traceback(&mat, end).0
+    }
+
+    /**
+     * Empirical significance of the optimal score, from the scores of
+     * `snd` shuffled as `test` specifies against the same `fst`.
+     */
+    pub fn significance(&self, test: &ShuffleTest) -> Significance {
+        test.run(self.score(), self.snd, |shuffled| {
+            Builder {
+                snd: shuffled,
+                ..self.clone()
+            }
+            .score()
+        })
+    }
+
+    /**
+     * Optimal score without a traceback, keeping only two rows of the
+     * matrix so memory is linear in the length of `snd`.
+     */
+    pub fn score(&self) -> isize {
+        let end_gaps = self.mode.end_gaps();
+        let (fst_len, snd_len) = (self.fst.len(), self.snd.len());
+        let mut prev: Vec<isize> = (0..=snd_len)
+            .map(|idx| self.border(idx, end_gaps.snd_prefix, Direction::Top).0)
+            .collect();
+        let mut curr = vec![0; snd_len + 1];
+        let mut left_gaps = vec![NEG_INF; snd_len + 1];
+
+        // Same candidate end cells as `end_cell`, checked one row at a time.
+        let mut best = isize::MIN;
+        let mut consider = |fst_idx: usize, row: &[isize]| {
+            let row_max = row.iter().copied().max().unwrap();
+            if self.mode == Mode::Local {
+                best = best.max(row_max);
+            } else {
+                if end_gaps.fst_suffix || fst_idx == fst_len {
+                    best = best.max(row[snd_len]);
+                }
+                if end_gaps.snd_suffix && fst_idx == fst_len {
+                    best = best.max(row_max);
+                }
+            }
+        };
+        consider(0, &prev);
+
+        for fst_idx in 1..=fst_len {
+            curr[0] = self.border(fst_idx, end_gaps.fst_prefix, Direction::Left).0;
+            let mut top_gap = NEG_INF;
+            for snd_idx in 1..=snd_len {
+                let matching = prev[snd_idx - 1] + self.scoring_matrix(fst_idx, snd_idx);
+                let (score, _, top, left) = self.cell(
+                    matching,
+                    (curr[snd_idx - 1], top_gap),
+                    (prev[snd_idx], left_gaps[snd_idx]),
+                );
+                curr[snd_idx] = score;
+                top_gap = top;
+                left_gaps[snd_idx] = left;
+            }
+            consider(fst_idx, &curr);
+            std::mem::swap(&mut prev, &mut curr);
+        }
+        best
+    }
+
+    pub(crate) fn scoring_matrix(&self, fst_idx: usize, snd_idx: usize) -> isize {
+        let fst_char = self.fst[fst_idx - 1];
+        let snd_char = self.snd[snd_idx - 1];
+        self.matrix.score(fst_char, snd_char)
+    }
+
+    /// Walks back from `end` until a cell with nowhere left to go, taking
+    /// the preferred move at every tie, and returns the alignment along
+    /// with every cell visited on the way.
+    pub(crate) fn traceback<T: ScoreTable<isize>>(
+        &self,
+        mat: &T,
+        end: (usize, usize),
+    ) -> (Alignment, Vec<(usize, usize)>) {
+        let mut pos = end;
+        let mut state = TraceState::Cell;
+        let mut path = Vec::new();
+        let mut ops = Vec::new();
+        while let Some(&(op, next, next_state)) = self.steps(mat, pos, state).first() {
+            path.push(pos);
+            ops.push(op);
+            pos = next;
+            state = next_state;
+        }
+        ops.reverse();
+
+        let alignment = Alignment::new(
+            mat.score(end.0, end.1),
+            pos,
+            ops,
+            (self.fst.len(), self.snd.len()),
+        );
+        (alignment, path)
+    }
+
+    /**
+     * Every optimal move out of `pos` in traceback `state`, preferred one
+     * first. Empty once the traceback has reached the start of the
+     * alignment.
+     */
+    fn steps<T: ScoreTable<isize>>(
+        &self,
+        mat: &T,
+        pos: (usize, usize),
+        state: TraceState,
+    ) -> Vec<Step> {
+        let dirs = mat.dirs(pos.0, pos.1);
+        let mut steps = Vec::new();
+        match state {
+            TraceState::Gap(dir) => self.gap_steps(mat, pos, dir, &mut steps),
+            TraceState::Cell | TraceState::AfterGap(_) => {
+                for dir in dirs.iter() {
+                    if state == TraceState::AfterGap(dir) {
+                        continue;
+                    }
+                    match dir {
+                        Direction::TopLeft => {
+                            let op = Operation::pair(self.fst[pos.0 - 1], self.snd[pos.1 - 1]);
+                            steps.push((op, (pos.0 - 1, pos.1 - 1), TraceState::Cell));
+                        }
+                        _ => self.gap_steps(mat, pos, dir, &mut steps),
+                    }
+                }
+            }
+        }
+        steps
+    }
+
+    /**
+     * Moves that take one gap residue out of `pos`, opening the gap there
+     * or extending a longer one. After a gap is opened, the next move may
+     * not be another gap in the same direction: that alignment is already
+     * reached by extending instead, and would otherwise be found twice.
+     */
+    fn gap_steps<T: ScoreTable<isize>>(
+        &self,
+        mat: &T,
+        pos: (usize, usize),
+        dir: Direction,
+        steps: &mut Vec<Step>,
+    ) {
+        let dirs = mat.dirs(pos.0, pos.1);
+        if dirs.first_gap(dir).is_none() {
+            return;
+        }
+        let (op, next) = match dir {
+            Direction::Top => (Operation::Insertion, (pos.0, pos.1 - 1)),
+            Direction::Left => (Operation::Deletion, (pos.0 - 1, pos.1)),
+            Direction::TopLeft => unreachable!(),
+        };
+        if dirs.contains_gap(dir, Gap::Open) {
+            let after = mat.dirs(next.0, next.1);
+            if after.is_empty() || after.iter().any(|other| other != dir) {
+                steps.push((op, next, TraceState::AfterGap(dir)));
+            }
+        }
+        if dirs.contains_gap(dir, Gap::Extend) {
+            steps.push((op, next, TraceState::Gap(dir)));
+        }
+    }
+
+    /// Number of distinct optimal alignments, saturating at `u128::MAX`.
+    pub fn count_optimal(&self) -> u128 {
+        let (mat, _) = self.fill(&HashSet::new());
+        // Alignments from each cell and state back to a start, filled in
+        // the same order as the matrix since every move goes up or left.
+        let cols = self.snd.len() + 1;
+        let mut counts = vec![[0u128; TraceState::ALL.len()]; (self.fst.len() + 1) * cols];
+        for pos in iproduct![0..=self.fst.len(), 0..=self.snd.len()] {
+            for state in TraceState::ALL {
+                let steps = self.steps(&mat, pos, state);
+                counts[pos.0 * cols + pos.1][state.index()] = match state {
+                    TraceState::Cell | TraceState::AfterGap(_) if steps.is_empty() => 1,
+                    _ => steps.iter().fold(0u128, |acc, &(_, next, next_state)| {
+                        acc.saturating_add(counts[next.0 * cols + next.1][next_state.index()])
+                    }),
+                };
+            }
+        }
+        self.end_cells(&mat)
+            .into_iter()
+            .fold(0, |acc, (fst_idx, snd_idx)| {
+                acc.saturating_add(counts[fst_idx * cols + snd_idx][0])
+            })
+    }
+
+    /// Lazily enumerates every optimal alignment, preferred one first, so
+    /// the first is the one `align` returns. Use `take` to stop early: the
+    /// number of co-optimal alignments can grow exponentially.
+    pub fn optimal_alignments(&self) -> OptimalAlignments<'a> {
+        let (mat, _) = self.fill(&HashSet::new());
+        let ends = self.end_cells(&mat).into_iter();
+        OptimalAlignments {
+            core: self.clone(),
+            mat,
+            ends,
+            end: (0, 0),
+            stack: Vec::new(),
+            ops: Vec::new(),
+        }
+    }
+}
+
+/// Where a traceback is within the affine gap state machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TraceState {
+    /// Following the best score of a cell.
+    Cell,
+    /// Following the best score of a cell, right after the start of a gap
+    /// in this direction.
+    AfterGap(Direction),
+    /// Inside a gap in this direction.
+    Gap(Direction),
+}
+
+impl TraceState {
+    const ALL: [TraceState; 5] = [
+        TraceState::Cell,
+        TraceState::AfterGap(Direction::Top),
+        TraceState::AfterGap(Direction::Left),
+        TraceState::Gap(Direction::Top),
+        TraceState::Gap(Direction::Left),
+    ];
+
+    fn index(self) -> usize {
+        match self {
+            TraceState::Cell => 0,
+            TraceState::AfterGap(Direction::Top) => 1,
+            TraceState::AfterGap(_) => 2,
+            TraceState::Gap(Direction::Top) => 3,
+            TraceState::Gap(_) => 4,
+        }
+    }
+}
+
+/// One traceback move: the alignment column it adds, and the cell and
+/// state it leads to.
+type Step = (Operation, (usize, usize), TraceState);
+
+/// Node of the depth first search in `OptimalAlignments`, with the moves
+/// out of it not tried yet.
+struct Frame {
+    pos: (usize, usize),
+    steps: vec::IntoIter<Step>,
+    terminal: bool,
+}
+
+/// Iterator over every optimal alignment, from `Builder::optimal_alignments`.
+pub struct OptimalAlignments<'a> {
+    core: Builder<'a>,
+    mat: AlignmentMatrix,
+    ends: vec::IntoIter<(usize, usize)>,
+    end: (usize, usize),
+    stack: Vec<Frame>,
+    /// Columns from the end cell to the top frame, last column first.
+    ops: Vec<Operation>,
+}
+
+impl OptimalAlignments<'_> {
+    fn push(&mut self, pos: (usize, usize), state: TraceState) {
+        let steps = self.core.steps(&self.mat, pos, state);
+        let terminal = steps.is_empty();
+        self.stack.push(Frame {
+            pos,
+            steps: steps.into_iter(),
+            terminal,
+        });
+    }
+
+    fn pop(&mut self) {
+        self.stack.pop();
+        self.ops.truncate(self.stack.len().saturating_sub(1));
+    }
+}
+
+impl Iterator for OptimalAlignments<'_> {
+    type Item = Alignment;
+
+    fn next(&mut self) -> Option<Alignment> {
+        loop {
+            let Some(frame) = self.stack.last_mut() else {
+                self.end = self.ends.next()?;
+                self.push(self.end, TraceState::Cell);
+                continue;
+            };
+            if frame.terminal {
+                let start = frame.pos;
+                let ops = self.ops.iter().rev().copied().collect();
+                let score = self.mat.score(self.end.0, self.end.1);
+                let lens = (self.core.fst.len(), self.core.snd.len());
+                self.pop();
+                return Some(Alignment::new(score, start, ops, lens));
+            }
+            match frame.steps.next() {
+                Some((op, pos, state)) => {
+                    self.ops.push(op);
+                    self.push(pos, state);
+                }
+                None => self.pop(),
+            }
+        }
+    }
+}
+
+/**
+ * Recomputes the score of an alignment of `fst` and `snd` from scratch.
+ * Each run of insertions or deletions is a single gap, scored as in
+ * `Builder`.
+ */
+pub(crate) fn alignment_score(
+    alignment: &Alignment,
+    fst: &[u8],
+    snd: &[u8],
+    matrix: &SubstitutionMatrix,
+    linear_gap_penalty: isize,
+    gap_open: isize,
+) -> isize {
+    let (fst_aligned, snd_aligned) = alignment.aligned(fst, snd);
+    let substitutions: isize = fst_aligned
+        .iter()
+        .zip(&snd_aligned)
+        .filter(|&(&a, &b)| a != b'-' && b != b'-')
+        .map(|(&a, &b)| matrix.score(a, b))
+        .sum();
+    let gaps = alignment.insertions() + alignment.deletions();
+    substitutions + linear_gap_penalty * gaps as isize + gap_open * alignment.gap_opens() as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, check_alignment, random_dna};
+    use bio::alignment::pairwise::{Aligner, Scoring, MIN_SCORE};
+
+    fn free(free: bool) -> i32 {
+        if free {
+            0
+        } else {
+            MIN_SCORE
+        }
+    }
+
+    fn bio_score(fst: &[u8], snd: &[u8], end_gaps: EndGaps, gap_open: i32) -> isize {
+        let score = |a: u8, b: u8| if a == b { 2 } else { -3 };
+        let scoring = Scoring::new(gap_open, -4, score)
+            .xclip_prefix(free(end_gaps.fst_prefix))
+            .xclip_suffix(free(end_gaps.fst_suffix))
+            .yclip_prefix(free(end_gaps.snd_prefix))
+            .yclip_suffix(free(end_gaps.snd_suffix));
+        Aligner::with_scoring(scoring).custom(fst, snd).score as isize
+    }
+
+    #[test]
+    fn test_global() {
+        let fst = b"PLEASANTLY";
+        let snd = b"MEANLY";
+        let alignment = Builder::new(fst, snd, -5).align();
+        assert_eq!(8, alignment.score);
+        assert_eq!(8, Builder::new(fst, snd, -5).score());
+        assert_eq!(
+            8,
+            alignment_score(&alignment, fst, snd, &SubstitutionMatrix::blosum62(), -5, 0)
+        );
+        assert_eq!((0, 10), (alignment.fst_start, alignment.fst_end));
+        assert_eq!((0, 6), (alignment.snd_start, alignment.snd_end));
+        check_alignment(fst, snd, &alignment);
+    }
+
+    #[test]
+    fn test_local() {
+        let fst = b"MEANLYPRTEINSTRING";
+        let snd = b"PLEASANTLYEINSTEIN";
+        let alignment = Builder::new(fst, snd, -5)
+            .matrix(SubstitutionMatrix::pam250())
+            .mode(Mode::Local)
+            .align();
+        assert_eq!(23, alignment.score);
+        let score = Builder::new(fst, snd, -5)
+            .matrix(SubstitutionMatrix::pam250())
+            .mode(Mode::Local)
+            .score();
+        assert_eq!(23, score);
+        assert_eq!(
+            23,
+            alignment_score(&alignment, fst, snd, &SubstitutionMatrix::pam250(), -5, 0)
+        );
+        check_alignment(fst, snd, &alignment);
+    }
+
+    #[test]
+    fn test_fitting() {
+        let fst = b"GCAAACCATAAGCCCTACGTGCCGCCTGTTTAAACTCGCGAACTGAATCTTCTGCTTCACGGTGAAAGTACCACAATGGTATCACACCCCAAGGAAAC";
+        let snd = b"GCCGTCAGGCTGGTGTCCG";
+        let alignment = Builder::new(fst, snd, -1)
+            .matrix(SubstitutionMatrix::dna(1, -1))
+            .mode(Mode::Fitting)
+            .align();
+        assert_eq!(5, alignment.score);
+        assert_eq!((0, snd.len()), (alignment.snd_start, alignment.snd_end));
+        check_alignment(fst, snd, &alignment);
+    }
+
+    #[test]
+    fn test_overlap() {
+        let fst = b"CTAAGGGATTCCGGTAATTAGACAG";
+        let snd = b"ATAGACCATATGTCAGTGACTGTGTAA";
+        let alignment = Builder::new(fst, snd, -2)
+            .matrix(SubstitutionMatrix::dna(1, -2))
+            .mode(Mode::Overlap)
+            .align();
+        assert_eq!(1, alignment.score);
+        assert_eq!(fst.len(), alignment.fst_end);
+        assert_eq!(0, alignment.snd_start);
+        check_alignment(fst, snd, &alignment);
+    }
+
+    /// Every global alignment of `fst` and `snd`, by brute force.
+    fn all_global(fst: &[u8], snd: &[u8]) -> Vec<Vec<Operation>> {
+        if fst.is_empty() && snd.is_empty() {
+            return vec![Vec::new()];
+        }
+        let mut acc = Vec::new();
+        let mut prepend = |op, rest: Vec<Vec<Operation>>| {
+            acc.extend(rest.into_iter().map(|ops| [vec![op], ops].concat()));
+        };
+        if !fst.is_empty() && !snd.is_empty() {
+            prepend(
+                Operation::pair(fst[0], snd[0]),
+                all_global(&fst[1..], &snd[1..]),
+            );
+        }
+        if !fst.is_empty() {
+            prepend(Operation::Deletion, all_global(&fst[1..], snd));
+        }
+        if !snd.is_empty() {
+            prepend(Operation::Insertion, all_global(fst, &snd[1..]));
+        }
+        acc
+    }
+
+    #[test]
+    fn test_co_optimal_against_brute_force() {
+        let mut rng = testing::rng(13);
+        let matrix = SubstitutionMatrix::dna(2, -3);
+        for (_, gap_open) in iproduct!(0..15, [0, -3]) {
+            let fst = random_dna(&mut rng, 5);
+            let snd = random_dna(&mut rng, 4);
+            let scored: Vec<(isize, Vec<Operation>)> = all_global(&fst, &snd)
+                .into_iter()
+                .map(|ops| {
+                    let alignment = Alignment::new(0, (0, 0), ops, (fst.len(), snd.len()));
+                    let score = alignment_score(&alignment, &fst, &snd, &matrix, -2, gap_open);
+                    (score, alignment.ops)
+                })
+                .collect();
+            let best = scored.iter().map(|&(score, _)| score).max().unwrap();
+            let expected: HashSet<Vec<Operation>> = scored
+                .into_iter()
+                .filter(|&(score, _)| score == best)
+                .map(|(_, ops)| ops)
+                .collect();
+
+            let builder = Builder::new(&fst, &snd, -2)
+                .matrix(matrix.clone())
+                .gap_open(gap_open);
+            let found: Vec<Alignment> = builder.optimal_alignments().collect();
+            assert_eq!(expected.len() as u128, builder.count_optimal());
+            assert_eq!(expected.len(), found.len());
+            assert_eq!(builder.align(), found[0]);
+            for alignment in &found {
+                assert_eq!(best, alignment.score);
+                assert!(expected.contains(&alignment.ops));
+            }
+        }
+    }
+
+    #[test]
+    fn test_co_optimal_limit_and_local() {
+        let matrix = SubstitutionMatrix::dna(1, -1);
+        let builder = Builder::new(b"AAAA", b"AA", -1).matrix(matrix.clone());
+        assert_eq!(6, builder.count_optimal());
+        assert_eq!(4, builder.optimal_alignments().take(4).count());
+
+        let builder = Builder::new(b"ACGT", b"ACGTTACGT", -2)
+            .matrix(matrix)
+            .mode(Mode::Local);
+        assert_eq!(2, builder.count_optimal());
+        let snd_starts: Vec<usize> = builder
+            .optimal_alignments()
+            .map(|alignment| alignment.snd_start)
+            .collect();
+        assert_eq!(vec![0, 5], snd_starts);
+    }
+
+    #[test]
+    fn test_modes_against_bio() {
+        let mut rng = testing::rng(7);
+        let modes = [
+            Mode::Global,
+            Mode::Fitting,
+            Mode::Overlap,
+            Mode::SemiGlobal(EndGaps {
+                snd_prefix: true,
+                snd_suffix: true,
+                ..EndGaps::default()
+            }),
+            Mode::SemiGlobal(EndGaps {
+                fst_suffix: true,
+                snd_prefix: true,
+                ..EndGaps::default()
+            }),
+        ];
+        let matrix = SubstitutionMatrix::dna(2, -3);
+        for (_, gap_open) in iproduct!(0..20, [0, -6]) {
+            let fst = random_dna(&mut rng, 25);
+            let snd = random_dna(&mut rng, 15);
+            for mode in modes {
+                let builder = Builder::new(&fst, &snd, -4)
+                    .matrix(matrix.clone())
+                    .gap_open(gap_open)
+                    .mode(mode);
+                let alignment = builder.align();
+                assert_eq!(
+                    bio_score(&fst, &snd, mode.end_gaps(), gap_open as i32),
+                    alignment.score
+                );
+                assert_eq!(alignment.score, builder.score());
+                assert_eq!(
+                    alignment.score,
+                    alignment_score(&alignment, &fst, &snd, &matrix, -4, gap_open)
+                );
+                check_alignment(&fst, &snd, &alignment);
+            }
+        }
+    }
+}
@@ -0,0 +1,896 @@
+use std::error::Error;
+use std::fmt::{Display, Write};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use itertools::Itertools;
+
+const DNA: &[u8] = b"ACGT";
+const AMINO: &[u8] = b"ACDEFGHIKLMNPQRSTVWY";
+
+/// Transitions out of every node, in the order HMMER3 files list them.
+const TRANSITIONS: [&str; 7] = ["m->m", "m->i", "m->d", "i->m", "i->i", "d->m", "d->d"];
+const MM: usize = 0;
+const MI: usize = 1;
+const MD: usize = 2;
+const IM: usize = 3;
+const II: usize = 4;
+const DM: usize = 5;
+const DD: usize = 6;
+
+#[derive(Debug)]
+pub enum HmmError {
+    Io(io::Error),
+    MissingHeader,
+    MissingField(&'static str),
+    UnknownAlphabet(String),
+    BadLine(String),
+    BadValue(String),
+}
+
+impl Display for HmmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HmmError::Io(e) => write!(f, "could not read HMM: {}", e),
+            HmmError::MissingHeader => write!(f, "HMM has no HMMER3 header"),
+            HmmError::MissingField(field) => write!(f, "HMM has no {} field", field),
+            HmmError::UnknownAlphabet(alph) => write!(f, "unsupported alphabet: {}", alph),
+            HmmError::BadLine(line) => write!(f, "malformed HMM line: {}", line),
+            HmmError::BadValue(value) => write!(f, "invalid probability: {}", value),
+        }
+    }
+}
+
+impl Error for HmmError {}
+
+impl From<io::Error> for HmmError {
+    fn from(e: io::Error) -> Self {
+        HmmError::Io(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alphabet {
+    Dna,
+    Amino,
+}
+
+impl Alphabet {
+    pub fn residues(self) -> &'static [u8] {
+        match self {
+            Alphabet::Dna => DNA,
+            Alphabet::Amino => AMINO,
+        }
+    }
+
+    /// Position of `residue` in `residues`, ignoring case.
+    pub fn index(self, residue: u8) -> Option<usize> {
+        let residue = residue.to_ascii_uppercase();
+        self.residues().iter().position(|&ch| ch == residue)
+    }
+
+    /// DNA if every residue of `rows` is a nucleotide, otherwise amino acids.
+    fn guess(rows: &[Vec<u8>]) -> Self {
+        let nucleotides = rows
+            .iter()
+            .flatten()
+            .all(|&ch| is_gap(ch) || b"ACGTN".contains(&ch.to_ascii_uppercase()));
+        if nucleotides {
+            Alphabet::Dna
+        } else {
+            Alphabet::Amino
+        }
+    }
+
+    fn hmmer_name(self) -> &'static str {
+        match self {
+            Alphabet::Dna => "DNA",
+            Alphabet::Amino => "amino",
+        }
+    }
+}
+
+/**
+ * State of a profile HMM. Match and delete states are numbered from 1 to
+ * the length of the model and insert states from 0, insert `k` sitting
+ * between match `k` and match `k + 1`. `Match(0)` stands for the begin
+ * state and `Match(len + 1)` for the end state.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum State {
+    Match(usize),
+    Insert(usize),
+    Delete(usize),
+}
+
+/// Position of the transition from `from` to `to` in the transitions of
+/// its node, if the model has one.
+fn slot(from: State, to: State) -> Option<(usize, usize)> {
+    use State::*;
+    match (from, to) {
+        (Match(k), Match(next)) if next == k + 1 => Some((k, MM)),
+        (Match(k), Insert(next)) if next == k => Some((k, MI)),
+        (Match(k), Delete(next)) if next == k + 1 => Some((k, MD)),
+        (Insert(k), Match(next)) if next == k + 1 => Some((k, IM)),
+        (Insert(k), Insert(next)) if next == k => Some((k, II)),
+        (Delete(k), Match(next)) if k > 0 && next == k + 1 => Some((k, DM)),
+        (Delete(k), Delete(next)) if k > 0 && next == k + 1 => Some((k, DD)),
+        _ => None,
+    }
+}
+
+fn is_gap(ch: u8) -> bool {
+    ch == b'-' || ch == b'.'
+}
+
+/**
+ * Builds a profile HMM from the rows of a multiple alignment, or from
+ * equal-length sequences, which are an alignment without gaps. Columns
+ * with more than `gap_threshold` of gaps become insertions, the others
+ * match states. Every probability is estimated from the counts along the
+ * rows plus `pseudocount`, add-one by default.
+ */
+pub struct Builder<'a> {
+    rows: &'a [Vec<u8>],
+    name: String,
+    alphabet: Option<Alphabet>,
+    pseudocount: f64,
+    gap_threshold: f64,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(rows: &'a [Vec<u8>]) -> Self {
+        Builder {
+            rows,
+            name: String::from("hmm"),
+            alphabet: None,
+            pseudocount: 1.0,
+            gap_threshold: 0.5,
+        }
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_owned();
+        self
+    }
+
+    /// Alphabet of the model, guessed from the rows by default.
+    pub fn alphabet(mut self, alphabet: Alphabet) -> Self {
+        self.alphabet = Some(alphabet);
+        self
+    }
+
+    pub fn pseudocount(mut self, pseudocount: f64) -> Self {
+        self.pseudocount = pseudocount;
+        self
+    }
+
+    pub fn gap_threshold(mut self, gap_threshold: f64) -> Self {
+        self.gap_threshold = gap_threshold;
+        self
+    }
+
+    pub fn build(&self) -> ProfileHmm {
+        let alphabet = self.alphabet.unwrap_or_else(|| Alphabet::guess(self.rows));
+        let size = alphabet.residues().len();
+        let width = self.rows.first().map_or(0, Vec::len);
+        assert!(
+            self.rows.iter().all(|row| row.len() == width),
+            "rows must all have the same length"
+        );
+        let match_columns: Vec<bool> = (0..width)
+            .map(|col| {
+                let gaps = self.rows.iter().filter(|row| is_gap(row[col])).count();
+                gaps as f64 <= self.gap_threshold * self.rows.len() as f64
+            })
+            .collect();
+        let len = match_columns.iter().filter(|&&is_match| is_match).count();
+
+        let mut match_counts = vec![vec![0.0; size]; len];
+        let mut insert_counts = vec![vec![0.0; size]; len + 1];
+        let mut transition_counts = vec![[0.0; 7]; len + 1];
+        for row in self.rows {
+            let mut prev = State::Match(0);
+            for (state, residue) in Builder::path(row, &match_columns) {
+                let (node, idx) = slot(prev, state).unwrap();
+                transition_counts[node][idx] += 1.0;
+                prev = state;
+                let counts = match state {
+                    State::Match(k) => &mut match_counts[k - 1],
+                    State::Insert(k) => &mut insert_counts[k],
+                    State::Delete(_) => continue,
+                };
+                if let Some(x) = alphabet.index(residue) {
+                    counts[x] += 1.0;
+                }
+            }
+            let (node, idx) = slot(prev, State::Match(len + 1)).unwrap();
+            transition_counts[node][idx] += 1.0;
+        }
+
+        let estimate = |counts: &[f64]| {
+            let total = counts.iter().sum::<f64>() + self.pseudocount * counts.len() as f64;
+            counts
+                .iter()
+                .map(|&count| match total > 0.0 {
+                    true => (count + self.pseudocount) / total,
+                    false => 1.0 / counts.len() as f64,
+                })
+                .collect::<Vec<f64>>()
+        };
+        let transitions = transition_counts
+            .iter()
+            .enumerate()
+            .map(|(node, counts)| {
+                let mut probs = [0.0; 7];
+                // The last match state can only go on to the end state or
+                // its insert, and the begin and last nodes have no delete
+                // state of their own to leave.
+                let match_end = if node == len { MI + 1 } else { MD + 1 };
+                probs[MM..match_end].copy_from_slice(&estimate(&counts[MM..match_end]));
+                probs[IM..=II].copy_from_slice(&estimate(&counts[IM..=II]));
+                if node == 0 || node == len {
+                    probs[DM] = 1.0;
+                } else {
+                    probs[DM..=DD].copy_from_slice(&estimate(&counts[DM..=DD]));
+                }
+                probs
+            })
+            .collect();
+
+        ProfileHmm {
+            name: self.name.clone(),
+            alphabet,
+            match_emissions: match_counts.iter().map(|c| estimate(c)).collect(),
+            insert_emissions: insert_counts.iter().map(|c| estimate(c)).collect(),
+            transitions,
+        }
+    }
+
+    /**
+     * States `row` passes through, with the residue each one emits. Like
+     * HMMER's models, ours have no transitions between insert and delete
+     * states, so as HMMER does, a delete next to an insert is merged with
+     * it into a match emitting the inserted residue.
+     */
+    fn path(row: &[u8], match_columns: &[bool]) -> Vec<(State, u8)> {
+        let mut path: Vec<(State, u8)> = Vec::with_capacity(row.len());
+        let mut node = 0;
+        for (&residue, &is_match) in row.iter().zip(match_columns) {
+            let state = match (is_match, is_gap(residue)) {
+                (true, false) => State::Match(node + 1),
+                (true, true) => State::Delete(node + 1),
+                (false, false) => State::Insert(node),
+                (false, true) => continue,
+            };
+            node += is_match as usize;
+            match (path.last_mut(), state) {
+                (Some(last @ (State::Delete(_), _)), State::Insert(k)) => {
+                    *last = (State::Match(k), residue);
+                }
+                (Some(last @ (State::Insert(_), _)), State::Delete(k)) => {
+                    last.0 = State::Match(k);
+                }
+                _ => path.push((state, residue)),
+            }
+        }
+        path
+    }
+}
+
+/// Most likely path of a sequence through a `ProfileHmm`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HmmAlignment {
+    /// Natural log of the joint probability of the sequence and the path.
+    pub log_prob: f64,
+    /// States between the begin and end states.
+    pub states: Vec<State>,
+}
+
+impl HmmAlignment {
+    /// `seq` as a row of the model's alignment: residues emitted by match
+    /// states in uppercase, inserted ones in lowercase and `-` for deletes.
+    pub fn aligned(&self, seq: &[u8]) -> Vec<u8> {
+        let mut residues = seq.iter();
+        self.states
+            .iter()
+            .map(|state| match state {
+                State::Match(_) => residues.next().unwrap().to_ascii_uppercase(),
+                State::Insert(_) => residues.next().unwrap().to_ascii_lowercase(),
+                State::Delete(_) => b'-',
+            })
+            .collect()
+    }
+}
+
+/**
+ * Profile HMM with match, insert and delete states, laid out like
+ * HMMER3's Plan7 models without their flanking local alignment states.
+ * Node `k` holds the emissions of match `k` and insert `k` and the
+ * transitions out of match, insert and delete `k`, node 0 being the begin
+ * state.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileHmm {
+    name: String,
+    alphabet: Alphabet,
+    match_emissions: Vec<Vec<f64>>,
+    insert_emissions: Vec<Vec<f64>>,
+    transitions: Vec<[f64; 7]>,
+}
+
+impl ProfileHmm {
+    /// Number of match states.
+    pub fn len(&self) -> usize {
+        self.match_emissions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.match_emissions.is_empty()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn alphabet(&self) -> Alphabet {
+        self.alphabet
+    }
+
+    /// Probability that `state` emits `residue`, zero for delete states.
+    /// Residues outside the alphabet are emitted with probability one over
+    /// the size of the alphabet, as in `viterbi` and `forward`.
+    pub fn emission(&self, state: State, residue: u8) -> f64 {
+        let probs = match state {
+            State::Match(k) if k >= 1 && k <= self.len() => &self.match_emissions[k - 1],
+            State::Insert(k) if k <= self.len() => &self.insert_emissions[k],
+            _ => return 0.0,
+        };
+        let unknown = 1.0 / probs.len() as f64;
+        self.alphabet.index(residue).map_or(unknown, |x| probs[x])
+    }
+
+    /// Probability of going from `from` to `to`, zero if there is no such
+    /// transition.
+    pub fn transition(&self, from: State, to: State) -> f64 {
+        match slot(from, to) {
+            Some((node, idx)) if node <= self.len() => self.transitions[node][idx],
+            _ => 0.0,
+        }
+    }
+
+    /// Most likely residue of every match state.
+    pub fn consensus(&self) -> Vec<u8> {
+        let residues = self.alphabet.residues();
+        self.match_emissions
+            .iter()
+            .map(|probs| {
+                residues[(0..probs.len())
+                    .rev()
+                    .max_by(|&a, &b| probs[a].total_cmp(&probs[b]))
+                    .unwrap()]
+            })
+            .collect()
+    }
+
+    /**
+     * Viterbi alignment of `seq` to the model, from the begin state to the
+     * end state. Residues outside the alphabet, like `N` or `X`, are emitted
+     * by every state with probability one over the size of the alphabet.
+     * `None` if the model cannot emit `seq`, which needs zero pseudocounts.
+     */
+    pub fn viterbi(&self, seq: &[u8]) -> Option<HmmAlignment> {
+        let lattice = Lattice::fill(self, seq, |scores| {
+            scores.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+        });
+        if lattice.end == f64::NEG_INFINITY {
+            return None;
+        }
+
+        let mut states = Vec::new();
+        let (mut state, mut idx) = (State::Match(self.len() + 1), seq.len() + 1);
+        loop {
+            let incoming = lattice.incoming(state, idx);
+            let best = incoming
+                .iter()
+                .map(|&(_, score)| score)
+                .fold(f64::NEG_INFINITY, f64::max);
+            let prev = incoming
+                .iter()
+                .find(|&&(_, score)| score == best)
+                .unwrap()
+                .0;
+            if !matches!(state, State::Delete(_)) {
+                idx -= 1;
+            }
+            if prev == State::Match(0) {
+                break;
+            }
+            states.push(prev);
+            state = prev;
+        }
+        states.reverse();
+        Some(HmmAlignment {
+            log_prob: lattice.end,
+            states,
+        })
+    }
+
+    /// Natural log of the probability of `seq` summed over every path
+    /// through the model, by the forward algorithm.
+    pub fn forward(&self, seq: &[u8]) -> f64 {
+        Lattice::fill(self, seq, |scores| {
+            let max = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            if max == f64::NEG_INFINITY {
+                return max;
+            }
+            max + scores
+                .iter()
+                .map(|score| (score - max).exp())
+                .sum::<f64>()
+                .ln()
+        })
+        .end
+    }
+
+    /**
+     * The model in HMMER3 text format, as `hmmbuild` writes it, but without
+     * the optional annotation columns or the `STATS` lines HMMER adds after
+     * calibrating a model. Probabilities are written as negative natural
+     * logs, `*` standing for zero.
+     */
+    pub fn to_hmmer(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "HMMER3/f [compbio]").unwrap();
+        writeln!(out, "NAME  {}", self.name).unwrap();
+        writeln!(out, "LENG  {}", self.len()).unwrap();
+        writeln!(out, "ALPH  {}", self.alphabet.hmmer_name()).unwrap();
+        for field in ["RF", "MM", "CS", "MAP"] {
+            writeln!(out, "{:<6}no", field).unwrap();
+        }
+        writeln!(out, "CONS  yes").unwrap();
+        write!(out, "HMM     ").unwrap();
+        for &ch in self.alphabet.residues() {
+            write!(out, "     {}   ", ch as char).unwrap();
+        }
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "        {}",
+            TRANSITIONS
+                .iter()
+                .map(|name| format!(" {:>8}", name))
+                .join("")
+        )
+        .unwrap();
+
+        let compo: Vec<f64> = (0..self.alphabet.residues().len())
+            .map(|x| {
+                let total: f64 = self.match_emissions.iter().map(|probs| probs[x]).sum();
+                total / self.len().max(1) as f64
+            })
+            .collect();
+        write!(out, "  COMPO ").unwrap();
+        write_probs(&mut out, &compo);
+        let consensus = self.consensus();
+        for node in 0..=self.len() {
+            if node > 0 {
+                write!(out, "{:>7} ", node).unwrap();
+                let probs = &self.match_emissions[node - 1];
+                for &prob in probs {
+                    write_prob(&mut out, prob);
+                }
+                let cons = consensus[node - 1] as char;
+                writeln!(out, " {:>6} {} - - -", "-", cons).unwrap();
+            }
+            write!(out, "        ").unwrap();
+            write_probs(&mut out, &self.insert_emissions[node]);
+            write!(out, "        ").unwrap();
+            write_probs(&mut out, &self.transitions[node]);
+        }
+        writeln!(out, "//").unwrap();
+        out
+    }
+
+    /**
+     * Parses the first model of a HMMER3 text file. Only the alphabet, the
+     * name, the length and the probabilities are kept, so annotations and
+     * calibration are lost.
+     */
+    pub fn from_hmmer(text: &str) -> Result<Self, HmmError> {
+        let mut lines = text.lines();
+        match lines.next() {
+            Some(line) if line.starts_with("HMMER3") => (),
+            _ => return Err(HmmError::MissingHeader),
+        }
+        let (mut name, mut len, mut alphabet) = (None, None, None);
+        for line in lines.by_ref() {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some("NAME"), Some(value)) => name = Some(value.to_owned()),
+                (Some("LENG"), Some(value)) => {
+                    let value = value
+                        .parse()
+                        .map_err(|_| HmmError::BadLine(line.to_owned()))?;
+                    len = Some(value);
+                }
+                (Some("ALPH"), Some(value)) => {
+                    alphabet = match value.to_ascii_lowercase().as_str() {
+                        "dna" => Some(Alphabet::Dna),
+                        "amino" => Some(Alphabet::Amino),
+                        _ => return Err(HmmError::UnknownAlphabet(value.to_owned())),
+                    };
+                }
+                (Some("HMM"), _) => break,
+                _ => (),
+            }
+        }
+        let name = name.ok_or(HmmError::MissingField("NAME"))?;
+        let len = len.ok_or(HmmError::MissingField("LENG"))?;
+        let alphabet = alphabet.ok_or(HmmError::MissingField("ALPH"))?;
+        let size = alphabet.residues().len();
+
+        // Skip the header of transition names and the optional COMPO line.
+        let mut lines = lines
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .skip(1)
+            .peekable();
+        if lines.peek().is_some_and(|line| line.starts_with("COMPO")) {
+            lines.next();
+        }
+        let mut next_probs = |skip: usize, count: usize| {
+            let line = lines.next().ok_or(HmmError::MissingField("node"))?;
+            let fields: Vec<&str> = line.split_whitespace().skip(skip).take(count).collect();
+            if fields.len() < count {
+                return Err(HmmError::BadLine(line.to_owned()));
+            }
+            fields
+                .iter()
+                .map(|&field| match field {
+                    "*" => Ok(0.0),
+                    _ => field
+                        .parse::<f64>()
+                        .map(|value| (-value).exp())
+                        .map_err(|_| HmmError::BadValue(field.to_owned())),
+                })
+                .collect::<Result<Vec<f64>, HmmError>>()
+        };
+
+        let mut hmm = ProfileHmm {
+            name,
+            alphabet,
+            match_emissions: Vec::with_capacity(len),
+            insert_emissions: Vec::with_capacity(len + 1),
+            transitions: Vec::with_capacity(len + 1),
+        };
+        for node in 0..=len {
+            if node > 0 {
+                hmm.match_emissions.push(next_probs(1, size)?);
+            }
+            hmm.insert_emissions.push(next_probs(0, size)?);
+            let transitions = next_probs(0, 7)?;
+            hmm.transitions.push(transitions.try_into().unwrap());
+        }
+        Ok(hmm)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, HmmError> {
+        ProfileHmm::from_hmmer(&fs::read_to_string(path)?)
+    }
+}
+
+fn write_prob(out: &mut String, prob: f64) {
+    if prob > 0.0 {
+        write!(out, " {:8.5}", f64::max(0.0 - prob.ln(), 0.0)).unwrap();
+    } else {
+        write!(out, " {:>8}", "*").unwrap();
+    }
+}
+
+fn write_probs(out: &mut String, probs: &[f64]) {
+    for &prob in probs {
+        write_prob(out, prob);
+    }
+    writeln!(out).unwrap();
+}
+
+/**
+ * Log space DP over a sequence and a model, shared by Viterbi and forward,
+ * which only differ in how the scores of the paths into a state combine.
+ * Row `idx` holds the paths that have emitted the first `idx` residues.
+ */
+struct Lattice {
+    transitions: Vec<[f64; 7]>,
+    matches: Vec<Vec<f64>>,
+    inserts: Vec<Vec<f64>>,
+    deletes: Vec<Vec<f64>>,
+    end: f64,
+}
+
+impl Lattice {
+    fn fill(hmm: &ProfileHmm, seq: &[u8], combine: fn(&[f64; 3]) -> f64) -> Self {
+        let len = hmm.len();
+        let codes: Vec<Option<usize>> = seq.iter().map(|&ch| hmm.alphabet.index(ch)).collect();
+        let unknown = -(hmm.alphabet.residues().len() as f64).ln();
+        let emit = |probs: &[f64], code: Option<usize>| code.map_or(unknown, |x| probs[x].ln());
+
+        let empty = vec![vec![f64::NEG_INFINITY; len + 1]; seq.len() + 1];
+        let mut lattice = Lattice {
+            transitions: hmm
+                .transitions
+                .iter()
+                .map(|probs| probs.map(f64::ln))
+                .collect(),
+            matches: empty.clone(),
+            inserts: empty.clone(),
+            deletes: empty,
+            end: f64::NEG_INFINITY,
+        };
+        lattice.matches[0][0] = 0.0;
+        let combined = |lattice: &Lattice, state: State, idx: usize| {
+            combine(&lattice.incoming(state, idx).map(|(_, score)| score))
+        };
+        for idx in 0..=seq.len() {
+            for node in 0..=len {
+                if idx > 0 && node > 0 {
+                    let emitted = emit(&hmm.match_emissions[node - 1], codes[idx - 1]);
+                    lattice.matches[idx][node] =
+                        emitted + combined(&lattice, State::Match(node), idx);
+                }
+                if idx > 0 {
+                    let emitted = emit(&hmm.insert_emissions[node], codes[idx - 1]);
+                    lattice.inserts[idx][node] =
+                        emitted + combined(&lattice, State::Insert(node), idx);
+                }
+                if node > 0 {
+                    lattice.deletes[idx][node] = combined(&lattice, State::Delete(node), idx);
+                }
+            }
+        }
+        lattice.end = combined(&lattice, State::Match(len + 1), seq.len() + 1);
+        lattice
+    }
+
+    /// Every state that can precede `state` in row `idx`, with the score of
+    /// the best or all paths through it into `state`. Impossible moves
+    /// score minus infinity so that there are always three.
+    fn incoming(&self, state: State, idx: usize) -> [(State, f64); 3] {
+        match state {
+            State::Match(node) => {
+                let (prev, trans) = (node - 1, &self.transitions[node - 1]);
+                [
+                    (State::Match(prev), self.matches[idx - 1][prev] + trans[MM]),
+                    (State::Insert(prev), self.inserts[idx - 1][prev] + trans[IM]),
+                    (State::Delete(prev), self.deletes[idx - 1][prev] + trans[DM]),
+                ]
+            }
+            State::Insert(node) => {
+                let trans = &self.transitions[node];
+                [
+                    (State::Match(node), self.matches[idx - 1][node] + trans[MI]),
+                    (State::Insert(node), self.inserts[idx - 1][node] + trans[II]),
+                    (State::Delete(node), f64::NEG_INFINITY),
+                ]
+            }
+            State::Delete(node) => {
+                let (prev, trans) = (node - 1, &self.transitions[node - 1]);
+                [
+                    (State::Match(prev), self.matches[idx][prev] + trans[MD]),
+                    (State::Delete(prev), self.deletes[idx][prev] + trans[DD]),
+                    (State::Insert(prev), f64::NEG_INFINITY),
+                ]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TINY: &str = "\
+HMMER3/f [3.1b2 | February 2015]
+NAME  tiny
+LENG  2
+ALPH  DNA
+RF    no
+MM    no
+CONS  yes
+CS    no
+MAP   yes
+NSEQ  3
+EFFN  3.000000
+STATS LOCAL MSV       -5.0000  0.71000
+STATS LOCAL VITERBI   -5.5000  0.71000
+STATS LOCAL FORWARD   -3.0000  0.71000
+HMM          A        C        G        T
+            m->m     m->i     m->d     i->m     i->i     d->m     d->d
+  COMPO   1.38629  1.38629  1.38629  1.38629
+          1.38629  1.38629  1.38629  1.38629
+          0.10536  2.99573  2.99573  0.69315  0.69315  0.00000        *
+      1   0.22314  2.30259  2.30259  2.30259      1 a - - -
+          1.38629  1.38629  1.38629  1.38629
+          0.10536  2.99573  2.99573  0.69315  0.69315  0.69315  0.69315
+      2   2.30259  2.30259  2.30259  0.22314      2 t - - -
+          1.38629  1.38629  1.38629  1.38629
+          0.05129  2.99573        *  0.69315  0.69315  0.00000        *
+//
+";
+
+    fn rows(rows: &[&str]) -> Vec<Vec<u8>> {
+        rows.iter().map(|row| row.as_bytes().to_vec()).collect()
+    }
+
+    fn assert_close(expected: f64, actual: f64) {
+        assert!(
+            (expected - actual).abs() < 1e-9,
+            "expected {} but got {}",
+            expected,
+            actual
+        );
+    }
+
+    /// Total and best probability of every path emitting `seq[idx..]` from
+    /// `state` to the end state, by walking each path.
+    fn paths(hmm: &ProfileHmm, seq: &[u8], state: State, idx: usize) -> (f64, f64) {
+        let end = State::Match(hmm.len() + 1);
+        let (node, mut total, mut best) = match state {
+            State::Match(k) | State::Insert(k) | State::Delete(k) => (k, 0.0, 0.0),
+        };
+        let nexts = [
+            State::Match(node + 1),
+            State::Insert(node),
+            State::Delete(node + 1),
+        ];
+        for next in nexts {
+            let trans = hmm.transition(state, next);
+            if trans == 0.0 {
+                continue;
+            }
+            let (sum, max) = if next == end {
+                if idx < seq.len() {
+                    continue;
+                }
+                (1.0, 1.0)
+            } else if let State::Delete(_) = next {
+                paths(hmm, seq, next, idx)
+            } else {
+                if idx == seq.len() {
+                    continue;
+                }
+                let emitted = hmm.emission(next, seq[idx]);
+                let (sum, max) = paths(hmm, seq, next, idx + 1);
+                (emitted * sum, emitted * max)
+            };
+            total += trans * sum;
+            best = f64::max(best, trans * max);
+        }
+        (total, best)
+    }
+
+    #[test]
+    fn test_build() {
+        let msa = rows(&["AC-GT", "ACAGT", "A--GT", "AG-GT"]);
+        let hmm = Builder::new(&msa).pseudocount(0.0).build();
+        assert_eq!(4, hmm.len());
+        assert_eq!(Alphabet::Dna, hmm.alphabet());
+        assert_eq!(b"ACGT".to_vec(), hmm.consensus());
+        assert_close(0.75, hmm.transition(State::Match(1), State::Match(2)));
+        assert_close(0.25, hmm.transition(State::Match(1), State::Delete(2)));
+        assert_close(1.0 / 3.0, hmm.transition(State::Match(2), State::Insert(2)));
+        assert_close(1.0, hmm.transition(State::Insert(2), State::Match(3)));
+        assert_close(1.0, hmm.transition(State::Delete(2), State::Match(3)));
+        assert_close(1.0, hmm.transition(State::Match(4), State::Match(5)));
+        assert_eq!(0.0, hmm.transition(State::Match(4), State::Delete(5)));
+        assert_eq!(0.0, hmm.transition(State::Match(1), State::Match(3)));
+        assert_close(1.0 / 3.0, hmm.emission(State::Match(2), b'g'));
+        assert_close(1.0, hmm.emission(State::Insert(2), b'A'));
+        assert_eq!(0.0, hmm.emission(State::Delete(2), b'A'));
+
+        let hmm = Builder::new(&msa).name("toy").build();
+        assert_eq!("toy", hmm.name());
+        assert_close(5.0 / 8.0, hmm.emission(State::Match(1), b'A'));
+        assert_close(5.0 / 7.0, hmm.transition(State::Match(0), State::Match(1)));
+        assert_close(0.25, hmm.emission(State::Insert(0), b'T'));
+
+        // A delete followed by an insert is counted as a match.
+        let msa = rows(&["AG-T", "AG-T", "A-CT"]);
+        let hmm = Builder::new(&msa).pseudocount(0.0).build();
+        assert_eq!(3, hmm.len());
+        assert_close(1.0 / 3.0, hmm.emission(State::Match(2), b'C'));
+        assert_eq!(0.0, hmm.transition(State::Match(1), State::Delete(2)));
+
+        let proteins = rows(&["MKV", "MRV"]);
+        assert_eq!(Alphabet::Amino, Builder::new(&proteins).build().alphabet());
+        assert!(Builder::new(&[]).build().is_empty());
+    }
+
+    #[test]
+    fn test_viterbi() {
+        let msa = rows(&["AC-GT", "ACAGT", "A--GT", "AG-GT"]);
+        let hmm = Builder::new(&msa).build();
+        let aligned = |seq: &[u8]| hmm.viterbi(seq).unwrap().aligned(seq);
+        assert_eq!(b"ACGT".to_vec(), aligned(b"ACGT"));
+        assert_eq!(b"ACaGT".to_vec(), aligned(b"ACAGT"));
+        assert_eq!(b"A-GT".to_vec(), aligned(b"AGT"));
+
+        let alignment = hmm.viterbi(b"ACAGT").unwrap();
+        let mut prob = 1.0;
+        let mut prev = State::Match(0);
+        let mut residues = b"ACAGT".iter();
+        for &state in &alignment.states {
+            prob *= hmm.transition(prev, state);
+            if !matches!(state, State::Delete(_)) {
+                prob *= hmm.emission(state, *residues.next().unwrap());
+            }
+            prev = state;
+        }
+        prob *= hmm.transition(prev, State::Match(hmm.len() + 1));
+        assert!((prob.ln() - alignment.log_prob).abs() < 1e-9);
+
+        let hmm = Builder::new(&msa).pseudocount(0.0).build();
+        assert_eq!(None, hmm.viterbi(b"TTTT"));
+        assert_eq!(f64::NEG_INFINITY, hmm.forward(b"TTTT"));
+    }
+
+    #[test]
+    fn test_forward_matches_paths() {
+        let msa = rows(&["AC-GT", "ACAGT", "A--GT", "AG-GT"]);
+        let hmm = Builder::new(&msa).pseudocount(0.5).build();
+        for seq in [&b""[..], b"A", b"CT", b"ACGT", b"AAGGT", b"TTCAGA", b"ANGT"] {
+            let (total, best) = paths(&hmm, seq, State::Match(0), 0);
+            assert!((total.ln() - hmm.forward(seq)).abs() < 1e-9);
+            assert!((best.ln() - hmm.viterbi(seq).unwrap().log_prob).abs() < 1e-9);
+            assert!(hmm.forward(seq) >= hmm.viterbi(seq).unwrap().log_prob);
+        }
+    }
+
+    #[test]
+    fn test_hmmer() {
+        let hmm = ProfileHmm::from_hmmer(TINY).unwrap();
+        assert_eq!("tiny", hmm.name());
+        assert_eq!(2, hmm.len());
+        assert_eq!(b"AT".to_vec(), hmm.consensus());
+        assert!((hmm.emission(State::Match(1), b'A') - 0.8).abs() < 1e-5);
+        assert!((hmm.transition(State::Match(2), State::Match(3)) - 0.95).abs() < 1e-5);
+        assert!((hmm.transition(State::Delete(1), State::Delete(2)) - 0.5).abs() < 1e-5);
+        assert_eq!(0.0, hmm.transition(State::Match(2), State::Delete(3)));
+        assert_eq!(b"AT".to_vec(), hmm.viterbi(b"AT").unwrap().aligned(b"AT"));
+
+        let msa = rows(&["AC-GT", "ACAGT", "A--GT", "AG-GT"]);
+        let built = Builder::new(&msa).name("toy").build();
+        let text = built.to_hmmer();
+        assert!(text.starts_with("HMMER3/f"));
+        assert!(text.contains("\nLENG  4\n"));
+        assert!(text.contains("\nALPH  DNA\n"));
+        assert!(text.ends_with("//\n"));
+        let parsed = ProfileHmm::from_hmmer(&text).unwrap();
+        assert_eq!(
+            ("toy", 4, Alphabet::Dna),
+            (parsed.name(), parsed.len(), parsed.alphabet())
+        );
+        for seq in [&b"ACGT"[..], b"AGT", b"CCATG"] {
+            assert!((built.forward(seq) - parsed.forward(seq)).abs() < 1e-4);
+        }
+        assert_eq!(text, parsed.to_hmmer());
+
+        assert!(matches!(
+            ProfileHmm::from_hmmer(""),
+            Err(HmmError::MissingHeader)
+        ));
+        assert!(matches!(
+            ProfileHmm::from_hmmer(&TINY.replace("DNA", "RNA")),
+            Err(HmmError::UnknownAlphabet(_))
+        ));
+        assert!(matches!(
+            ProfileHmm::from_hmmer(&TINY.replace("LENG  2", "LENG  3")),
+            Err(HmmError::BadLine(_))
+        ));
+        assert!(matches!(
+            ProfileHmm::from_hmmer(&TINY.replace("0.22314", "0.2x314")),
+            Err(HmmError::BadValue(_))
+        ));
+    }
+}
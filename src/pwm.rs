@@ -0,0 +1,755 @@
+use std::error::Error;
+use std::fmt::{Display, Write};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use itertools::Itertools;
+
+use crate::consensus_profile::Profile;
+
+const BASES: &[u8; 4] = b"ACGT";
+
+/// Scores are rounded to this many steps per bit for the exact score
+/// distribution.
+const SCORE_SCALE: f64 = 100.0;
+
+/// Floor on scores in the score distribution, for the bases a zero
+/// pseudocount makes impossible.
+const MIN_BITS: f64 = -30.0;
+
+/// Sites assumed behind a MEME matrix that gives no `nsites`.
+const DEFAULT_SITES: f64 = 20.0;
+
+#[derive(Debug)]
+pub enum PwmError {
+    Io(io::Error),
+    MissingMotif,
+    MissingMatrix(String),
+    BadLine(String),
+    BadValue(String),
+    BadBackground([f64; 4]),
+    BadPseudocount(f64),
+}
+
+impl Display for PwmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PwmError::Io(e) => write!(f, "could not read motifs: {}", e),
+            PwmError::MissingMotif => write!(f, "no motif found"),
+            PwmError::MissingMatrix(name) => write!(f, "no matrix for motif {}", name),
+            PwmError::BadLine(line) => write!(f, "malformed motif line: {}", line),
+            PwmError::BadValue(value) => write!(f, "invalid count: {}", value),
+            PwmError::BadBackground(freqs) => {
+                write!(f, "background frequencies must be positive: {:?}", freqs)
+            }
+            PwmError::BadPseudocount(pseudocount) => write!(
+                f,
+                "pseudocount {} must be positive, or zero with counts in every column",
+                pseudocount
+            ),
+        }
+    }
+}
+
+impl Error for PwmError {}
+
+impl From<io::Error> for PwmError {
+    fn from(e: io::Error) -> Self {
+        PwmError::Io(e)
+    }
+}
+
+fn base_index(residue: u8) -> Option<usize> {
+    BASES
+        .iter()
+        .position(|&base| base == residue.to_ascii_uppercase())
+}
+
+fn parse_value(value: &str) -> Result<f64, PwmError> {
+    match value.parse::<f64>() {
+        Ok(value) if value >= 0.0 => Ok(value),
+        _ => Err(PwmError::BadValue(value.to_owned())),
+    }
+}
+
+/**
+ * Turns counts of A, C, G and T at each position of a set of aligned
+ * binding sites into a position weight matrix. A total of `pseudocount`
+ * sites is spread over the bases by their background frequency before
+ * taking log-odds, so that bases never seen in a site are unlikely rather
+ * than impossible.
+ */
+pub struct Builder {
+    counts: Vec<[f64; 4]>,
+    name: String,
+    pseudocount: f64,
+    background: [f64; 4],
+}
+
+impl Builder {
+    pub fn new(counts: Vec<[f64; 4]>) -> Self {
+        Builder {
+            counts,
+            name: String::from("motif"),
+            pseudocount: 1.0,
+            background: [0.25; 4],
+        }
+    }
+
+    pub fn from_profile(profile: &Profile) -> Self {
+        Builder::new((0..profile.len()).map(|idx| profile.counts(idx)).collect())
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_owned();
+        self
+    }
+
+    /// Must not be negative, nor zero while a column has no counts, or
+    /// `build` fails.
+    pub fn pseudocount(mut self, pseudocount: f64) -> Self {
+        self.pseudocount = pseudocount;
+        self
+    }
+
+    /// Frequencies of A, C, G and T in the sequences to scan, normalised
+    /// to sum to one. Each must be positive, or `build` fails.
+    pub fn background(mut self, background: [f64; 4]) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn build(mut self) -> Result<Pwm, PwmError> {
+        if !self
+            .background
+            .iter()
+            .all(|freq| freq.is_finite() && *freq > 0.0)
+        {
+            return Err(PwmError::BadBackground(self.background));
+        }
+        // A column with neither counts nor pseudocounts has no frequencies.
+        let empty_column = || {
+            self.counts
+                .iter()
+                .any(|counts| counts.iter().sum::<f64>() <= 0.0)
+        };
+        if !(self.pseudocount.is_finite() && self.pseudocount >= 0.0)
+            || (self.pseudocount == 0.0 && empty_column())
+        {
+            return Err(PwmError::BadPseudocount(self.pseudocount));
+        }
+        let total: f64 = self.background.iter().sum();
+        self.background = self.background.map(|freq| freq / total);
+        let scores: Vec<[f64; 4]> = self
+            .counts
+            .iter()
+            .map(|counts| {
+                let total = counts.iter().sum::<f64>() + self.pseudocount;
+                [0, 1, 2, 3].map(|base| {
+                    let bg = self.background[base];
+                    let freq = (counts[base] + self.pseudocount * bg) / total;
+                    (freq / bg).log2()
+                })
+            })
+            .collect();
+        let rounded: Vec<[i64; 4]> = scores
+            .iter()
+            .map(|column| column.map(|score| (score.max(MIN_BITS) * SCORE_SCALE).round() as i64))
+            .collect();
+        let (min_rounded, tail) = score_tail(&rounded, &self.background);
+        Ok(Pwm {
+            name: self.name,
+            counts: self.counts,
+            background: self.background,
+            scores,
+            rounded,
+            min_rounded,
+            tail,
+        })
+    }
+}
+
+/**
+ * Exact distribution of the rounded score of a random site drawn from
+ * the background, as the smallest reachable score and the chance of
+ * scoring at least each score from it on. Each position adds its score
+ * to every sum reached so far, so this takes time proportional to the
+ * length of the motif times the range of scores.
+ */
+fn score_tail(rounded: &[[i64; 4]], background: &[f64; 4]) -> (i64, Vec<f64>) {
+    let mut dist = vec![1.0];
+    let mut min = 0;
+    for column in rounded {
+        let (lo, hi) = (*column.iter().min().unwrap(), *column.iter().max().unwrap());
+        let mut next = vec![0.0; dist.len() + (hi - lo) as usize];
+        for (sum, &prob) in dist.iter().enumerate().filter(|(_, &prob)| prob > 0.0) {
+            for (&score, &bg) in column.iter().zip(background) {
+                next[sum + (score - lo) as usize] += prob * bg;
+            }
+        }
+        dist = next;
+        min += lo;
+    }
+    let mut tail = dist;
+    for idx in (0..tail.len() - 1).rev() {
+        tail[idx] += tail[idx + 1];
+    }
+    (min, tail)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Strand {
+    Forward,
+    Reverse,
+}
+
+/// Which windows `Pwm::scan` reports.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Threshold {
+    /// Scoring at least this many bits.
+    Score(f64),
+    /// Scoring at least as well as this fraction of background sites.
+    PValue(f64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hit {
+    /// Start of the window on the forward strand.
+    pub start: usize,
+    pub strand: Strand,
+    pub score: f64,
+    pub p_value: f64,
+}
+
+/// Position weight matrix of log2-odds scores against a background.
+#[derive(Clone, Debug)]
+pub struct Pwm {
+    name: String,
+    counts: Vec<[f64; 4]>,
+    background: [f64; 4],
+    scores: Vec<[f64; 4]>,
+    rounded: Vec<[i64; 4]>,
+    min_rounded: i64,
+    /// `tail[idx]` is the chance of a rounded score of at least
+    /// `min_rounded + idx`.
+    tail: Vec<f64>,
+}
+
+impl Pwm {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn background(&self) -> [f64; 4] {
+        self.background
+    }
+
+    /// Counts of A, C, G and T at `pos` the matrix was built from.
+    pub fn counts(&self, pos: usize) -> [f64; 4] {
+        self.counts[pos]
+    }
+
+    /// Scores of A, C, G and T at `pos`, in bits.
+    pub fn scores(&self, pos: usize) -> [f64; 4] {
+        self.scores[pos]
+    }
+
+    pub fn max_score(&self) -> f64 {
+        self.scores
+            .iter()
+            .map(|column| column.iter().copied().fold(f64::MIN, f64::max))
+            .sum()
+    }
+
+    pub fn min_score(&self) -> f64 {
+        self.scores
+            .iter()
+            .map(|column| column.iter().copied().fold(f64::MAX, f64::min))
+            .sum()
+    }
+
+    /// Bases of `site` as indices on the given strand, `None` if one is
+    /// not A, C, G or T.
+    fn indices(&self, site: &[u8], strand: Strand) -> Option<Vec<usize>> {
+        let indices: Option<Vec<usize>> = site.iter().map(|&res| base_index(res)).collect();
+        match strand {
+            Strand::Forward => indices,
+            Strand::Reverse => indices.map(|idx| idx.iter().rev().map(|base| 3 - base).collect()),
+        }
+    }
+
+    /// Score of `site` read on `strand`, which needs to be as long as the
+    /// matrix and only hold A, C, G or T.
+    pub fn score(&self, site: &[u8], strand: Strand) -> Option<f64> {
+        if site.len() != self.len() {
+            return None;
+        }
+        let indices = self.indices(site, strand)?;
+        Some(site_score(&self.scores, &indices))
+    }
+
+    fn rounded_p_value(&self, rounded: i64) -> f64 {
+        match usize::try_from(rounded - self.min_rounded) {
+            Err(_) => 1.0,
+            Ok(idx) => self.tail.get(idx).map_or(0.0, |&p| p.min(1.0)),
+        }
+    }
+
+    /// Chance that a background site scores at least `score`, with scores
+    /// rounded to hundredths of a bit.
+    pub fn p_value(&self, score: f64) -> f64 {
+        self.rounded_p_value((score * SCORE_SCALE).round() as i64)
+    }
+
+    /// Lowest score whose p-value is at most `p_value`, above the best
+    /// score if there is none.
+    pub fn score_for_p_value(&self, p_value: f64) -> f64 {
+        let idx = self.tail.partition_point(|&p| p.min(1.0) > p_value);
+        (self.min_rounded + idx as i64) as f64 / SCORE_SCALE
+    }
+
+    /**
+     * Windows of `seq` scoring past `threshold` on either strand, by start
+     * and with the forward strand first. Windows holding anything but A,
+     * C, G or T are skipped. A p-value threshold compares the p-value of
+     * the rounded score of each window.
+     */
+    pub fn scan(&self, seq: &[u8], threshold: Threshold) -> Vec<Hit> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let mut hits = Vec::new();
+        for (start, window) in seq.windows(self.len()).enumerate() {
+            for strand in [Strand::Forward, Strand::Reverse] {
+                let Some(indices) = self.indices(window, strand) else {
+                    break;
+                };
+                let score = site_score(&self.scores, &indices);
+                let rounded = site_score(&self.rounded, &indices);
+                let p_value = self.rounded_p_value(rounded);
+                let passes = match threshold {
+                    Threshold::Score(min) => score >= min,
+                    Threshold::PValue(max) => p_value <= max,
+                };
+                if passes {
+                    hits.push(Hit {
+                        start,
+                        strand,
+                        score,
+                        p_value,
+                    });
+                }
+            }
+        }
+        hits
+    }
+
+    /// Frequencies of the bases at each position, from the counts alone.
+    fn frequencies(&self) -> impl Iterator<Item = [f64; 4]> + '_ {
+        self.counts.iter().map(|counts| {
+            let total: f64 = counts.iter().sum();
+            counts.map(|count| if total > 0.0 { count / total } else { 0.25 })
+        })
+    }
+
+    /// The counts in JASPAR format, a row for each base.
+    pub fn to_jaspar(&self) -> String {
+        let mut out = format!(">{}\n", self.name);
+        for (idx, &base) in BASES.iter().enumerate() {
+            let counts = self.counts.iter().map(|counts| counts[idx]).join(" ");
+            writeln!(out, "{}  [ {} ]", base as char, counts).unwrap();
+        }
+        out
+    }
+
+    /**
+     * Reads the motifs of a JASPAR file, each a `>` header followed by
+     * four rows of counts for A, C, G and T. Rows may be labelled with
+     * their base and bracketed, as in current JASPAR, or be bare numbers
+     * as in the older `.pfm` files.
+     */
+    pub fn from_jaspar(text: &str) -> Result<Vec<Pwm>, PwmError> {
+        let mut pwms = Vec::new();
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        while let Some(header) = lines.next() {
+            let name = header
+                .strip_prefix('>')
+                .ok_or_else(|| PwmError::BadLine(header.to_owned()))?;
+            let mut rows = Vec::new();
+            for &base in BASES {
+                let line = lines.next().ok_or(PwmError::MissingMotif)?;
+                let row = line
+                    .trim_start_matches(|ch: char| ch.to_ascii_uppercase() == base as char)
+                    .trim()
+                    .trim_start_matches('[')
+                    .trim_end_matches(']');
+                let row: Vec<f64> = row.split_whitespace().map(parse_value).try_collect()?;
+                rows.push(row);
+            }
+            if rows.iter().any(|row| row.len() != rows[0].len()) {
+                return Err(PwmError::BadLine(name.to_owned()));
+            }
+            let counts = (0..rows[0].len())
+                .map(|pos| [rows[0][pos], rows[1][pos], rows[2][pos], rows[3][pos]])
+                .collect();
+            pwms.push(Builder::new(counts).name(name.trim()).build()?);
+        }
+        if pwms.is_empty() {
+            return Err(PwmError::MissingMotif);
+        }
+        Ok(pwms)
+    }
+
+    /**
+     * Reads the motifs of a MEME file. The letter probabilities become
+     * counts out of `nsites` sites (20 if not given), and the background
+     * letter frequencies of the file, if any, are used for every motif.
+     */
+    pub fn from_meme(text: &str) -> Result<Vec<Pwm>, PwmError> {
+        let mut background = [0.25; 4];
+        let mut pwms = Vec::new();
+        let mut lines = text.lines().map(str::trim);
+        while let Some(line) = lines.next() {
+            if line.starts_with("Background letter frequencies") {
+                let freqs = lines.next().ok_or(PwmError::MissingMotif)?;
+                let fields: Vec<&str> = freqs.split_whitespace().collect();
+                for pair in fields.chunks(2) {
+                    let [base, freq] = pair else {
+                        return Err(PwmError::BadLine(freqs.to_owned()));
+                    };
+                    let idx = base
+                        .bytes()
+                        .next()
+                        .and_then(base_index)
+                        .ok_or_else(|| PwmError::BadLine(freqs.to_owned()))?;
+                    background[idx] = parse_value(freq)?;
+                }
+            } else if let Some(name) = line.strip_prefix("MOTIF") {
+                // Stop at the next motif, so it keeps its own matrix.
+                let header = lines
+                    .by_ref()
+                    .take_while(|line| !line.starts_with("MOTIF"))
+                    .find(|line| line.starts_with("letter-probability matrix"))
+                    .ok_or_else(|| PwmError::MissingMatrix(name.trim().to_owned()))?;
+                let field = |key: &str| {
+                    let mut fields = header.split_whitespace();
+                    fields.find(|&field| field == key)?;
+                    fields.next()
+                };
+                let width: usize = field("w=")
+                    .and_then(|width| width.parse().ok())
+                    .ok_or_else(|| PwmError::BadLine(header.to_owned()))?;
+                let sites = field("nsites=").map_or(Ok(DEFAULT_SITES), parse_value)?;
+                let mut counts = Vec::with_capacity(width);
+                for _ in 0..width {
+                    let row = lines.next().ok_or(PwmError::MissingMotif)?;
+                    let probs: Vec<f64> = row.split_whitespace().map(parse_value).try_collect()?;
+                    let [a, c, g, t] = probs[..] else {
+                        return Err(PwmError::BadLine(row.to_owned()));
+                    };
+                    counts.push([a, c, g, t].map(|prob| prob * sites));
+                }
+                pwms.push(Builder::new(counts).name(name.trim()));
+            }
+        }
+        if pwms.is_empty() {
+            return Err(PwmError::MissingMotif);
+        }
+        pwms.into_iter()
+            .map(|builder| builder.background(background).build())
+            .collect()
+    }
+
+    /// Reads a MEME or a JASPAR file, told apart by the MEME version line.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Vec<Pwm>, PwmError> {
+        let text = fs::read_to_string(path)?;
+        if text.trim_start().starts_with("MEME version") {
+            Pwm::from_meme(&text)
+        } else {
+            Pwm::from_jaspar(&text)
+        }
+    }
+}
+
+/// Score of the bases at `indices` along `matrix`.
+fn site_score<T: Copy + std::iter::Sum<T>>(matrix: &[[T; 4]], indices: &[usize]) -> T {
+    matrix
+        .iter()
+        .zip(indices)
+        .map(|(column, &base)| column[base])
+        .sum()
+}
+
+/// Motifs in MEME's minimal format, with the background of the first.
+pub fn to_meme(pwms: &[Pwm]) -> String {
+    let background = pwms.first().map_or([0.25; 4], |pwm| pwm.background);
+    let mut out = String::from("MEME version 4\n\nALPHABET= ACGT\n\nstrands: + -\n\n");
+    writeln!(out, "Background letter frequencies").unwrap();
+    let freqs = BASES
+        .iter()
+        .zip(background)
+        .map(|(&base, freq)| format!("{} {:.6}", base as char, freq))
+        .join(" ");
+    writeln!(out, "{}", freqs).unwrap();
+    for pwm in pwms {
+        let sites: f64 = pwm.counts.first().map_or(0.0, |counts| counts.iter().sum());
+        writeln!(out, "\nMOTIF {}", pwm.name).unwrap();
+        writeln!(
+            out,
+            "letter-probability matrix: alength= 4 w= {} nsites= {} E= 0",
+            pwm.len(),
+            sites
+        )
+        .unwrap();
+        for freqs in pwm.frequencies() {
+            writeln!(
+                out,
+                " {}",
+                freqs.iter().map(|freq| format!("{:.6}", freq)).join(" ")
+            )
+            .unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, random_dna};
+
+    const ARNT: &str = ">MA0004.1 Arnt
+A  [ 4 19  0  0  0  0 ]
+C  [16  0 20  0  0  0 ]
+G  [ 0  1  0 20  0 20 ]
+T  [ 0  0  0  0 20  0 ]
+";
+
+    fn arnt() -> Pwm {
+        Pwm::from_jaspar(ARNT).unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_build() {
+        let mut profile = Profile::new();
+        for site in [b"CACGTG", b"CACGTG", b"AACGTG", b"CACGTG"] {
+            profile.add_seq(site).unwrap();
+        }
+        let pwm = Builder::from_profile(&profile)
+            .pseudocount(0.0)
+            .build()
+            .unwrap();
+        assert_eq!(6, pwm.len());
+        assert_eq!(2.0, pwm.scores(2)[1]);
+        assert_eq!(f64::NEG_INFINITY, pwm.scores(2)[0]);
+        assert_eq!(0.0, pwm.scores(0)[0]);
+        assert_eq!(0.0, pwm.p_value(pwm.max_score() + 0.01));
+
+        let pwm = Builder::from_profile(&profile).build().unwrap();
+        // (0 + 0.25) / 5 against a quarter.
+        assert!((pwm.scores(2)[0] - (0.2f64).log2()).abs() < 1e-12);
+        assert!((pwm.scores(2)[1] - (4.25f64 / 5.0 / 0.25).log2()).abs() < 1e-12);
+        assert!((pwm.max_score() - pwm.score(b"CACGTG", Strand::Forward).unwrap()).abs() < 1e-12);
+
+        let skewed = Builder::from_profile(&profile)
+            .background([3.0, 1.0, 1.0, 3.0])
+            .build()
+            .unwrap();
+        assert_eq!([0.375, 0.125, 0.125, 0.375], skewed.background());
+        assert!(skewed.scores(2)[1] > pwm.scores(2)[1]);
+        for pseudocount in [-1.0, f64::NAN] {
+            assert!(matches!(
+                Builder::from_profile(&profile)
+                    .pseudocount(pseudocount)
+                    .build(),
+                Err(PwmError::BadPseudocount(_))
+            ));
+        }
+        let empty = Builder::new(vec![[1.0, 0.0, 0.0, 0.0], [0.0; 4]]);
+        assert!(matches!(
+            empty.pseudocount(0.0).build(),
+            Err(PwmError::BadPseudocount(_))
+        ));
+        for background in [[0.5, 0.0, 0.25, 0.25], [-1.0; 4]] {
+            assert!(matches!(
+                Builder::from_profile(&profile)
+                    .background(background)
+                    .build(),
+                Err(PwmError::BadBackground(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_p_value() {
+        let pwm = Builder::new(vec![
+            [5.0, 1.0, 0.0, 2.0],
+            [0.0, 0.0, 8.0, 0.0],
+            [3.0, 3.0, 1.0, 1.0],
+        ])
+        .background([0.3, 0.2, 0.2, 0.3])
+        .build()
+        .unwrap();
+        // Every site of the background, with its chance and rounded score.
+        let sites: Vec<(f64, i64)> = (0..3)
+            .map(|_| 0..4)
+            .multi_cartesian_product()
+            .map(|site| {
+                let prob = site.iter().map(|&base| pwm.background()[base]).product();
+                (prob, site_score(&pwm.rounded, &site))
+            })
+            .collect();
+        for &(_, score) in &sites {
+            let naive: f64 = sites
+                .iter()
+                .filter(|&&(_, other)| other >= score)
+                .map(|&(prob, _)| prob)
+                .sum();
+            let score = score as f64 / SCORE_SCALE;
+            assert!((naive - pwm.p_value(score)).abs() < 1e-12);
+            assert!(pwm.score_for_p_value(pwm.p_value(score)) <= score + 1e-9);
+        }
+        assert_eq!(1.0, pwm.p_value(pwm.min_score() - 1.0));
+        assert_eq!(0.0, pwm.p_value(pwm.max_score() + 1.0));
+        assert!(pwm.score_for_p_value(0.0) > pwm.max_score());
+    }
+
+    #[test]
+    fn test_scan() {
+        let pwm = arnt();
+        let mut seq = random_dna(&mut testing::rng(17), 200);
+        seq[40..46].copy_from_slice(b"CACGTG");
+        seq[120..126].copy_from_slice(b"CACGTA");
+        // The reverse complement of CACGTA.
+        seq[150..156].copy_from_slice(b"TACGTG");
+        let best = pwm.max_score();
+        let hits = pwm.scan(&seq, Threshold::Score(best - 1e-9));
+        let found: Vec<(usize, Strand)> = hits.iter().map(|hit| (hit.start, hit.strand)).collect();
+        // CACGTG is its own reverse complement.
+        assert_eq!(vec![(40, Strand::Forward), (40, Strand::Reverse)], found);
+
+        // One mismatch against the consensus, at a position it allows.
+        let score = pwm.score(b"CACGTA", Strand::Forward).unwrap();
+        let p_value = pwm.p_value(score);
+        assert!(p_value < 0.01);
+        let hits = pwm.scan(&seq, Threshold::PValue(p_value));
+        let found: Vec<(usize, Strand)> = hits.iter().map(|hit| (hit.start, hit.strand)).collect();
+        assert!(found.contains(&(120, Strand::Forward)));
+        assert!(found.contains(&(150, Strand::Reverse)));
+        assert!(hits.iter().all(|hit| hit.p_value <= p_value));
+        let hit = hits
+            .iter()
+            .find(|hit| (hit.start, hit.strand) == (150, Strand::Reverse))
+            .unwrap();
+        assert!((score - hit.score).abs() < 1e-9);
+
+        assert!(pwm.scan(b"CACNTG", Threshold::Score(f64::MIN)).is_empty());
+        assert_eq!(None, pwm.score(b"CACGT", Strand::Forward));
+    }
+
+    #[test]
+    fn test_jaspar() {
+        let pwm = arnt();
+        assert_eq!("MA0004.1 Arnt", pwm.name());
+        assert_eq!([4.0, 16.0, 0.0, 0.0], pwm.counts(0));
+        assert_eq!(
+            ">MA0004.1 Arnt\n\
+             A  [ 4 19 0 0 0 0 ]\n\
+             C  [ 16 0 20 0 0 0 ]\n\
+             G  [ 0 1 0 20 0 20 ]\n\
+             T  [ 0 0 0 0 20 0 ]\n",
+            pwm.to_jaspar()
+        );
+        let pfm = ">MA0004.1 Arnt\n4 19 0 0 0 0\n16 0 20 0 0 0\n0 1 0 20 0 20\n0 0 0 0 20 0\n";
+        assert_eq!(
+            pwm.to_jaspar(),
+            Pwm::from_jaspar(pfm).unwrap()[0].to_jaspar()
+        );
+        let two = format!("{}\n{}", ARNT, pwm.to_jaspar());
+        assert_eq!(2, Pwm::from_jaspar(&two).unwrap().len());
+
+        assert!(matches!(
+            Pwm::from_jaspar(">x\nA [ 1 2 ]\nC [ 1 x ]\nG [ 0 0 ]\nT [ 0 0 ]"),
+            Err(PwmError::BadValue(_))
+        ));
+        assert!(matches!(
+            Pwm::from_jaspar(">x\nA [ 1 2 ]\nC [ 1 ]\nG [ 0 0 ]\nT [ 0 0 ]"),
+            Err(PwmError::BadLine(_))
+        ));
+        assert!(matches!(Pwm::from_jaspar(""), Err(PwmError::MissingMotif)));
+    }
+
+    #[test]
+    fn test_meme() {
+        let text = "MEME version 4
+
+ALPHABET= ACGT
+
+strands: + -
+
+Background letter frequencies
+A 0.3 C 0.2 G 0.2 T 0.3
+
+MOTIF MA0004.1 Arnt
+letter-probability matrix: alength= 4 w= 2 nsites= 20 E= 0
+ 0.200000 0.800000 0.000000 0.000000
+ 0.950000 0.000000 0.050000 0.000000
+
+MOTIF second
+letter-probability matrix: alength= 4 w= 1
+ 0.5 0.5 0 0
+";
+        let pwms = Pwm::from_meme(text).unwrap();
+        assert_eq!(2, pwms.len());
+        assert_eq!("MA0004.1 Arnt", pwms[0].name());
+        assert_eq!([4.0, 16.0, 0.0, 0.0], pwms[0].counts(0));
+        assert_eq!([10.0, 10.0, 0.0, 0.0], pwms[1].counts(0));
+        assert_eq!([0.3, 0.2, 0.2, 0.3], pwms[1].background());
+
+        let written = to_meme(&pwms);
+        assert!(written.contains("A 0.300000 C 0.200000 G 0.200000 T 0.300000"));
+        assert!(written.contains("letter-probability matrix: alength= 4 w= 2 nsites= 20 E= 0\n"));
+        let reread = Pwm::from_meme(&written).unwrap();
+        for (pwm, other) in pwms.iter().zip(&reread) {
+            assert_eq!(pwm.name(), other.name());
+            assert_eq!(pwm.background(), other.background());
+            for pos in 0..pwm.len() {
+                for (fst, snd) in pwm.counts(pos).iter().zip(other.counts(pos)) {
+                    assert!((fst - snd).abs() < 1e-4);
+                }
+            }
+        }
+
+        let jaspar = arnt();
+        let meme = Pwm::from_meme(&to_meme(std::slice::from_ref(&jaspar)))
+            .unwrap()
+            .remove(0);
+        for (fst, snd) in jaspar.counts(1).iter().zip(meme.counts(1)) {
+            assert!((fst - snd).abs() < 1e-9);
+        }
+        assert!(matches!(
+            Pwm::from_meme("MEME version 4\n"),
+            Err(PwmError::MissingMotif)
+        ));
+        let unmatched = "MOTIF first\n\nMOTIF second\n\
+                         letter-probability matrix: alength= 4 w= 1\n0.5 0.5 0 0\n";
+        assert!(matches!(
+            Pwm::from_meme(unmatched),
+            Err(PwmError::MissingMatrix(name)) if name == "first"
+        ));
+        let zero = text.replace("A 0.3 C 0.2", "A 0.5 C 0");
+        assert!(matches!(
+            Pwm::from_meme(&zero),
+            Err(PwmError::BadBackground(_))
+        ));
+    }
+}
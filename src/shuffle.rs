@@ -0,0 +1,147 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// Kind of random permutation used to build a null model for a sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shuffle {
+    /// Any permutation of the residues, keeping only their counts.
+    Mononucleotide,
+    /// A permutation keeping the count of every pair of adjacent residues.
+    Dinucleotide,
+}
+
+impl Shuffle {
+    pub fn apply<R: Rng>(self, seq: &[u8], rng: &mut R) -> Vec<u8> {
+        match self {
+            Shuffle::Mononucleotide => mononucleotide(seq, rng),
+            Shuffle::Dinucleotide => dinucleotide(seq, rng),
+        }
+    }
+}
+
+pub fn mononucleotide<R: Rng>(seq: &[u8], rng: &mut R) -> Vec<u8> {
+    let mut shuffled = seq.to_vec();
+    shuffled.shuffle(rng);
+    shuffled
+}
+
+/**
+ * Altschul and Erickson's dinucleotide shuffle. The sequence is a walk
+ * through the graph with one edge for every pair of adjacent residues, and
+ * the result is a uniformly random Eulerian walk with the same first and
+ * last residue. Such a walk is fixed by the last edge it leaves each
+ * residue by, which must form a tree pointing at the last residue, and by
+ * the order of the remaining edges out of each residue.
+ */
+pub fn dinucleotide<R: Rng>(seq: &[u8], rng: &mut R) -> Vec<u8> {
+    let (first, last) = match (seq.first(), seq.last()) {
+        (Some(&first), Some(&last)) if seq.len() > 2 => (first, last),
+        _ => return seq.to_vec(),
+    };
+    let mut edges: Vec<Vec<u8>> = vec![Vec::new(); 256];
+    for pair in seq.windows(2) {
+        edges[pair[0] as usize].push(pair[1]);
+    }
+    let residues: Vec<u8> = (0..=255)
+        .filter(|&ch| ch != last && !edges[ch as usize].is_empty())
+        .collect();
+
+    // Pick a random last edge out of every residue until they form a tree,
+    // which happens with probability at least one over the number of
+    // residues for the usual small alphabets.
+    let mut last_edges = [0u8; 256];
+    loop {
+        for &ch in &residues {
+            let out = &edges[ch as usize];
+            last_edges[ch as usize] = out[rng.gen_range(0..out.len())];
+        }
+        let reaches_last = |start: u8| {
+            let mut ch = start;
+            for _ in 0..residues.len() {
+                if ch == last {
+                    return true;
+                }
+                ch = last_edges[ch as usize];
+            }
+            ch == last
+        };
+        if residues.iter().all(|&ch| reaches_last(ch)) {
+            break;
+        }
+    }
+
+    for &ch in &residues {
+        let out = &mut edges[ch as usize];
+        let idx = out
+            .iter()
+            .position(|&next| next == last_edges[ch as usize])
+            .unwrap();
+        let last_edge = out.swap_remove(idx);
+        out.shuffle(rng);
+        out.push(last_edge);
+    }
+    edges[last as usize].shuffle(rng);
+
+    let mut positions = vec![0; 256];
+    let mut shuffled = Vec::with_capacity(seq.len());
+    let mut ch = first;
+    shuffled.push(ch);
+    for _ in 1..seq.len() {
+        let next = edges[ch as usize][positions[ch as usize]];
+        positions[ch as usize] += 1;
+        shuffled.push(next);
+        ch = next;
+    }
+    shuffled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, random_dna};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    fn pair_counts(seq: &[u8]) -> HashMap<(u8, u8), usize> {
+        let mut counts = HashMap::new();
+        for pair in seq.windows(2) {
+            *counts.entry((pair[0], pair[1])).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_mononucleotide() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let seq = random_dna(&mut testing::rng(5), 100);
+        let shuffled = mononucleotide(&seq, &mut rng);
+        assert_ne!(seq, shuffled);
+        let (mut sorted, mut sorted_shuffled) = (seq.clone(), shuffled);
+        sorted.sort_unstable();
+        sorted_shuffled.sort_unstable();
+        assert_eq!(sorted, sorted_shuffled);
+    }
+
+    #[test]
+    fn test_dinucleotide() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for len in [3, 10, 100, 500] {
+            let seq = random_dna(&mut rng, len);
+            for _ in 0..10 {
+                let shuffled = dinucleotide(&seq, &mut rng);
+                assert_eq!(seq.len(), shuffled.len());
+                assert_eq!(seq[0], shuffled[0]);
+                assert_eq!(seq[len - 1], shuffled[len - 1]);
+                assert_eq!(pair_counts(&seq), pair_counts(&shuffled));
+            }
+        }
+        let seq = random_dna(&mut rng, 200);
+        assert_ne!(seq, dinucleotide(&seq, &mut rng));
+        assert_eq!(b"AC".to_vec(), dinucleotide(b"AC", &mut rng));
+        assert_eq!(
+            b"AAAA".to_vec(),
+            Shuffle::Dinucleotide.apply(b"AAAA", &mut rng)
+        );
+    }
+}
@@ -1,64 +1,281 @@
-use itertools::Itertools;
-
-pub fn spliced_motif(target: &[u8], mut query: &[u8]) -> Vec<usize> {
-    let mut acc = Vec::new();
-
-    let idx_target = target.iter().zip(1..=target.len());
-    for (&ch, idx) in idx_target {
-        if ch == query[0] {
-            acc.push(idx);
-            if query.len() == 1 {
-                break;
-            } else {
-                query = &query[1..];
+use std::ops::Range;
+
+use crate::lcs_splicing;
+
+/**
+ * First embedding of `query` as a subsequence of `target`, found greedily:
+ * each residue of `query` is matched at the first position after the
+ * previous one. Positions are 1-based as Rosalind's SSEQ problem expects,
+ * and `None` means `query` is not a subsequence of `target`.
+ */
+pub fn spliced_motif(target: &[u8], query: &[u8]) -> Option<Vec<usize>> {
+    let mut positions = target.iter().zip(1..);
+    query
+        .iter()
+        .map(|ch| positions.find(|&(x, _)| x == ch).map(|(_, idx)| idx))
+        .collect()
+}
+
+/// Number of embeddings of `query` in `target`, i.e. of increasing lists
+/// of positions spelling `query`, or `None` if it overflows a `u128`.
+pub fn count_embeddings(target: &[u8], query: &[u8]) -> Option<u128> {
+    // A count that overflowed only reaches the result by overflowing it.
+    count_with(target, query, Some(0), Some(1), |ways, more| {
+        ways?.checked_add(more?)
+    })
+}
+
+/// Number of embeddings of `query` in `target` modulo `modulus`, for
+/// counts too large for any integer type. Panics if `modulus` is zero.
+pub fn count_embeddings_mod(target: &[u8], query: &[u8], modulus: u64) -> u64 {
+    assert!(modulus > 0, "count_embeddings_mod needs a positive modulus");
+    let count = count_with(target, query, 0, 1, |ways, more| {
+        (ways + more) % modulus as u128
+    });
+    count as u64 % modulus
+}
+
+/// `ways[k]` counts the embeddings of `query[..k]` in the part of `target`
+/// read so far, going down so each position is used once per update.
+fn count_with<T: Copy>(target: &[u8], query: &[u8], zero: T, one: T, add: impl Fn(T, T) -> T) -> T {
+    let mut ways = vec![zero; query.len() + 1];
+    ways[0] = one;
+    for ch in target {
+        for k in (1..=query.len()).rev() {
+            if query[k - 1] == *ch {
+                ways[k] = add(ways[k], ways[k - 1]);
             }
         }
     }
-    acc
+    ways[query.len()]
 }
 
-fn spliced(pattern: &[u8]) -> impl Iterator<Item = &[u8]> {
-    (1..=pattern.len()).flat_map(move |len| pattern.windows(len))
+/**
+ * Shortest window of `target`, as a 0-based half-open range, containing
+ * `query` as a subsequence, the leftmost one on ties. Each embedding found
+ * greedily forward is tightened greedily backward from its end, and the
+ * search restarts just after the tightened start.
+ */
+pub fn shortest_window(target: &[u8], query: &[u8]) -> Option<Range<usize>> {
+    let (first, last) = match (query.first(), query.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Some(0..0),
+    };
+    let mut best: Option<Range<usize>> = None;
+    let mut start = 0;
+    while let Some(offset) = target[start..].iter().position(|ch| ch == first) {
+        let mut end = start + offset;
+        for ch in query {
+            match target[end..].iter().position(|x| x == ch) {
+                Some(offset) => end += offset + 1,
+                None => return best,
+            }
+        }
+        debug_assert_eq!(last, &target[end - 1]);
+        let mut window_start = end;
+        for ch in query.iter().rev() {
+            window_start = target[..window_start]
+                .iter()
+                .rposition(|x| x == ch)
+                .unwrap();
+        }
+        if best
+            .as_ref()
+            .is_none_or(|best| end - window_start < best.len())
+        {
+            best = Some(window_start..end);
+        }
+        start = window_start + 1;
+    }
+    best
 }
 
-pub fn shared_spliced_motif(target: &[u8], query: &[u8]) -> String {
-    let t_indices = spliced(query).map(|qsub| (true, spliced_motif(target, qsub)));
-    let q_indices = spliced(target).map(|tsub| (false, spliced_motif(query, tsub)));
-    let (is_target, indices) = q_indices
-        .chain(t_indices)
-        .max_by_key(|(_, idxs)| idxs.len())
-        .unwrap();
-    if is_target {
-        indices
-            .into_iter()
-            .map(|idx| target[idx - 1] as char)
-            .join("")
-    } else {
-        indices
-            .into_iter()
-            .map(|idx| query[idx - 1] as char)
-            .join("")
+/// Every embedding of `query` in `target`, lazily and in lexicographic
+/// order, with the same 1-based positions as `spliced_motif`.
+pub fn embeddings<'a>(target: &'a [u8], query: &'a [u8]) -> Embeddings<'a> {
+    // Latest position each residue can take with the rest of `query` still
+    // fitting after it, found greedily from the back.
+    let mut latest = vec![0; query.len()];
+    let mut end = target.len();
+    let mut possible = true;
+    for (k, ch) in query.iter().enumerate().rev() {
+        match target[..end].iter().rposition(|x| x == ch) {
+            Some(pos) => {
+                latest[k] = pos;
+                end = pos;
+            }
+            None => possible = false,
+        }
+    }
+    Embeddings {
+        target,
+        query,
+        latest,
+        current: Vec::new(),
+        done: !possible,
     }
 }
 
+pub struct Embeddings<'a> {
+    target: &'a [u8],
+    query: &'a [u8],
+    latest: Vec<usize>,
+    /// Last embedding returned, 0-based.
+    current: Vec<usize>,
+    done: bool,
+}
+
+impl Embeddings<'_> {
+    /// Next position of `query[k]` after `after`, if it leaves room for the
+    /// rest of `query`.
+    fn next_position(&self, k: usize, after: Option<usize>) -> Option<usize> {
+        let from = after.map_or(0, |pos| pos + 1);
+        let ch = self.query[k];
+        (from..=self.latest[k]).find(|&pos| self.target[pos] == ch)
+    }
+
+    /// Completes `current` from position `k` on with the earliest positions.
+    fn fill_from(&mut self, k: usize) {
+        self.current.truncate(k);
+        for k in k..self.query.len() {
+            let after = k.checked_sub(1).map(|prev| self.current[prev]);
+            let pos = self.next_position(k, after).unwrap();
+            self.current.push(pos);
+        }
+    }
+}
+
+impl Iterator for Embeddings<'_> {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        if self.done {
+            return None;
+        }
+        if self.current.len() < self.query.len() || self.query.is_empty() {
+            // First call: the greedy embedding.
+            self.fill_from(0);
+            self.done = self.query.is_empty();
+        } else {
+            // Move the last residue that can go further right, then pack
+            // the ones after it as far left as possible.
+            let moved = (0..self.query.len()).rev().find_map(|k| {
+                self.next_position(k, Some(self.current[k]))
+                    .map(|pos| (k, pos))
+            });
+            match moved {
+                Some((k, pos)) => {
+                    self.current[k] = pos;
+                    self.fill_from(k + 1);
+                }
+                None => {
+                    self.done = true;
+                    return None;
+                }
+            }
+        }
+        Some(self.current.iter().map(|pos| pos + 1).collect())
+    }
+}
+
+/// A longest common subsequence of `target` and `query`, the shared
+/// spliced motif of Rosalind's LCSQ problem.
+pub fn shared_spliced_motif(target: &[u8], query: &[u8]) -> String {
+    lcs_splicing::longest_common_subsequence(target, query).as_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{self, random_dna};
+    use itertools::Itertools;
+    use rand::Rng;
 
     #[test]
     fn test_spliced_motif() {
         let target = b"ACGTACGTGACG";
         let query = b"GTA";
         let indices = spliced_motif(target, query);
-        assert_eq!(vec![3, 4, 5], indices);
+        assert_eq!(Some(vec![3, 4, 5]), indices);
+        assert_eq!(Some(vec![]), spliced_motif(target, b""));
+        assert_eq!(None, spliced_motif(target, b"GTAAA"));
+        assert_eq!(None, spliced_motif(b"", b"A"));
+    }
+
+    /// Every embedding of `query` in `target`, by trying every set of
+    /// positions.
+    fn brute_force(target: &[u8], query: &[u8]) -> Vec<Vec<usize>> {
+        (0..target.len())
+            .map(|pos| pos + 1)
+            .combinations(query.len())
+            .filter(|positions| {
+                positions
+                    .iter()
+                    .zip(query)
+                    .all(|(&pos, &ch)| target[pos - 1] == ch)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_embeddings() {
+        let mut rng = testing::rng(21);
+        for _ in 0..30 {
+            let target = random_dna(&mut rng, 12);
+            let query_len = rng.gen_range(0..5);
+            let query = random_dna(&mut rng, query_len);
+            let expected = brute_force(&target, &query);
+            assert_eq!(expected, embeddings(&target, &query).collect::<Vec<_>>());
+            assert_eq!(
+                Some(expected.len() as u128),
+                count_embeddings(&target, &query)
+            );
+            assert_eq!(expected.first().cloned(), spliced_motif(&target, &query));
+
+            let window = expected
+                .iter()
+                .map(|positions| match (positions.first(), positions.last()) {
+                    (Some(&first), Some(&last)) => first - 1..last,
+                    _ => 0..0,
+                })
+                .min_by_key(|window| (window.len(), window.start));
+            assert_eq!(window, shortest_window(&target, &query));
+        }
+        assert_eq!(
+            vec![Vec::<usize>::new()],
+            embeddings(b"AC", b"").collect::<Vec<_>>()
+        );
+        assert_eq!(0, embeddings(b"AC", b"CA").count());
+        assert_eq!(Some(0..3), shortest_window(b"ACGTACGTGACG", b"ACG"));
+        assert_eq!(Some(8..11), shortest_window(b"ACGTACGTGACG", b"GAC"));
+        assert_eq!(Some(1..3), shortest_window(b"ACGTACGTGACG", b"CG"));
+    }
+
+    #[test]
+    fn test_count_embeddings() {
+        assert_eq!(Some(1), count_embeddings(b"ACGT", b""));
+        assert_eq!(Some(0), count_embeddings(b"ACGT", b"TA"));
+        let target = vec![b'A'; 2000];
+        assert_eq!(Some(1999 * 1000), count_embeddings(&target, b"AA"));
+        assert_eq!(999_000, count_embeddings_mod(&target, b"AA", 1_000_000));
+        let query = vec![b'A'; 1000];
+        assert!(count_embeddings(&target, &query).is_none());
+        // Overflowing partial counts that never finish an embedding.
+        let unmatched = [query.as_slice(), b"C"].concat();
+        assert_eq!(Some(0), count_embeddings(&target, &unmatched));
+        // C(2000, 1000) is divisible by 7, by Kummer's theorem.
+        assert_eq!(0, count_embeddings_mod(&target, &query, 7));
+        let (target, query) = (&target[..100], &query[..50]);
+        assert_eq!(
+            (count_embeddings(target, query).unwrap() % 1_000_000) as u64,
+            count_embeddings_mod(target, query, 1_000_000)
+        );
     }
 
     #[test]
-    fn test_spliced() {
-        let target = b"ABC";
-        let result: Vec<&[u8]> = spliced(target).collect();
-        let answer: Vec<&[u8]> = vec![b"A", b"B", b"C", b"AB", b"BC", b"ABC"];
-        assert_eq!(answer, result);
+    #[should_panic(expected = "positive modulus")]
+    fn test_count_embeddings_zero_modulus() {
+        count_embeddings_mod(b"ACGT", b"A", 0);
     }
 
     #[test]
@@ -66,6 +283,9 @@ mod tests {
         let target = b"AACCTTGG";
         let query = b"ACACTGTGA";
         let shared = shared_spliced_motif(target, query);
-        assert_eq!(String::from("ACCTTG"), shared);
+        // ACCTTG is as long; ties may go either way.
+        assert_eq!(6, shared.len());
+        assert!(spliced_motif(target, shared.as_bytes()).is_some());
+        assert!(spliced_motif(query, shared.as_bytes()).is_some());
     }
 }
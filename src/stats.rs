@@ -0,0 +1,493 @@
+use std::f64::consts::LN_2;
+use std::thread;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::shuffle::Shuffle;
+use crate::substitution::SubstitutionMatrix;
+
+/// Amino acid background frequencies of Robinson and Robinson (1991), the
+/// ones BLAST uses for its published parameters.
+pub const ROBINSON_FREQUENCIES: [(u8, f64); 20] = [
+    (b'A', 0.07805),
+    (b'C', 0.01925),
+    (b'D', 0.05364),
+    (b'E', 0.06295),
+    (b'F', 0.03856),
+    (b'G', 0.07377),
+    (b'H', 0.02199),
+    (b'I', 0.05142),
+    (b'K', 0.05744),
+    (b'L', 0.09019),
+    (b'M', 0.02243),
+    (b'N', 0.04487),
+    (b'P', 0.05203),
+    (b'Q', 0.04264),
+    (b'R', 0.05129),
+    (b'S', 0.07120),
+    (b'T', 0.05841),
+    (b'V', 0.06441),
+    (b'W', 0.01330),
+    (b'Y', 0.03216),
+];
+
+/// Gapped BLOSUM62 parameters published with BLAST, as
+/// `(gap_open, linear_gap_penalty, lambda, k, h)`.
+const BLOSUM62_GAPPED: [(isize, isize, f64, f64, f64); 11] = [
+    (-11, -2, 0.297, 0.082, 0.27),
+    (-10, -2, 0.291, 0.075, 0.23),
+    (-9, -2, 0.279, 0.058, 0.19),
+    (-8, -2, 0.264, 0.045, 0.15),
+    (-7, -2, 0.239, 0.027, 0.10),
+    (-6, -2, 0.201, 0.012, 0.061),
+    (-13, -1, 0.292, 0.071, 0.23),
+    (-12, -1, 0.283, 0.059, 0.19),
+    (-11, -1, 0.267, 0.041, 0.14),
+    (-10, -1, 0.243, 0.024, 0.10),
+    (-9, -1, 0.206, 0.010, 0.052),
+];
+
+/// Terms of the series for `k` after which the estimate is left as is.
+const MAX_ITERATIONS: usize = 100;
+
+/// Size below which the terms of the series for `k` no longer matter.
+const TOLERANCE: f64 = 1e-10;
+
+/**
+ * Karlin-Altschul statistics for local alignment scores: the number of
+ * distinct local alignments scoring at least `S` between random sequences
+ * of lengths `m` and `n` is close to Poisson with mean
+ * `E = k * m * n * exp(-lambda * S)`. `h` is the relative entropy of the
+ * scoring system in nats per aligned pair.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KarlinAltschul {
+    pub lambda: f64,
+    pub k: f64,
+    pub h: f64,
+}
+
+impl KarlinAltschul {
+    /**
+     * Parameters for ungapped alignment with `matrix`, for residues drawn
+     * independently from `background`. Residues missing from `background`
+     * never occur. `None` if the scoring system is not fit for local
+     * alignment: the expected score of a pair must be negative and some
+     * pair must score above zero.
+     */
+    pub fn ungapped(matrix: &SubstitutionMatrix, background: &[(u8, f64)]) -> Option<Self> {
+        let dist = ScoreDistribution::new(matrix, background)?;
+        let lambda = dist.lambda()?;
+        let h = lambda
+            * dist
+                .scores()
+                .map(|(score, prob)| prob * score as f64 * (lambda * score as f64).exp())
+                .sum::<f64>();
+        let k = dist.k(lambda, h);
+        Some(KarlinAltschul { lambda, k, h })
+    }
+
+    /**
+     * Published parameters for gapped alignment with BLOSUM62, from
+     * simulations, for the gap penalties BLAST supports. A gap of length
+     * `len` scores `gap_open + len * linear_gap_penalty`, as in
+     * `pairwise::Builder`.
+     */
+    pub fn blosum62_gapped(gap_open: isize, linear_gap_penalty: isize) -> Option<Self> {
+        BLOSUM62_GAPPED
+            .iter()
+            .find(|&&(open, linear, ..)| (open, linear) == (gap_open, linear_gap_penalty))
+            .map(|&(_, _, lambda, k, h)| KarlinAltschul { lambda, k, h })
+    }
+
+    /// Score in bits, comparable across scoring systems.
+    pub fn bit_score(&self, score: isize) -> f64 {
+        (self.lambda * score as f64 - self.k.ln()) / LN_2
+    }
+
+    /// Expected number of alignments scoring at least `score` by chance
+    /// in a search space of `search_space` residue pairs.
+    pub fn evalue(&self, score: isize, search_space: f64) -> f64 {
+        self.k * search_space * (-self.lambda * score as f64).exp()
+    }
+
+    /**
+     * Effective search space for a query of `query_len` residues against a
+     * database of `db_seqs` sequences totalling `db_len` residues. Each
+     * length is shortened by the expected length of a chance alignment,
+     * `ln(k * m * n) / h`, since an alignment that starts too close to the
+     * end of a sequence has no room to reach a significant score.
+     */
+    pub fn search_space(&self, query_len: usize, db_len: usize, db_seqs: usize) -> f64 {
+        let (m, n) = (query_len as f64, db_len as f64);
+        let expected_len = ((self.k * m * n).ln() / self.h).max(0.0);
+        let min_len = 1.0 / self.k;
+        let m = (m - expected_len).max(min_len);
+        let n = (n - db_seqs as f64 * expected_len).max(min_len);
+        m * n
+    }
+}
+
+/// Score of an alignment compared with the scores of alignments against
+/// shuffled sequences.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Significance {
+    pub score: isize,
+    pub mean: f64,
+    pub std_dev: f64,
+    /// Standard deviations above the mean of the shuffled scores, infinite
+    /// if every shuffle scores the same and `score` differs.
+    pub z_score: f64,
+    /// Fraction of shuffles scoring at least `score`, counting the real
+    /// sequence among them so that it is never zero.
+    pub p_value: f64,
+}
+
+/**
+ * Empirical significance by shuffling: one sequence is shuffled `samples`
+ * times and realigned, and the real score is compared with the scores of
+ * the shuffles. Shuffle `i` uses a generator seeded from `seed + i`, so the
+ * result only depends on the seed, not on the number of threads.
+ */
+pub struct ShuffleTest {
+    samples: usize,
+    shuffle: Shuffle,
+    seed: u64,
+    threads: usize,
+}
+
+impl ShuffleTest {
+    pub fn new(samples: usize) -> Self {
+        ShuffleTest {
+            samples,
+            shuffle: Shuffle::Mononucleotide,
+            seed: 0,
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+        }
+    }
+
+    /// Keeps dinucleotide counts with `Shuffle::Dinucleotide` instead of
+    /// the default mononucleotide shuffle.
+    pub fn shuffle(mut self, shuffle: Shuffle) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Number of threads to spread the shuffles over, by default one per
+    /// available core.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Shuffles `seq` and scores each shuffle with `align`, comparing the
+    /// results with `score`.
+    pub fn run<F>(&self, score: isize, seq: &[u8], align: F) -> Significance
+    where
+        F: Fn(&[u8]) -> isize + Sync,
+    {
+        let chunk_len = self.samples.div_ceil(self.threads).max(1);
+        let mut scores = vec![0; self.samples];
+        thread::scope(|scope| {
+            for (chunk_idx, chunk) in scores.chunks_mut(chunk_len).enumerate() {
+                let align = &align;
+                scope.spawn(move || {
+                    for (idx, slot) in chunk.iter_mut().enumerate() {
+                        let sample = (chunk_idx * chunk_len + idx) as u64;
+                        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(sample));
+                        *slot = align(&self.shuffle.apply(seq, &mut rng));
+                    }
+                });
+            }
+        });
+
+        let count = scores.len().max(1) as f64;
+        let mean = scores.iter().map(|&other| other as f64).sum::<f64>() / count;
+        let variance = scores
+            .iter()
+            .map(|&other| (other as f64 - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        let std_dev = variance.sqrt();
+        let z_score = match std_dev > 0.0 {
+            true => (score as f64 - mean) / std_dev,
+            false if score as f64 == mean => 0.0,
+            false => (score as f64 - mean).signum() * f64::INFINITY,
+        };
+        let at_least = scores.iter().filter(|&&other| other >= score).count();
+        Significance {
+            score,
+            mean,
+            std_dev,
+            z_score,
+            p_value: (at_least + 1) as f64 / (scores.len() + 1) as f64,
+        }
+    }
+}
+
+/// Frequency of each residue in `seqs`, ignoring case and gaps, in order
+/// of first appearance.
+pub fn residue_frequencies(seqs: &[&[u8]]) -> Vec<(u8, f64)> {
+    let mut counts: Vec<(u8, usize)> = Vec::new();
+    for &ch in seqs.iter().flat_map(|seq| seq.iter()) {
+        let ch = ch.to_ascii_uppercase();
+        if ch == b'-' {
+            continue;
+        }
+        match counts.iter_mut().find(|(other, _)| *other == ch) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((ch, 1)),
+        }
+    }
+    let total: usize = counts.iter().map(|&(_, count)| count).sum();
+    counts
+        .into_iter()
+        .map(|(ch, count)| (ch, count as f64 / total as f64))
+        .collect()
+}
+
+/// Probability of each score for a pair of random residues.
+struct ScoreDistribution {
+    lowest: isize,
+    probs: Vec<f64>,
+}
+
+impl ScoreDistribution {
+    fn new(matrix: &SubstitutionMatrix, background: &[(u8, f64)]) -> Option<Self> {
+        let pairs = || {
+            background.iter().flat_map(|&(a, p)| {
+                background
+                    .iter()
+                    .map(move |&(b, q)| (matrix.score(a, b), p * q))
+            })
+        };
+        let total: f64 = pairs().map(|(_, prob)| prob).sum();
+        let live = || pairs().filter(|&(_, prob)| prob > 0.0);
+        let lowest = live().map(|(score, _)| score).min()?;
+        let highest = live().map(|(score, _)| score).max()?;
+        let mut probs = vec![0.0; (highest - lowest) as usize + 1];
+        for (score, prob) in live() {
+            probs[(score - lowest) as usize] += prob / total;
+        }
+        let dist = ScoreDistribution { lowest, probs };
+        let mean: f64 = dist.scores().map(|(score, prob)| score as f64 * prob).sum();
+        (mean < 0.0 && highest > 0).then_some(dist)
+    }
+
+    fn scores(&self) -> impl Iterator<Item = (isize, f64)> + '_ {
+        self.probs
+            .iter()
+            .enumerate()
+            .map(|(idx, &prob)| (self.lowest + idx as isize, prob))
+    }
+
+    /// Greatest common divisor of the scores that can occur.
+    fn gcd(&self) -> isize {
+        fn gcd(a: isize, b: isize) -> isize {
+            if b == 0 {
+                a.abs()
+            } else {
+                gcd(b, a % b)
+            }
+        }
+        self.scores()
+            .filter(|&(_, prob)| prob > 0.0)
+            .fold(0, |acc, (score, _)| gcd(acc, score))
+    }
+
+    /// Unique positive root of `sum(prob * exp(lambda * score)) = 1`, found
+    /// by bisection.
+    fn lambda(&self) -> Option<f64> {
+        let moment = |lambda: f64| -> f64 {
+            self.scores()
+                .map(|(score, prob)| prob * (lambda * score as f64).exp())
+                .sum::<f64>()
+                - 1.0
+        };
+        let mut high = 0.5;
+        while moment(high) < 0.0 {
+            high *= 2.0;
+            if !high.is_finite() {
+                return None;
+            }
+        }
+        let mut low = 0.0;
+        for _ in 0..200 {
+            let mid = (low + high) / 2.0;
+            if moment(mid) < 0.0 {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        Some((low + high) / 2.0)
+    }
+
+    /**
+     * Karlin and Altschul's formula for `k`, with `sigma` summed over the
+     * distributions of the total score of `1, 2, ...` random pairs:
+     * `k = gcd * lambda * exp(-2 * sigma) / (h * (1 - exp(-lambda * gcd)))`.
+     */
+    fn k(&self, lambda: f64, h: f64) -> f64 {
+        let delta = self.gcd() as f64;
+        let mut sum_dist = vec![1.0];
+        let mut sum_lowest = 0;
+        let mut sigma = 0.0;
+        for iteration in 1..=MAX_ITERATIONS {
+            let mut next = vec![0.0; sum_dist.len() + self.probs.len() - 1];
+            for (i, &p) in sum_dist.iter().enumerate() {
+                for (j, &q) in self.probs.iter().enumerate() {
+                    next[i + j] += p * q;
+                }
+            }
+            sum_dist = next;
+            sum_lowest += self.lowest;
+
+            let term: f64 = sum_dist
+                .iter()
+                .enumerate()
+                .map(|(idx, &prob)| {
+                    let score = (sum_lowest + idx as isize) as f64;
+                    if score < 0.0 {
+                        prob * (lambda * score).exp()
+                    } else {
+                        prob
+                    }
+                })
+                .sum();
+            sigma += term / iteration as f64;
+            if term < TOLERANCE {
+                break;
+            }
+        }
+        delta * lambda * (-2.0 * sigma).exp() / (h * -(-lambda * delta).exp_m1())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, random_dna};
+    use crate::{local, pairwise};
+
+    fn assert_close(expected: f64, actual: f64, tolerance: f64) {
+        assert!(
+            (expected - actual).abs() <= tolerance,
+            "expected {} but got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_blosum62_ungapped() {
+        let params =
+            KarlinAltschul::ungapped(&SubstitutionMatrix::blosum62(), &ROBINSON_FREQUENCIES)
+                .unwrap();
+        assert_close(0.3176, params.lambda, 5e-4);
+        assert_close(0.134, params.k, 2e-3);
+        assert_close(0.4012, params.h, 2e-3);
+    }
+
+    #[test]
+    fn test_dna_ungapped() {
+        // BLASTN's published values for +1/-3 with uniform bases.
+        let uniform = [(b'A', 0.25), (b'C', 0.25), (b'G', 0.25), (b'T', 0.25)];
+        let params = KarlinAltschul::ungapped(&SubstitutionMatrix::dna(1, -3), &uniform).unwrap();
+        assert_close(1.374, params.lambda, 1e-3);
+        assert_close(0.711, params.k, 2e-3);
+        assert_close(1.31, params.h, 1e-2);
+
+        // A positive expected score is no use for local alignment.
+        assert!(KarlinAltschul::ungapped(&SubstitutionMatrix::dna(3, -1), &uniform).is_none());
+        assert!(KarlinAltschul::ungapped(&SubstitutionMatrix::dna(-1, -1), &uniform).is_none());
+    }
+
+    #[test]
+    fn test_significance() {
+        let params = KarlinAltschul::blosum62_gapped(-11, -1).unwrap();
+        assert_eq!(0.267, params.lambda);
+        assert_eq!(0.041, params.k);
+        assert!(KarlinAltschul::blosum62_gapped(-3, -3).is_none());
+
+        let bits = params.bit_score(50);
+        assert_close((0.267 * 50.0 - 0.041f64.ln()) / LN_2, bits, 1e-12);
+        // The E-value follows from the bit score alone.
+        let space = 1e6;
+        assert_close(space * 2f64.powf(-bits), params.evalue(50, space), 1e-9);
+        assert!(params.evalue(60, space) < params.evalue(50, space));
+
+        let space = params.search_space(250, 1_000_000, 4000);
+        assert!(space < 250.0 * 1_000_000.0);
+        assert!(space > params.search_space(250, 1_000_000, 8000));
+        assert_close(
+            1.0 / (params.k * params.k),
+            params.search_space(1, 1, 1),
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn test_residue_frequencies() {
+        let freqs = residue_frequencies(&[b"ACGT", b"aa-C"]);
+        assert_eq!(
+            vec![
+                (b'A', 3.0 / 7.0),
+                (b'C', 2.0 / 7.0),
+                (b'G', 1.0 / 7.0),
+                (b'T', 1.0 / 7.0)
+            ],
+            freqs
+        );
+    }
+
+    #[test]
+    fn test_shuffle_test() {
+        let mut rng = testing::rng(11);
+        let fst = random_dna(&mut rng, 80);
+        let mut related = fst.clone();
+        related[10] = b'A';
+        related.drain(40..44);
+        let unrelated = random_dna(&mut rng, 80);
+
+        let test = ShuffleTest::new(40).seed(3);
+        let significance = local::Builder::new(&fst, &related, -5).significance(&test);
+        assert_eq!(1.0 / 41.0, significance.p_value);
+        assert!(significance.z_score > 5.0);
+        assert!(significance.std_dev > 0.0);
+
+        let aligner = pairwise::Builder::new(&fst, &unrelated, -2).gap_open(-5);
+        let significance = aligner.significance(&test);
+        assert_eq!(aligner.score(), significance.score);
+        assert!(significance.p_value > 1.0 / 41.0);
+        assert!(significance.z_score < 3.0);
+
+        // Shuffle `i` is seeded from `seed + i` wherever it runs.
+        for shuffle in [Shuffle::Mononucleotide, Shuffle::Dinucleotide] {
+            let one = ShuffleTest::new(25).shuffle(shuffle).seed(7).threads(1);
+            let four = ShuffleTest::new(25).shuffle(shuffle).seed(7).threads(4);
+            assert_eq!(aligner.significance(&one), aligner.significance(&four));
+        }
+        let other = ShuffleTest::new(25).seed(8);
+        assert_ne!(aligner.significance(&test), aligner.significance(&other));
+    }
+
+    #[test]
+    fn test_shuffle_test_constant_scores() {
+        let test = ShuffleTest::new(5).threads(8);
+        let significance = test.run(4, b"AAAA", |seq| seq.len() as isize);
+        assert_eq!(4.0, significance.mean);
+        assert_eq!(0.0, significance.z_score);
+        assert_eq!(1.0, significance.p_value);
+        let significance = test.run(5, b"AAAA", |seq| seq.len() as isize);
+        assert_eq!(f64::INFINITY, significance.z_score);
+        assert_eq!(1.0 / 6.0, significance.p_value);
+        assert_eq!(1.0, ShuffleTest::new(0).run(0, b"", |_| 0).p_value);
+    }
+}
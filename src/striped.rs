@@ -0,0 +1,682 @@
+use std::mem;
+
+use crate::pairwise::{self, Mode};
+use crate::substitution::SubstitutionMatrix;
+
+/**
+ * Score-only local alignment of one query against many targets, using
+ * Farrar's striped layout so that a whole vector of query positions is
+ * updated per instruction. Build the query profile once with `profile`
+ * and call `Profile::score` for every target.
+ *
+ * Gaps are affine as in `pairwise::Builder`, and every score matches
+ * `pairwise::Builder` in `Mode::Local`.
+ */
+pub struct Builder<'a> {
+    query: &'a [u8],
+    linear_gap_penalty: isize,
+    gap_open: isize,
+    matrix: SubstitutionMatrix,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(query: &'a [u8], linear_gap_penalty: isize) -> Self {
+        Builder {
+            query,
+            linear_gap_penalty,
+            gap_open: 0,
+            matrix: SubstitutionMatrix::blosum62(),
+        }
+    }
+
+    /// Extra penalty charged once for every gap, on top of the per-residue
+    /// `linear_gap_penalty`.
+    pub fn gap_open(mut self, penalty: isize) -> Self {
+        self.gap_open = penalty;
+        self
+    }
+
+    pub fn matrix(mut self, matrix: SubstitutionMatrix) -> Self {
+        self.matrix = matrix;
+        self
+    }
+
+    /**
+     * Striped query profile for 8 and 16 bit lanes. A width is left out if
+     * the matrix scores or gap penalties do not fit its lanes, in which
+     * case targets go straight to the next wider one.
+     */
+    pub fn profile(&self) -> Profile {
+        // Every residue outside the matrix alphabet scores the same, so
+        // targets only need one profile row per letter plus one for the
+        // rest.
+        let mut residues = self.matrix.alphabet().to_vec();
+        let mut classes = [residues.len(); 256];
+        for (class, &residue) in residues.iter().enumerate() {
+            classes[residue as usize] = class;
+            classes[residue.to_ascii_lowercase() as usize] = class;
+        }
+        if let Some(other) = (0..=255).find(|&byte| classes[byte as usize] == residues.len()) {
+            residues.push(other);
+        }
+
+        let gap_first = -(self.gap_open + self.linear_gap_penalty);
+        let gap_extend = -self.linear_gap_penalty;
+        let bytes = Striped::new(self.query, &residues, &self.matrix, gap_first, gap_extend);
+        let words = Striped::new(self.query, &residues, &self.matrix, gap_first, gap_extend);
+        Profile {
+            query: self.query.to_vec(),
+            linear_gap_penalty: self.linear_gap_penalty,
+            gap_open: self.gap_open,
+            matrix: self.matrix.clone(),
+            classes,
+            bytes,
+            words,
+        }
+    }
+}
+
+/// Query prepared for scoring against any number of targets.
+pub struct Profile {
+    query: Vec<u8>,
+    linear_gap_penalty: isize,
+    gap_open: isize,
+    matrix: SubstitutionMatrix,
+    /// Profile row for each target byte.
+    classes: [usize; 256],
+    bytes: Option<Striped<Bytes>>,
+    words: Option<Striped<Words>>,
+}
+
+impl Profile {
+    /**
+     * Best local alignment score of the query against `target`. Runs with
+     * 8 bit lanes first and only falls back to 16 bit lanes, then to the
+     * scalar aligner, when the score saturates the narrower lanes.
+     */
+    pub fn score(&self, target: &[u8]) -> isize {
+        let classes: Vec<usize> = target.iter().map(|&ch| self.classes[ch as usize]).collect();
+        self.bytes
+            .as_ref()
+            .and_then(|bytes| bytes.score(&classes))
+            .or_else(|| self.words.as_ref().and_then(|words| words.score(&classes)))
+            .unwrap_or_else(|| self.scalar_score(target))
+    }
+
+    fn scalar_score(&self, target: &[u8]) -> isize {
+        pairwise::Builder::new(&self.query, target, self.linear_gap_penalty)
+            .gap_open(self.gap_open)
+            .matrix(self.matrix.clone())
+            .mode(Mode::Local)
+            .score()
+    }
+}
+
+/**
+ * Query profile and kernel for one lane width. Lane `k` of vector `seg`
+ * holds query position `k * segs + seg`, so neighbouring positions sit in
+ * neighbouring vectors and a column of the matrix is one pass over the
+ * vectors, plus the lazy F loop for vertical gaps that wrap from one lane
+ * into the next.
+ */
+struct Striped<V> {
+    segs: usize,
+    /// Added to every profile score so the lowest one is zero.
+    bias: i32,
+    /// `segs` vectors for each residue class, holding biased scores.
+    profile: Vec<V>,
+    /// Cost of the first residue of a gap and of every further residue.
+    gap_first: V,
+    gap_extend: V,
+}
+
+impl<V: Lanes> Striped<V> {
+    fn new(
+        query: &[u8],
+        residues: &[u8],
+        matrix: &SubstitutionMatrix,
+        gap_first: isize,
+        gap_extend: isize,
+    ) -> Option<Self> {
+        let scores = || {
+            residues
+                .iter()
+                .flat_map(|&residue| query.iter().map(move |&ch| matrix.score(ch, residue)))
+        };
+        let lowest = scores().min().unwrap_or(0).min(0);
+        let highest = scores().max().unwrap_or(0);
+        let bias = -lowest;
+        let fits = |score: isize| (0..=V::MAX as isize).contains(&score);
+        if !fits(highest + bias) || !fits(gap_first) || !(0..=gap_first).contains(&gap_extend) {
+            return None;
+        }
+
+        let segs = query.len().div_ceil(V::WIDTH).max(1);
+        let mut profile = Vec::with_capacity(residues.len() * segs);
+        for &residue in residues {
+            for seg in 0..segs {
+                let lanes: Vec<i32> = (0..V::WIDTH)
+                    .map(|lane| match query.get(lane * segs + seg) {
+                        Some(&ch) => (matrix.score(ch, residue) + bias) as i32,
+                        None => 0,
+                    })
+                    .collect();
+                profile.push(V::from_scores(&lanes));
+            }
+        }
+        Some(Striped {
+            segs,
+            bias: bias as i32,
+            profile,
+            gap_first: V::splat(gap_first as i32),
+            gap_extend: V::splat(gap_extend as i32),
+        })
+    }
+
+    /// Best score against `target`, given as residue classes, or `None` if
+    /// it saturated the lanes.
+    fn score(&self, target: &[usize]) -> Option<isize> {
+        let segs = self.segs;
+        let zero = V::splat(0);
+        let bias = V::splat(self.bias);
+        let mut load = vec![zero; segs];
+        let mut store = vec![zero; segs];
+        // Best scores ending in a gap that skips target residues, for the
+        // next column, and in one skipping query residues.
+        let mut target_gaps = vec![zero; segs];
+        let mut best = zero;
+
+        for &class in target {
+            let profile = &self.profile[class * segs..][..segs];
+            let mut query_gap = zero;
+            let mut score = store[segs - 1].shift();
+            mem::swap(&mut load, &mut store);
+            for seg in 0..segs {
+                score = score
+                    .adds(profile[seg])
+                    .subs(bias)
+                    .max(target_gaps[seg])
+                    .max(query_gap);
+                best = best.max(score);
+                store[seg] = score;
+                let opened = score.subs(self.gap_first);
+                target_gaps[seg] = target_gaps[seg].subs(self.gap_extend).max(opened);
+                query_gap = query_gap.subs(self.gap_extend).max(opened);
+                score = load[seg];
+            }
+
+            // Gaps along the query crossing from the end of one lane into
+            // the start of the next, until none of them beats opening a
+            // gap.
+            query_gap = query_gap.shift();
+            let mut seg = 0;
+            while query_gap.any_gt(store[seg].subs(self.gap_first)) {
+                let score = store[seg].max(query_gap);
+                store[seg] = score;
+                target_gaps[seg] = target_gaps[seg].max(score.subs(self.gap_first));
+                query_gap = query_gap.subs(self.gap_extend);
+                seg += 1;
+                if seg == segs {
+                    seg = 0;
+                    query_gap = query_gap.shift();
+                }
+            }
+        }
+
+        let best = best.hmax();
+        (best < V::MAX - self.bias).then_some(best as isize)
+    }
+}
+
+/// Vector of saturating integer lanes the striped kernel runs on. Scores
+/// are never negative, as in every cell of a local alignment.
+trait Lanes: Copy {
+    const WIDTH: usize;
+    const MAX: i32;
+
+    /// Vector of the first `WIDTH` scores, which must fit in a lane.
+    fn from_scores(scores: &[i32]) -> Self;
+
+    fn splat(score: i32) -> Self;
+
+    fn adds(self, other: Self) -> Self;
+
+    /// Saturating subtraction, clamped at zero.
+    fn subs(self, other: Self) -> Self;
+
+    fn max(self, other: Self) -> Self;
+
+    /// Moves every lane up by one, shifting a zero into the first.
+    fn shift(self) -> Self;
+
+    /// Whether any lane is greater than the same lane of `other`.
+    fn any_gt(self, other: Self) -> bool;
+
+    /// Largest lane.
+    fn hmax(self) -> i32;
+}
+
+#[cfg(target_arch = "x86_64")]
+use sse2::{Bytes, Words};
+
+#[cfg(target_arch = "aarch64")]
+use neon::{Bytes, Words};
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+use scalar::{Bytes, Words};
+
+// SSE2 is part of the x86_64 baseline, so the intrinsics are always
+// available.
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use std::arch::x86_64::*;
+
+    use super::Lanes;
+
+    /// Sixteen unsigned 8 bit lanes.
+    #[derive(Clone, Copy)]
+    pub struct Bytes(__m128i);
+
+    /// Eight signed 16 bit lanes.
+    #[derive(Clone, Copy)]
+    pub struct Words(__m128i);
+
+    impl Lanes for Bytes {
+        const WIDTH: usize = 16;
+        const MAX: i32 = u8::MAX as i32;
+
+        fn from_scores(scores: &[i32]) -> Self {
+            let lanes: [u8; 16] = std::array::from_fn(|lane| scores[lane] as u8);
+            unsafe { Bytes(_mm_loadu_si128(lanes.as_ptr() as *const __m128i)) }
+        }
+
+        fn splat(score: i32) -> Self {
+            unsafe { Bytes(_mm_set1_epi8(score as u8 as i8)) }
+        }
+
+        fn adds(self, other: Self) -> Self {
+            unsafe { Bytes(_mm_adds_epu8(self.0, other.0)) }
+        }
+
+        fn subs(self, other: Self) -> Self {
+            unsafe { Bytes(_mm_subs_epu8(self.0, other.0)) }
+        }
+
+        fn max(self, other: Self) -> Self {
+            unsafe { Bytes(_mm_max_epu8(self.0, other.0)) }
+        }
+
+        fn shift(self) -> Self {
+            unsafe { Bytes(_mm_slli_si128::<1>(self.0)) }
+        }
+
+        fn any_gt(self, other: Self) -> bool {
+            // SSE2 has no unsigned comparison, but `a - b` saturates to
+            // zero exactly when `a <= b`.
+            unsafe {
+                let diff = _mm_subs_epu8(self.0, other.0);
+                _mm_movemask_epi8(_mm_cmpeq_epi8(diff, _mm_setzero_si128())) != 0xffff
+            }
+        }
+
+        fn hmax(self) -> i32 {
+            let mut lanes = [0u8; 16];
+            unsafe { _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, self.0) };
+            lanes.into_iter().max().unwrap() as i32
+        }
+    }
+
+    impl Lanes for Words {
+        const WIDTH: usize = 8;
+        const MAX: i32 = i16::MAX as i32;
+
+        fn from_scores(scores: &[i32]) -> Self {
+            let lanes: [i16; 8] = std::array::from_fn(|lane| scores[lane] as i16);
+            unsafe { Words(_mm_loadu_si128(lanes.as_ptr() as *const __m128i)) }
+        }
+
+        fn splat(score: i32) -> Self {
+            unsafe { Words(_mm_set1_epi16(score as i16)) }
+        }
+
+        fn adds(self, other: Self) -> Self {
+            unsafe { Words(_mm_adds_epi16(self.0, other.0)) }
+        }
+
+        fn subs(self, other: Self) -> Self {
+            unsafe {
+                Words(_mm_max_epi16(
+                    _mm_subs_epi16(self.0, other.0),
+                    _mm_setzero_si128(),
+                ))
+            }
+        }
+
+        fn max(self, other: Self) -> Self {
+            unsafe { Words(_mm_max_epi16(self.0, other.0)) }
+        }
+
+        fn shift(self) -> Self {
+            unsafe { Words(_mm_slli_si128::<2>(self.0)) }
+        }
+
+        fn any_gt(self, other: Self) -> bool {
+            unsafe { _mm_movemask_epi8(_mm_cmpgt_epi16(self.0, other.0)) != 0 }
+        }
+
+        fn hmax(self) -> i32 {
+            let mut lanes = [0i16; 8];
+            unsafe { _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, self.0) };
+            lanes.into_iter().max().unwrap() as i32
+        }
+    }
+}
+
+// NEON is part of the aarch64 baseline, so the intrinsics are always
+// available.
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    use super::Lanes;
+
+    /// Sixteen unsigned 8 bit lanes.
+    #[derive(Clone, Copy)]
+    pub struct Bytes(uint8x16_t);
+
+    /// Eight signed 16 bit lanes.
+    #[derive(Clone, Copy)]
+    pub struct Words(int16x8_t);
+
+    impl Lanes for Bytes {
+        const WIDTH: usize = 16;
+        const MAX: i32 = u8::MAX as i32;
+
+        fn from_scores(scores: &[i32]) -> Self {
+            let lanes: [u8; 16] = std::array::from_fn(|lane| scores[lane] as u8);
+            unsafe { Bytes(vld1q_u8(lanes.as_ptr())) }
+        }
+
+        fn splat(score: i32) -> Self {
+            unsafe { Bytes(vdupq_n_u8(score as u8)) }
+        }
+
+        fn adds(self, other: Self) -> Self {
+            unsafe { Bytes(vqaddq_u8(self.0, other.0)) }
+        }
+
+        fn subs(self, other: Self) -> Self {
+            unsafe { Bytes(vqsubq_u8(self.0, other.0)) }
+        }
+
+        fn max(self, other: Self) -> Self {
+            unsafe { Bytes(vmaxq_u8(self.0, other.0)) }
+        }
+
+        fn shift(self) -> Self {
+            unsafe { Bytes(vextq_u8::<15>(vdupq_n_u8(0), self.0)) }
+        }
+
+        fn any_gt(self, other: Self) -> bool {
+            unsafe { vmaxvq_u8(vcgtq_u8(self.0, other.0)) != 0 }
+        }
+
+        fn hmax(self) -> i32 {
+            unsafe { vmaxvq_u8(self.0) as i32 }
+        }
+    }
+
+    impl Lanes for Words {
+        const WIDTH: usize = 8;
+        const MAX: i32 = i16::MAX as i32;
+
+        fn from_scores(scores: &[i32]) -> Self {
+            let lanes: [i16; 8] = std::array::from_fn(|lane| scores[lane] as i16);
+            unsafe { Words(vld1q_s16(lanes.as_ptr())) }
+        }
+
+        fn splat(score: i32) -> Self {
+            unsafe { Words(vdupq_n_s16(score as i16)) }
+        }
+
+        fn adds(self, other: Self) -> Self {
+            unsafe { Words(vqaddq_s16(self.0, other.0)) }
+        }
+
+        fn subs(self, other: Self) -> Self {
+            unsafe { Words(vmaxq_s16(vqsubq_s16(self.0, other.0), vdupq_n_s16(0))) }
+        }
+
+        fn max(self, other: Self) -> Self {
+            unsafe { Words(vmaxq_s16(self.0, other.0)) }
+        }
+
+        fn shift(self) -> Self {
+            unsafe { Words(vextq_s16::<7>(vdupq_n_s16(0), self.0)) }
+        }
+
+        fn any_gt(self, other: Self) -> bool {
+            unsafe { vmaxvq_u16(vcgtq_s16(self.0, other.0)) != 0 }
+        }
+
+        fn hmax(self) -> i32 {
+            unsafe { vmaxvq_s16(self.0) as i32 }
+        }
+    }
+}
+
+/// Plain arrays standing in for vectors on other targets. Also built for
+/// the tests, which check the kernel gives the same scores with them.
+#[cfg(any(test, not(any(target_arch = "x86_64", target_arch = "aarch64"))))]
+mod scalar {
+    use super::Lanes;
+
+    #[derive(Clone, Copy)]
+    pub struct Bytes([u8; 16]);
+
+    #[derive(Clone, Copy)]
+    pub struct Words([i16; 8]);
+
+    impl Lanes for Bytes {
+        const WIDTH: usize = 16;
+        const MAX: i32 = u8::MAX as i32;
+
+        fn from_scores(scores: &[i32]) -> Self {
+            Bytes(std::array::from_fn(|lane| scores[lane] as u8))
+        }
+
+        fn splat(score: i32) -> Self {
+            Bytes([score as u8; 16])
+        }
+
+        fn adds(self, other: Self) -> Self {
+            Bytes(std::array::from_fn(|lane| {
+                self.0[lane].saturating_add(other.0[lane])
+            }))
+        }
+
+        fn subs(self, other: Self) -> Self {
+            Bytes(std::array::from_fn(|lane| {
+                self.0[lane].saturating_sub(other.0[lane])
+            }))
+        }
+
+        fn max(self, other: Self) -> Self {
+            Bytes(std::array::from_fn(|lane| self.0[lane].max(other.0[lane])))
+        }
+
+        fn shift(self) -> Self {
+            Bytes(std::array::from_fn(|lane| match lane {
+                0 => 0,
+                _ => self.0[lane - 1],
+            }))
+        }
+
+        fn any_gt(self, other: Self) -> bool {
+            self.0.iter().zip(other.0).any(|(&a, b)| a > b)
+        }
+
+        fn hmax(self) -> i32 {
+            self.0.into_iter().max().unwrap() as i32
+        }
+    }
+
+    impl Lanes for Words {
+        const WIDTH: usize = 8;
+        const MAX: i32 = i16::MAX as i32;
+
+        fn from_scores(scores: &[i32]) -> Self {
+            Words(std::array::from_fn(|lane| scores[lane] as i16))
+        }
+
+        fn splat(score: i32) -> Self {
+            Words([score as i16; 8])
+        }
+
+        fn adds(self, other: Self) -> Self {
+            Words(std::array::from_fn(|lane| {
+                self.0[lane].saturating_add(other.0[lane])
+            }))
+        }
+
+        fn subs(self, other: Self) -> Self {
+            Words(std::array::from_fn(|lane| {
+                self.0[lane].saturating_sub(other.0[lane]).max(0)
+            }))
+        }
+
+        fn max(self, other: Self) -> Self {
+            Words(std::array::from_fn(|lane| self.0[lane].max(other.0[lane])))
+        }
+
+        fn shift(self) -> Self {
+            Words(std::array::from_fn(|lane| match lane {
+                0 => 0,
+                _ => self.0[lane - 1],
+            }))
+        }
+
+        fn any_gt(self, other: Self) -> bool {
+            self.0.iter().zip(other.0).any(|(&a, b)| a > b)
+        }
+
+        fn hmax(self) -> i32 {
+            self.0.into_iter().max().unwrap() as i32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, random_dna, random_protein};
+    use rand::rngs::StdRng;
+
+    fn expected(
+        query: &[u8],
+        target: &[u8],
+        matrix: &SubstitutionMatrix,
+        gaps: (isize, isize),
+    ) -> isize {
+        pairwise::Builder::new(query, target, gaps.0)
+            .gap_open(gaps.1)
+            .matrix(matrix.clone())
+            .mode(Mode::Local)
+            .score()
+    }
+
+    #[test]
+    fn test_matches_pairwise() {
+        type Random = fn(&mut StdRng, usize) -> Vec<u8>;
+        let mut rng = testing::rng(41);
+        let cases: [(SubstitutionMatrix, Random); 2] = [
+            (SubstitutionMatrix::blosum62(), random_protein),
+            (SubstitutionMatrix::dna(2, -3), random_dna),
+        ];
+        for (matrix, random) in &cases {
+            for gaps in [(-1, -10), (-4, 0), (-2, -3)] {
+                for (query_len, target_len) in [(1, 5), (15, 40), (16, 16), (17, 90), (120, 300)] {
+                    let query = random(&mut rng, query_len);
+                    let profile = Builder::new(&query, gaps.0)
+                        .gap_open(gaps.1)
+                        .matrix(matrix.clone())
+                        .profile();
+                    for _ in 0..3 {
+                        let target = random(&mut rng, target_len);
+                        assert_eq!(
+                            expected(&query, &target, matrix, gaps),
+                            profile.score(&target)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_related_sequences() {
+        let mut rng = testing::rng(43);
+        let matrix = SubstitutionMatrix::blosum62();
+        let query = random_protein(&mut rng, 150);
+        let mut target = random_protein(&mut rng, 40);
+        target.extend(&query[20..70]);
+        target.extend(random_protein(&mut rng, 7));
+        target.extend(&query[75..140]);
+        target.extend(random_protein(&mut rng, 30));
+        let profile = Builder::new(&query, -1).gap_open(-10).profile();
+        assert_eq!(
+            expected(&query, &target, &matrix, (-1, -10)),
+            profile.score(&target)
+        );
+
+        let lowercase = target.to_ascii_lowercase();
+        assert_eq!(profile.score(&target), profile.score(&lowercase));
+        assert_eq!(0, profile.score(b""));
+        assert_eq!(0, Builder::new(b"", -1).profile().score(&target));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let query = vec![b'W'; 60];
+        let profile = Builder::new(&query, -1).gap_open(-10).profile();
+        let target = [b"GG".as_slice(), &query, b"GG"].concat();
+        let classes: Vec<usize> = target
+            .iter()
+            .map(|&ch| profile.classes[ch as usize])
+            .collect();
+        assert_eq!(None, profile.bytes.as_ref().unwrap().score(&classes));
+        assert_eq!(Some(660), profile.words.as_ref().unwrap().score(&classes));
+        assert_eq!(660, profile.score(&target));
+
+        // Too large for 16 bit lanes, and a matrix too wide for 8 bit ones.
+        let matrix = SubstitutionMatrix::dna(1000, -1000);
+        let query = b"ACGT".repeat(10);
+        let profile = Builder::new(&query, -1).matrix(matrix.clone()).profile();
+        assert!(profile.bytes.is_none());
+        assert_eq!(40_000, profile.score(&query));
+        assert_eq!(
+            expected(&query, b"TTACGTT", &matrix, (-1, 0)),
+            profile.score(b"TTACGTT")
+        );
+    }
+
+    #[test]
+    fn test_scalar_lanes() {
+        let mut rng = testing::rng(47);
+        let matrix = SubstitutionMatrix::blosum62();
+        let residues = matrix.alphabet();
+        for query_len in [3, 33, 100] {
+            let query = random_protein(&mut rng, query_len);
+            let target = random_protein(&mut rng, 120);
+            let classes: Vec<usize> = target
+                .iter()
+                .map(|&ch| residues.iter().position(|&other| other == ch).unwrap())
+                .collect();
+            let score = expected(&query, &target, &matrix, (-1, -10));
+            let bytes = Striped::<scalar::Bytes>::new(&query, residues, &matrix, 11, 1).unwrap();
+            let words = Striped::<scalar::Words>::new(&query, residues, &matrix, 11, 1).unwrap();
+            assert_eq!(Some(score), bytes.score(&classes));
+            assert_eq!(Some(score), words.score(&classes));
+        }
+    }
+}
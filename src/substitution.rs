@@ -0,0 +1,324 @@
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const BLOSUM45: &str = include_str!("matrices/BLOSUM45");
+const BLOSUM62: &str = include_str!("matrices/BLOSUM62");
+const BLOSUM80: &str = include_str!("matrices/BLOSUM80");
+const PAM30: &str = include_str!("matrices/PAM30");
+const PAM70: &str = include_str!("matrices/PAM70");
+const PAM250: &str = include_str!("matrices/PAM250");
+
+#[derive(Debug)]
+pub enum MatrixError {
+    Io(io::Error),
+    MissingHeader,
+    BadRow(String),
+    BadScore(String),
+}
+
+impl Display for MatrixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatrixError::Io(e) => write!(f, "could not read matrix: {}", e),
+            MatrixError::MissingHeader => write!(f, "matrix has no column header"),
+            MatrixError::BadRow(row) => write!(f, "malformed matrix row: {}", row),
+            MatrixError::BadScore(score) => write!(f, "invalid score: {}", score),
+        }
+    }
+}
+
+impl Error for MatrixError {}
+
+impl From<io::Error> for MatrixError {
+    fn from(e: io::Error) -> Self {
+        MatrixError::Io(e)
+    }
+}
+
+/// Score lookup for every pair of bytes, shared by all the pairwise
+/// aligners. Lookups are case-insensitive.
+#[derive(Clone)]
+pub struct SubstitutionMatrix {
+    name: String,
+    alphabet: Vec<u8>,
+    scores: Vec<i32>,
+}
+
+impl SubstitutionMatrix {
+    pub fn blosum45() -> Self {
+        SubstitutionMatrix::builtin("BLOSUM45", BLOSUM45)
+    }
+
+    pub fn blosum62() -> Self {
+        SubstitutionMatrix::builtin("BLOSUM62", BLOSUM62)
+    }
+
+    pub fn blosum80() -> Self {
+        SubstitutionMatrix::builtin("BLOSUM80", BLOSUM80)
+    }
+
+    pub fn pam30() -> Self {
+        SubstitutionMatrix::builtin("PAM30", PAM30)
+    }
+
+    pub fn pam70() -> Self {
+        SubstitutionMatrix::builtin("PAM70", PAM70)
+    }
+
+    pub fn pam250() -> Self {
+        SubstitutionMatrix::builtin("PAM250", PAM250)
+    }
+
+    fn builtin(name: &str, text: &str) -> Self {
+        let mut matrix = SubstitutionMatrix::from_ncbi(text).unwrap();
+        matrix.name = name.to_owned();
+        matrix
+    }
+
+    /// Looks up one of the built in matrices, ignoring case.
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "BLOSUM45" => Some(SubstitutionMatrix::blosum45()),
+            "BLOSUM62" => Some(SubstitutionMatrix::blosum62()),
+            "BLOSUM80" => Some(SubstitutionMatrix::blosum80()),
+            "PAM30" => Some(SubstitutionMatrix::pam30()),
+            "PAM70" => Some(SubstitutionMatrix::pam70()),
+            "PAM250" => Some(SubstitutionMatrix::pam250()),
+            _ => None,
+        }
+    }
+
+    /// Built in matrix if `arg` names one, otherwise `arg` is read as a path
+    /// to an NCBI formatted matrix. Meant for command line flags.
+    pub fn from_arg(arg: &str) -> Result<Self, MatrixError> {
+        match SubstitutionMatrix::by_name(arg) {
+            Some(matrix) => Ok(matrix),
+            None => SubstitutionMatrix::from_file(arg),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, MatrixError> {
+        let text = fs::read_to_string(&path)?;
+        let mut matrix = SubstitutionMatrix::from_ncbi(&text)?;
+        if let Some(stem) = path.as_ref().file_name() {
+            matrix.name = stem.to_string_lossy().into_owned();
+        }
+        Ok(matrix)
+    }
+
+    /**
+     * Parses the format used by the NCBI matrices: `#` comments, a header
+     * line of column residues, then one row per residue starting with its
+     * label. Residues missing from the matrix score like `X` if there is
+     * one, otherwise like the lowest score in the matrix.
+     */
+    pub fn from_ncbi(text: &str) -> Result<Self, MatrixError> {
+        let mut lines = text
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let header = lines.next().ok_or(MatrixError::MissingHeader)?;
+        let columns = header
+            .split_whitespace()
+            .map(|col| match col.as_bytes() {
+                &[ch] => Ok(ch.to_ascii_uppercase()),
+                _ => Err(MatrixError::BadRow(header.to_owned())),
+            })
+            .collect::<Result<Vec<u8>, MatrixError>>()?;
+
+        let mut rows = Vec::new();
+        for line in lines {
+            let mut fields = line.split_whitespace();
+            let label = match fields.next().map(|f| f.as_bytes()) {
+                Some(&[ch]) => ch.to_ascii_uppercase(),
+                _ => return Err(MatrixError::BadRow(line.to_owned())),
+            };
+            let scores = fields
+                .map(|f| f.parse().map_err(|_| MatrixError::BadScore(f.to_owned())))
+                .collect::<Result<Vec<i32>, MatrixError>>()?;
+            if scores.len() != columns.len() {
+                return Err(MatrixError::BadRow(line.to_owned()));
+            }
+            rows.push((label, scores));
+        }
+
+        let lowest = rows
+            .iter()
+            .flat_map(|(_, scores)| scores.iter().copied())
+            .min()
+            .unwrap_or(0);
+        let mut table = vec![lowest; 256 * 256];
+        for (label, scores) in &rows {
+            for (&col, &score) in columns.iter().zip(scores) {
+                table[*label as usize * 256 + col as usize] = score;
+            }
+        }
+
+        let alphabet: Vec<u8> = rows.iter().map(|&(label, _)| label).collect();
+        if alphabet.contains(&b'X') {
+            let stand_in = |ch: usize| {
+                if alphabet.contains(&(ch as u8)) {
+                    ch
+                } else {
+                    b'X' as usize
+                }
+            };
+            let known = table.clone();
+            for a in 0..256 {
+                for b in 0..256 {
+                    table[a * 256 + b] = known[stand_in(a) * 256 + stand_in(b)];
+                }
+            }
+        }
+
+        Ok(SubstitutionMatrix::finish(String::new(), alphabet, table))
+    }
+
+    /**
+     * Match/mismatch scoring for nucleotides. IUPAC ambiguity codes score as
+     * a match whenever the two codes share at least one base, so `R` matches
+     * `A` and `G` but not `C`. `U` is treated as `T`.
+     */
+    pub fn dna(match_score: isize, mismatch_score: isize) -> Self {
+        let alphabet = b"ACGTURYSWKMBDHVN".to_vec();
+        let mut table = vec![mismatch_score as i32; 256 * 256];
+        for &a in &alphabet {
+            for &b in &alphabet {
+                if iupac_bases(a) & iupac_bases(b) != 0 {
+                    table[a as usize * 256 + b as usize] = match_score as i32;
+                }
+            }
+        }
+        let name = format!("DNA({},{})", match_score, mismatch_score);
+        SubstitutionMatrix::finish(name, alphabet, table)
+    }
+
+    /// Copies the uppercase scores onto the lowercase entries.
+    fn finish(name: String, alphabet: Vec<u8>, mut scores: Vec<i32>) -> Self {
+        for a in 0..=255u8 {
+            for b in 0..=255u8 {
+                let (upper_a, upper_b) = (a.to_ascii_uppercase(), b.to_ascii_uppercase());
+                if (upper_a, upper_b) != (a, b) {
+                    scores[a as usize * 256 + b as usize] =
+                        scores[upper_a as usize * 256 + upper_b as usize];
+                }
+            }
+        }
+        SubstitutionMatrix {
+            name,
+            alphabet,
+            scores,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Residues that have their own row in the matrix, in file order.
+    pub fn alphabet(&self) -> &[u8] {
+        &self.alphabet
+    }
+
+    pub fn score(&self, a: u8, b: u8) -> isize {
+        self.scores[a as usize * 256 + b as usize] as isize
+    }
+
+    /// Highest score of any pair, e.g. for bounding the score of an
+    /// alignment from its length.
+    pub fn max_score(&self) -> isize {
+        self.scores.iter().copied().max().unwrap() as isize
+    }
+}
+
+/// Bit set of the bases (A=1, C=2, G=4, T=8) an IUPAC code stands for.
+fn iupac_bases(code: u8) -> u8 {
+    match code.to_ascii_uppercase() {
+        b'A' => 0b0001,
+        b'C' => 0b0010,
+        b'G' => 0b0100,
+        b'T' | b'U' => 0b1000,
+        b'R' => 0b0101,
+        b'Y' => 0b1010,
+        b'S' => 0b0110,
+        b'W' => 0b1001,
+        b'K' => 0b1100,
+        b'M' => 0b0011,
+        b'B' => 0b1110,
+        b'D' => 0b1101,
+        b'H' => 0b1011,
+        b'V' => 0b0111,
+        b'N' => 0b1111,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bio::scores::{blosum62, pam250};
+
+    #[test]
+    fn test_builtin_matches_bio() {
+        let blosum = SubstitutionMatrix::blosum62();
+        let pam = SubstitutionMatrix::pam250();
+        for &a in blosum.alphabet() {
+            for &b in blosum.alphabet() {
+                assert_eq!(blosum62::blosum62(a, b) as isize, blosum.score(a, b));
+                assert_eq!(pam250::pam250(a, b) as isize, pam.score(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn test_by_name() {
+        for name in [
+            "blosum45", "BLOSUM62", "Blosum80", "pam30", "PAM70", "PAM250",
+        ] {
+            let matrix = SubstitutionMatrix::by_name(name).unwrap();
+            assert_eq!(name.to_ascii_uppercase(), matrix.name());
+            assert_eq!(24, matrix.alphabet().len());
+        }
+        assert!(SubstitutionMatrix::by_name("BLOSUM50").is_none());
+        assert_eq!(13, SubstitutionMatrix::pam30().score(b'W', b'W'));
+        assert_eq!(5, SubstitutionMatrix::blosum45().score(b'A', b'A'));
+    }
+
+    #[test]
+    fn test_lowercase_and_unknown() {
+        let matrix = SubstitutionMatrix::blosum62();
+        assert_eq!(matrix.score(b'W', b'W'), matrix.score(b'w', b'W'));
+        assert_eq!(matrix.score(b'X', b'A'), matrix.score(b'J', b'A'));
+    }
+
+    #[test]
+    fn test_from_ncbi() {
+        let text = "# toy\n   A  C\nA  2 -1\nC -1  3\n";
+        let matrix = SubstitutionMatrix::from_ncbi(text).unwrap();
+        assert_eq!(2, matrix.score(b'A', b'A'));
+        assert_eq!(-1, matrix.score(b'c', b'A'));
+        assert_eq!(3, matrix.score(b'C', b'C'));
+        assert_eq!(-1, matrix.score(b'G', b'A'));
+
+        assert!(SubstitutionMatrix::from_ncbi("# nothing\n").is_err());
+        assert!(SubstitutionMatrix::from_ncbi("   A  C\nA  2\n").is_err());
+        assert!(SubstitutionMatrix::from_ncbi("   A\nA  x\n").is_err());
+    }
+
+    #[test]
+    fn test_dna() {
+        let matrix = SubstitutionMatrix::dna(2, -3);
+        assert_eq!(2, matrix.score(b'A', b'A'));
+        assert_eq!(-3, matrix.score(b'A', b'C'));
+        assert_eq!(2, matrix.score(b'a', b'R'));
+        assert_eq!(-3, matrix.score(b'C', b'R'));
+        assert_eq!(2, matrix.score(b'N', b'T'));
+        assert_eq!(2, matrix.score(b'U', b't'));
+        assert_eq!(-3, matrix.score(b'-', b'A'));
+        assert_eq!(2, matrix.max_score());
+        assert_eq!(17, SubstitutionMatrix::pam250().max_score());
+    }
+}
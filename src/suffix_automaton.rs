@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+
+struct State {
+    /// Length of the longest substring ending in this state.
+    len: usize,
+    /// State of the longest suffix that ends at more positions, `None` for
+    /// the root.
+    link: Option<usize>,
+    next: BTreeMap<u8, usize>,
+    /// Index of the last character of the first occurrence.
+    first_end: usize,
+    /// Number of positions the substrings of this state end at.
+    occurrences: usize,
+}
+
+/**
+ * Suffix automaton (DAWG) of a text: the smallest automaton accepting
+ * every substring of the text. Each state stands for the substrings that
+ * end at the same set of positions, and there are at most `2n - 1` of
+ * them, so it is built in linear time for a fixed alphabet.
+ */
+pub struct SuffixAutomaton<'a> {
+    text: &'a [u8],
+    states: Vec<State>,
+}
+
+impl<'a> SuffixAutomaton<'a> {
+    pub fn new(text: &'a [u8]) -> Self {
+        let mut states = Vec::with_capacity(2 * text.len() + 1);
+        states.push(State {
+            len: 0,
+            link: None,
+            next: BTreeMap::new(),
+            first_end: 0,
+            occurrences: 0,
+        });
+        let mut last = 0;
+        for (idx, &ch) in text.iter().enumerate() {
+            let curr = states.len();
+            states.push(State {
+                len: states[last].len + 1,
+                link: Some(0),
+                next: BTreeMap::new(),
+                first_end: idx,
+                occurrences: 1,
+            });
+            let mut state = Some(last);
+            while let Some(prev) = state {
+                if states[prev].next.contains_key(&ch) {
+                    break;
+                }
+                states[prev].next.insert(ch, curr);
+                state = states[prev].link;
+            }
+            if let Some(prev) = state {
+                let target = states[prev].next[&ch];
+                if states[prev].len + 1 == states[target].len {
+                    states[curr].link = Some(target);
+                } else {
+                    // Split `target`: the suffixes short enough to also
+                    // end at the new position move to a clone.
+                    let clone = states.len();
+                    states.push(State {
+                        len: states[prev].len + 1,
+                        link: states[target].link,
+                        next: states[target].next.clone(),
+                        first_end: states[target].first_end,
+                        occurrences: 0,
+                    });
+                    let mut state = Some(prev);
+                    while let Some(prev) = state {
+                        if states[prev].next.get(&ch) != Some(&target) {
+                            break;
+                        }
+                        states[prev].next.insert(ch, clone);
+                        state = states[prev].link;
+                    }
+                    states[target].link = Some(clone);
+                    states[curr].link = Some(clone);
+                }
+            }
+            last = curr;
+        }
+
+        let mut automaton = SuffixAutomaton { text, states };
+        for state in automaton.by_decreasing_len() {
+            if let Some(link) = automaton.states[state].link {
+                automaton.states[link].occurrences += automaton.states[state].occurrences;
+            }
+        }
+        automaton
+    }
+
+    /// Every state, longest substrings first, so each state comes before
+    /// its suffix link.
+    fn by_decreasing_len(&self) -> Vec<usize> {
+        let mut buckets = vec![0; self.text.len() + 2];
+        for state in &self.states {
+            buckets[state.len + 1] += 1;
+        }
+        for len in 1..buckets.len() {
+            buckets[len] += buckets[len - 1];
+        }
+        let mut order = vec![0; self.states.len()];
+        for (idx, state) in self.states.iter().enumerate() {
+            order[buckets[state.len]] = idx;
+            buckets[state.len] += 1;
+        }
+        order.reverse();
+        order
+    }
+
+    fn state_of(&self, pattern: &[u8]) -> Option<usize> {
+        pattern
+            .iter()
+            .try_fold(0, |state, ch| self.states[state].next.get(ch).copied())
+    }
+
+    pub fn contains(&self, pattern: &[u8]) -> bool {
+        self.state_of(pattern).is_some()
+    }
+
+    /// Number of possibly overlapping occurrences of `pattern` in the text,
+    /// one more than the length of the text for the empty pattern.
+    pub fn count(&self, pattern: &[u8]) -> usize {
+        match self.state_of(pattern) {
+            Some(0) => self.text.len() + 1,
+            Some(state) => self.states[state].occurrences,
+            None => 0,
+        }
+    }
+
+    /// Start of the first occurrence of `pattern` in the text.
+    pub fn find(&self, pattern: &[u8]) -> Option<usize> {
+        match self.state_of(pattern)? {
+            0 => Some(0),
+            state => Some(self.states[state].first_end + 1 - pattern.len()),
+        }
+    }
+
+    /// Number of distinct non-empty substrings of the text.
+    pub fn distinct_substrings(&self) -> usize {
+        self.states
+            .iter()
+            .filter_map(|state| Some(state.len - self.states[state.link?].len))
+            .sum()
+    }
+
+    /**
+     * Longest substring of the text that also occurs in each of `others`,
+     * the first one in the text on ties. Each of `others` is run through
+     * the automaton once, recording in every state the longest match that
+     * ended there, so this takes linear time in the total length.
+     */
+    pub fn longest_common_substring(&self, others: &[&[u8]]) -> &'a [u8] {
+        let order = self.by_decreasing_len();
+        let mut common: Vec<usize> = self.states.iter().map(|state| state.len).collect();
+        for other in others {
+            let mut matched = vec![0; self.states.len()];
+            let (mut state, mut len) = (0, 0);
+            for ch in other.iter() {
+                while state != 0 && !self.states[state].next.contains_key(ch) {
+                    state = self.states[state].link.unwrap();
+                    len = self.states[state].len;
+                }
+                match self.states[state].next.get(ch) {
+                    Some(&next) => {
+                        state = next;
+                        len += 1;
+                    }
+                    None => len = 0,
+                }
+                matched[state] = matched[state].max(len);
+            }
+            // A match ending in a state also matches its suffixes.
+            for &state in &order {
+                if let Some(link) = self.states[state].link {
+                    let suffix = matched[state].min(self.states[link].len);
+                    matched[link] = matched[link].max(suffix);
+                }
+            }
+            for (common, matched) in common.iter_mut().zip(matched) {
+                *common = (*common).min(matched);
+            }
+        }
+
+        // A common length is zero or one of the lengths of its state, as
+        // matches only reach a state with its shortest string or longer and
+        // a suffix link passes on its target's longest. Every string of a
+        // state ends first at `first_end`, so that picks the leftmost.
+        let best = (1..self.states.len())
+            .filter(|&state| common[state] > 0)
+            .map(|state| (common[state], self.states[state].first_end + 1))
+            .max_by(|fst, snd| fst.0.cmp(&snd.0).then(snd.1.cmp(&fst.1)));
+        match best {
+            Some((len, end)) => &self.text[end - len..end],
+            None => &self.text[..0],
+        }
+    }
+}
+
+/// Longest substring common to every one of `seqs`, the first one in
+/// `seqs[0]` on ties. Empty if there are no sequences.
+pub fn longest_common_substring<'a>(seqs: &[&'a [u8]]) -> &'a [u8] {
+    match seqs.split_first() {
+        Some((fst, rest)) => SuffixAutomaton::new(fst).longest_common_substring(rest),
+        None => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, random_dna};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_queries() {
+        let automaton = SuffixAutomaton::new(b"ABCBCA");
+        assert!(automaton.contains(b"CBC"));
+        assert!(automaton.contains(b""));
+        assert!(!automaton.contains(b"CC"));
+        assert_eq!(2, automaton.count(b"BC"));
+        assert_eq!(2, automaton.count(b"A"));
+        assert_eq!(1, automaton.count(b"ABCBCA"));
+        assert_eq!(0, automaton.count(b"AA"));
+        assert_eq!(7, automaton.count(b""));
+        assert_eq!(Some(1), automaton.find(b"BC"));
+        assert_eq!(Some(2), automaton.find(b"CB"));
+        assert_eq!(Some(4), automaton.find(b"CA"));
+        assert_eq!(None, automaton.find(b"BB"));
+
+        let overlapping = SuffixAutomaton::new(b"AAAA");
+        assert_eq!(3, overlapping.count(b"AA"));
+        assert_eq!(4, overlapping.distinct_substrings());
+        assert_eq!(0, SuffixAutomaton::new(b"").distinct_substrings());
+    }
+
+    #[test]
+    fn test_against_naive() {
+        let mut rng = testing::rng(3);
+        for len in [1, 5, 20, 60] {
+            let text = random_dna(&mut rng, len);
+            let automaton = SuffixAutomaton::new(&text);
+            let mut substrings = HashSet::new();
+            for start in 0..len {
+                for end in start + 1..=len {
+                    substrings.insert(&text[start..end]);
+                }
+            }
+            assert_eq!(substrings.len(), automaton.distinct_substrings());
+            for pattern in substrings {
+                let naive: Vec<usize> = (0..=len - pattern.len())
+                    .filter(|&start| text[start..].starts_with(pattern))
+                    .collect();
+                assert_eq!(naive.len(), automaton.count(pattern));
+                assert_eq!(Some(naive[0]), automaton.find(pattern));
+            }
+        }
+    }
+
+    #[test]
+    fn test_longest_common_substring() {
+        assert_eq!(
+            b"ABC",
+            longest_common_substring(&[b"ABABC", b"BABCA", b"ABCBA"])
+        );
+        assert_eq!(
+            b"TA",
+            longest_common_substring(&[b"GATTACA", b"TAGACCA", b"ATACA"])
+        );
+        assert_eq!(b"GATTACA", longest_common_substring(&[b"GATTACA"]));
+        assert_eq!(b"", longest_common_substring(&[b"AAA", b"CCC"]));
+        assert_eq!(b"", longest_common_substring(&[]));
+
+        let mut rng = testing::rng(9);
+        for _ in 0..20 {
+            let seqs: Vec<Vec<u8>> = (0..4).map(|_| random_dna(&mut rng, 30)).collect();
+            let seqs: Vec<&[u8]> = seqs.iter().map(Vec::as_slice).collect();
+            let in_all = |sub: &[u8]| {
+                seqs.iter()
+                    .all(|seq| seq.windows(sub.len()).any(|window| window == sub))
+            };
+            let naive = (1..=30)
+                .rev()
+                .find_map(|len| seqs[0].windows(len).find(|&sub| in_all(sub)))
+                .unwrap_or(b"");
+            assert_eq!(naive, longest_common_substring(&seqs));
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::ops::Range;
+
+use crate::lcs_splicing;
+
+/// Where a character of a supersequence or an interleaving comes from, as
+/// 0-based positions in the two input sequences.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Origin {
+    Fst(usize),
+    Snd(usize),
+    Both(usize, usize),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Supersequence {
+    pub seq: Vec<u8>,
+    /// Origin of every character of `seq`.
+    pub origins: Vec<Origin>,
+}
+
+impl Supersequence {
+    fn push(&mut self, ch: u8, origin: Origin) {
+        self.seq.push(ch);
+        self.origins.push(origin);
+    }
+}
+
+/**
+ * Shortest sequence having both `fst` and `snd` as subsequences. Its
+ * length is the sum of theirs minus their LCS, which it shares: the LCS
+ * is found in linear space, and between two of its characters the
+ * residues of `fst` come before those of `snd`.
+ */
+pub fn shortest_common_supersequence(fst: &[u8], snd: &[u8]) -> Supersequence {
+    let lcs = lcs_splicing::linear_space(fst, snd);
+    let mut supersequence = Supersequence::default();
+    let (mut i, mut j) = (0, 0);
+    let shared = lcs.fst_indices.iter().zip(&lcs.snd_indices);
+    let ends = shared
+        .map(|(&i, &j)| (i, j))
+        .chain([(fst.len(), snd.len())]);
+    for (next_i, next_j) in ends {
+        for (idx, &ch) in fst.iter().enumerate().take(next_i).skip(i) {
+            supersequence.push(ch, Origin::Fst(idx));
+        }
+        for (idx, &ch) in snd.iter().enumerate().take(next_j).skip(j) {
+            supersequence.push(ch, Origin::Snd(idx));
+        }
+        if next_i < fst.len() {
+            supersequence.push(fst[next_i], Origin::Both(next_i, next_j));
+        }
+        (i, j) = (next_i + 1, next_j + 1);
+    }
+    supersequence
+}
+
+/**
+ * Whether `text` is an interleaving of `fst` and `snd`: both read in order
+ * with their characters mixed together and nothing else. On success,
+ * gives the origin of every character of `text`, preferring `fst` when
+ * both could have supplied it.
+ */
+pub fn interleaving(text: &[u8], fst: &[u8], snd: &[u8]) -> Option<Vec<Origin>> {
+    if text.len() != fst.len() + snd.len() {
+        return None;
+    }
+    let reachable = interleavings(text, fst, snd);
+    if !reachable[fst.len()][snd.len()] {
+        return None;
+    }
+    let mut origins = Vec::with_capacity(text.len());
+    let (mut i, mut j) = (fst.len(), snd.len());
+    while i + j > 0 {
+        if i > 0 && fst[i - 1] == text[i + j - 1] && reachable[i - 1][j] {
+            i -= 1;
+            origins.push(Origin::Fst(i));
+        } else {
+            j -= 1;
+            origins.push(Origin::Snd(j));
+        }
+    }
+    origins.reverse();
+    Some(origins)
+}
+
+/// `reachable[i][j]` is whether `fst[..i]` and `snd[..j]` interleave into
+/// the first `i + j` characters of `text`.
+fn interleavings(text: &[u8], fst: &[u8], snd: &[u8]) -> Vec<Vec<bool>> {
+    let mut reachable = vec![vec![false; snd.len() + 1]; fst.len() + 1];
+    reachable[0][0] = true;
+    for i in 0..=fst.len() {
+        for j in 0..=snd.len() {
+            let Some(&ch) = text.get(i + j) else {
+                continue;
+            };
+            if !reachable[i][j] {
+                continue;
+            }
+            if fst.get(i) == Some(&ch) {
+                reachable[i + 1][j] = true;
+            }
+            if snd.get(j) == Some(&ch) {
+                reachable[i][j + 1] = true;
+            }
+        }
+    }
+    reachable
+}
+
+/// First substring of `text` that is an interleaving of `fst` and `snd`,
+/// the question of Rosalind's ITWV problem.
+pub fn find_interleaving(text: &[u8], fst: &[u8], snd: &[u8]) -> Option<Range<usize>> {
+    let len = fst.len() + snd.len();
+    (0..(text.len() + 1).checked_sub(len)?)
+        .find(|&start| interleavings(&text[start..start + len], fst, snd)[fst.len()][snd.len()])
+        .map(|start| start..start + len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, random_dna};
+
+    fn is_subsequence(sub: &[u8], seq: &[u8]) -> bool {
+        let mut rest = seq.iter();
+        sub.iter().all(|ch| rest.any(|x| x == ch))
+    }
+
+    fn check_origins(seq: &[u8], origins: &[Origin], fst: &[u8], snd: &[u8]) {
+        let (mut from_fst, mut from_snd) = (Vec::new(), Vec::new());
+        for (&ch, &origin) in seq.iter().zip(origins) {
+            match origin {
+                Origin::Fst(i) => from_fst.push((i, ch)),
+                Origin::Snd(j) => from_snd.push((j, ch)),
+                Origin::Both(i, j) => {
+                    from_fst.push((i, ch));
+                    from_snd.push((j, ch));
+                }
+            }
+        }
+        let expected = |seq: &[u8]| seq.iter().copied().enumerate().collect::<Vec<_>>();
+        assert_eq!(expected(fst), from_fst);
+        assert_eq!(expected(snd), from_snd);
+    }
+
+    #[test]
+    fn test_shortest_common_supersequence() {
+        let scs = shortest_common_supersequence(b"ATCTGAT", b"TGCATA");
+        assert_eq!(b"ATGCATGAT".to_vec(), scs.seq);
+        check_origins(&scs.seq, &scs.origins, b"ATCTGAT", b"TGCATA");
+        assert_eq!(Origin::Fst(0), scs.origins[0]);
+        assert_eq!(Origin::Both(1, 0), scs.origins[1]);
+
+        let mut rng = testing::rng(13);
+        for (fst_len, snd_len) in [(0, 4), (5, 0), (8, 8), (30, 17), (70, 90)] {
+            let fst = random_dna(&mut rng, fst_len);
+            let snd = random_dna(&mut rng, snd_len);
+            let scs = shortest_common_supersequence(&fst, &snd);
+            let lcs = lcs_splicing::lcs_length(&fst, &snd);
+            assert_eq!(fst_len + snd_len - lcs, scs.seq.len());
+            assert!(is_subsequence(&fst, &scs.seq));
+            assert!(is_subsequence(&snd, &scs.seq));
+            check_origins(&scs.seq, &scs.origins, &fst, &snd);
+        }
+    }
+
+    #[test]
+    fn test_interleaving() {
+        let origins = interleaving(b"ACAGTG", b"AAG", b"CGT").unwrap();
+        use Origin::*;
+        assert_eq!(
+            vec![Fst(0), Snd(0), Fst(1), Snd(1), Snd(2), Fst(2)],
+            origins
+        );
+        check_origins(b"ACAGTG", &origins, b"AAG", b"CGT");
+        assert_eq!(None, interleaving(b"ACAGTG", b"AAG", b"CGA"));
+        assert_eq!(None, interleaving(b"ACAGT", b"AAG", b"CGT"));
+        assert_eq!(Some(vec![]), interleaving(b"", b"", b""));
+
+        let mut rng = testing::rng(8);
+        for _ in 0..20 {
+            let fst = random_dna(&mut rng, 6);
+            let snd = random_dna(&mut rng, 5);
+            let scs = shortest_common_supersequence(&fst, &snd);
+            // Splitting the shared characters gives an interleaving.
+            let text: Vec<u8> = scs
+                .seq
+                .iter()
+                .zip(&scs.origins)
+                .flat_map(|(&ch, origin)| match origin {
+                    Origin::Both(..) => vec![ch, ch],
+                    _ => vec![ch],
+                })
+                .collect();
+            let origins = interleaving(&text, &fst, &snd).unwrap();
+            check_origins(&text, &origins, &fst, &snd);
+        }
+    }
+
+    #[test]
+    fn test_find_interleaving() {
+        let text = b"GACCACGGTT";
+        // The sample of Rosalind's ITWV problem.
+        assert_eq!(Some(1..8), find_interleaving(text, b"ACAG", b"CCG"));
+        assert_eq!(Some(6..10), find_interleaving(text, b"GT", b"GT"));
+        assert_eq!(None, find_interleaving(text, b"ACAG", b"GT"));
+        assert_eq!(None, find_interleaving(text, b"CCG", b"CCG"));
+        assert_eq!(Some(0..0), find_interleaving(text, b"", b""));
+        assert_eq!(None, find_interleaving(b"AC", b"ACG", b""));
+    }
+}
@@ -0,0 +1,38 @@
+//! Fixtures shared by the unit tests of several modules.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::alignment::{Alignment, Operation};
+
+/// Random number generator for test data, the same for the same seed.
+pub(crate) fn rng(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+pub(crate) fn random_dna(rng: &mut StdRng, len: usize) -> Vec<u8> {
+    (0..len).map(|_| b"ACGT"[rng.gen_range(0..4)]).collect()
+}
+
+pub(crate) fn random_protein(rng: &mut StdRng, len: usize) -> Vec<u8> {
+    let residues = b"ACDEFGHIKLMNPQRSTVWY";
+    (0..len)
+        .map(|_| residues[rng.gen_range(0..residues.len())])
+        .collect()
+}
+
+/// Checks that the alignment fits in the sequences and that its
+/// matches and mismatches agree with the residues they pair up.
+pub(crate) fn check_alignment(fst: &[u8], snd: &[u8], alignment: &Alignment) {
+    assert!(alignment.fst_end <= fst.len() && alignment.snd_end <= snd.len());
+    assert_eq!(
+        (fst.len(), snd.len()),
+        (alignment.fst_len, alignment.snd_len)
+    );
+    let (fst_aligned, snd_aligned) = alignment.aligned(fst, snd);
+    for ((&a, &b), &op) in fst_aligned.iter().zip(&snd_aligned).zip(&alignment.ops) {
+        if !op.is_gap() {
+            assert_eq!(Operation::pair(a, b), op);
+        }
+    }
+}
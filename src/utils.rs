@@ -36,3 +36,23 @@ pub fn write_tree_to_dot(output_file: &str, stree: &lcs_stree::SuffixTree) {
     let mut file = File::create(output_file).unwrap();
     file.write_all(dot_fmt.as_bytes()).unwrap();
 }
+
+/// Value following `flag` on the command line, e.g. `--matrix PAM250`.
+pub fn flag_value(flag: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != flag).nth(1)
+}
+
+/// FASTA records for `seqs` named by `names`, wrapped at 60 residues.
+pub fn fasta<S: AsRef<str>, T: AsRef<[u8]>>(names: &[S], seqs: &[T]) -> String {
+    let mut fasta = String::new();
+    for (name, seq) in names.iter().zip(seqs) {
+        fasta.push('>');
+        fasta.push_str(name.as_ref());
+        fasta.push('\n');
+        for line in seq.as_ref().chunks(60) {
+            fasta.push_str(&String::from_utf8_lossy(line));
+            fasta.push('\n');
+        }
+    }
+    fasta
+}